@@ -0,0 +1,15 @@
+-- Adds the load averages and CPU frequency to every sample
+ALTER TABLE devices ADD COLUMN load_1 REAL NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN load_5 REAL NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN load_15 REAL NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN cpu_freq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN cpu_freq_max BIGINT NOT NULL DEFAULT 0;
+
+-- Stores the usage of each core, one row per core per sample
+CREATE TABLE IF NOT EXISTS cpu_cores (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    core INTEGER NOT NULL,
+    core_usage REAL NOT NULL,
+    UNIQUE(device_id, time, core)
+);
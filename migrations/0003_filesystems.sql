@@ -0,0 +1,13 @@
+-- Stores the usage of each mounted filesystem, one row per mount point per sample
+CREATE TABLE IF NOT EXISTS filesystems (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    mount_point TEXT NOT NULL,
+    fs_type VARCHAR(64) NOT NULL,
+    total BIGINT NOT NULL,
+    used BIGINT NOT NULL,
+    free BIGINT NOT NULL,
+    inodes_total BIGINT NOT NULL,
+    inodes_used BIGINT NOT NULL,
+    UNIQUE(device_id, time, mount_point)
+);
@@ -0,0 +1,11 @@
+-- Stores the throughput of each block device, one row per disk per sample
+CREATE TABLE IF NOT EXISTS disk_io (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    disk VARCHAR(255) NOT NULL,
+    read_bytes BIGINT NOT NULL,
+    write_bytes BIGINT NOT NULL,
+    read_ops BIGINT NOT NULL,
+    write_ops BIGINT NOT NULL,
+    UNIQUE(device_id, time, disk)
+);
@@ -0,0 +1,16 @@
+-- Stores the traffic of each network interface, one row per interface per sample. Every value is per second,
+-- errors and drops are rare enough that they need fractions
+CREATE TABLE IF NOT EXISTS network_interfaces (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    interface VARCHAR(255) NOT NULL,
+    rx_bytes BIGINT NOT NULL,
+    tx_bytes BIGINT NOT NULL,
+    rx_packets BIGINT NOT NULL,
+    tx_packets BIGINT NOT NULL,
+    rx_errors REAL NOT NULL,
+    tx_errors REAL NOT NULL,
+    rx_drops REAL NOT NULL,
+    tx_drops REAL NOT NULL,
+    UNIQUE(device_id, time, interface)
+);
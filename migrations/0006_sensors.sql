@@ -0,0 +1,9 @@
+-- Stores hardware sensor readings, one row per sensor per sample
+CREATE TABLE IF NOT EXISTS sensors (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    label VARCHAR(255) NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    value REAL NOT NULL,
+    UNIQUE(device_id, time, label)
+);
@@ -0,0 +1,6 @@
+-- Adds the memory breakdown to every sample, ram_used is MemAvailable based from now on
+ALTER TABLE devices ADD COLUMN ram_buffers BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN ram_cached BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN swap_total BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN swap_used BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN zfs_arc BIGINT NOT NULL DEFAULT 0;
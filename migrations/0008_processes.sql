@@ -0,0 +1,12 @@
+-- Stores the busiest processes by CPU usage and by memory, one row per process per sample
+CREATE TABLE IF NOT EXISTS processes (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    pid BIGINT NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    user_name VARCHAR(255) NOT NULL,
+    cpu_usage REAL NOT NULL,
+    rss BIGINT NOT NULL,
+    cmdline TEXT NOT NULL,
+    UNIQUE(device_id, time, pid)
+);
@@ -0,0 +1,8 @@
+-- Stores custom metrics from commands and pushed values, one row per metric per sample
+CREATE TABLE IF NOT EXISTS metrics (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    value REAL NOT NULL,
+    UNIQUE(device_id, time, name)
+);
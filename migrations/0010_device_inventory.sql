@@ -0,0 +1,16 @@
+-- Stores what each device is, a row is added whenever the client reports a change
+CREATE TABLE IF NOT EXISTS device_inventory (
+    device_id VARCHAR(255) NOT NULL,
+    time BIGINT NOT NULL,
+    hostname VARCHAR(255) NOT NULL,
+    os VARCHAR(255) NOT NULL,
+    kernel VARCHAR(255) NOT NULL,
+    arch VARCHAR(255) NOT NULL,
+    cpu_model VARCHAR(255) NOT NULL,
+    cpu_cores BIGINT NOT NULL,
+    cpu_threads BIGINT NOT NULL,
+    ram_total BIGINT NOT NULL,
+    boot_time BIGINT NOT NULL,
+    version VARCHAR(255) NOT NULL,
+    UNIQUE(device_id, time)
+);
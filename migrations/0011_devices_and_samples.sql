@@ -0,0 +1,56 @@
+-- Splits the per sample rows into a registry of devices and their samples, so each device's name is stored once
+ALTER TABLE devices RENAME TO legacy_samples;
+
+CREATE TABLE IF NOT EXISTS devices (
+    device_id VARCHAR(255) NOT NULL PRIMARY KEY,
+    device_name VARCHAR(255) NOT NULL,
+    created_at BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    metadata TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS samples (
+    device_id VARCHAR(255) NOT NULL REFERENCES devices (device_id) ON DELETE CASCADE,
+    time BIGINT NOT NULL,
+    ram_used BIGINT NOT NULL,
+    ram_total BIGINT NOT NULL,
+    cpu_usage REAL NOT NULL,
+    processes INTEGER NOT NULL,
+    network_in BIGINT NOT NULL,
+    network_out BIGINT NOT NULL,
+    load_1 REAL NOT NULL DEFAULT 0,
+    load_5 REAL NOT NULL DEFAULT 0,
+    load_15 REAL NOT NULL DEFAULT 0,
+    cpu_freq BIGINT NOT NULL DEFAULT 0,
+    cpu_freq_max BIGINT NOT NULL DEFAULT 0,
+    ram_buffers BIGINT NOT NULL DEFAULT 0,
+    ram_cached BIGINT NOT NULL DEFAULT 0,
+    swap_total BIGINT NOT NULL DEFAULT 0,
+    swap_used BIGINT NOT NULL DEFAULT 0,
+    zfs_arc BIGINT NOT NULL DEFAULT 0
+);
+
+-- Every query reads a device's samples over a time range
+CREATE UNIQUE INDEX IF NOT EXISTS samples_device_time ON samples (device_id, time);
+
+-- The legacy times are unix timestamps in a TIMESTAMP column, which only SQLite ever stored. Going through text turns them
+-- into BIGINT on SQLite and lets PostgreSQL, where the table is always empty, accept the statements as well
+
+-- Each device keeps the name of its latest sample, renames used to rewrite every row so they should all match
+INSERT INTO devices (device_id, device_name, created_at, last_seen, metadata)
+SELECT
+    device_id,
+    (SELECT latest.device_name FROM legacy_samples AS latest WHERE latest.device_id = legacy.device_id ORDER BY latest.time DESC LIMIT 1),
+    MIN(CAST(CAST(time AS VARCHAR(32)) AS BIGINT)),
+    MAX(CAST(CAST(time AS VARCHAR(32)) AS BIGINT)),
+    '{}'
+FROM legacy_samples AS legacy
+GROUP BY device_id;
+
+INSERT INTO samples (device_id, time, ram_used, ram_total, cpu_usage, processes, network_in, network_out,
+    load_1, load_5, load_15, cpu_freq, cpu_freq_max, ram_buffers, ram_cached, swap_total, swap_used, zfs_arc)
+SELECT device_id, CAST(CAST(time AS VARCHAR(32)) AS BIGINT), ram_used, ram_total, cpu_usage, processes, network_in, network_out,
+    load_1, load_5, load_15, cpu_freq, cpu_freq_max, ram_buffers, ram_cached, swap_total, swap_used, zfs_arc
+FROM legacy_samples;
+
+DROP TABLE legacy_samples;
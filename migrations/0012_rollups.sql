@@ -0,0 +1,20 @@
+-- Aggregates of the samples over 15 minute, hourly and daily buckets so long ranges don't have to load every sample
+-- One row per value per bucket: `source` is the table the value comes from, `series` tells apart the rows of a detail table
+-- (the core, disk, interface, sensor label or metric name, empty for `samples`) and `field` is the column it comes from,
+-- sensors only have a value so their kind is kept there instead
+CREATE TABLE IF NOT EXISTS rollups (
+    device_id VARCHAR(255) NOT NULL REFERENCES devices (device_id) ON DELETE CASCADE,
+    resolution BIGINT NOT NULL,
+    time BIGINT NOT NULL,
+    source VARCHAR(255) NOT NULL,
+    series VARCHAR(255) NOT NULL,
+    field VARCHAR(255) NOT NULL,
+    min_value REAL NOT NULL,
+    max_value REAL NOT NULL,
+    avg_value REAL NOT NULL,
+    last_value REAL NOT NULL,
+    sample_count BIGINT NOT NULL
+);
+
+-- Rollups are read and rebuilt per device, tier and time range
+CREATE UNIQUE INDEX IF NOT EXISTS rollups_device_time ON rollups (device_id, resolution, time, source, series, field);
@@ -0,0 +1,35 @@
+-- Values of collectors that didn't run for a sample are stored as NULL instead of being repeated from their last run,
+-- SQLite can't drop NOT NULL from a column, so the table is rebuilt the same way on every database
+CREATE TABLE IF NOT EXISTS samples_new (
+    device_id VARCHAR(255) NOT NULL REFERENCES devices (device_id) ON DELETE CASCADE,
+    time BIGINT NOT NULL,
+    ram_used BIGINT,
+    ram_total BIGINT,
+    cpu_usage REAL,
+    processes INTEGER,
+    network_in BIGINT,
+    network_out BIGINT,
+    load_1 REAL,
+    load_5 REAL,
+    load_15 REAL,
+    cpu_freq BIGINT,
+    cpu_freq_max BIGINT,
+    ram_buffers BIGINT,
+    ram_cached BIGINT,
+    swap_total BIGINT,
+    swap_used BIGINT,
+    zfs_arc BIGINT
+);
+
+INSERT INTO samples_new (device_id, time, ram_used, ram_total, cpu_usage, processes, network_in, network_out,
+    load_1, load_5, load_15, cpu_freq, cpu_freq_max, ram_buffers, ram_cached, swap_total, swap_used, zfs_arc)
+SELECT device_id, time, ram_used, ram_total, cpu_usage, processes, network_in, network_out,
+    load_1, load_5, load_15, cpu_freq, cpu_freq_max, ram_buffers, ram_cached, swap_total, swap_used, zfs_arc
+FROM samples;
+
+-- The index goes with the old table, so it's made again once the name is free
+DROP TABLE samples;
+
+ALTER TABLE samples_new RENAME TO samples;
+
+CREATE UNIQUE INDEX IF NOT EXISTS samples_device_time ON samples (device_id, time);
@@ -1,5 +1,83 @@
+use std::collections::BTreeMap;
+
 use serde_json::{Value, json};
 
+use crate::constants::{COLLECTORS, LOOP_TIME_SECONDS, SAMPLE_TIME_SECONDS, TOP_PROCESS_COUNT};
+
+/// Settings of a single collector
+#[derive(Clone, Copy)]
+pub struct CollectorConfig {
+    /// Whether the collector is run at all
+    pub enabled: bool,
+    /// Least seconds between two runs of the collector, 0 runs it every sample. Its latest metrics are reused in between
+    pub interval: u64,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> CollectorConfig {
+        CollectorConfig { enabled: true, interval: 0 }
+    }
+}
+
+impl CollectorConfig {
+    /// Converts the `CollectorConfig` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "enabled": self.enabled,
+            "interval": self.interval
+        })
+    }
+
+    /// Converts a JSON `Value` to a `CollectorConfig`, missing settings are left at their defaults
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Settings of the collector in the client config
+    pub fn from_json(value: &Value) -> CollectorConfig {
+        let default = CollectorConfig::default();
+
+        CollectorConfig {
+            enabled: value["enabled"].as_bool().unwrap_or(default.enabled),
+            interval: value["interval"].as_u64().unwrap_or(default.interval),
+        }
+    }
+}
+
+/// A command run on an interval, each number it prints is sent as a custom metric
+#[derive(Clone)]
+pub struct CustomCommand {
+    /// Name of the command, shown in the logs when it fails
+    pub name: String,
+    /// Command line, run by the shell
+    pub command: String,
+    /// Least seconds between two runs, 0 runs it every sample. Its latest metrics are reused in between
+    pub interval: u64,
+}
+
+impl CustomCommand {
+    /// Converts the `CustomCommand` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "command": self.command,
+            "interval": self.interval
+        })
+    }
+
+    /// Converts a JSON `Value` to a `CustomCommand`, `None` if it doesn't have a command
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Command in the client config
+    pub fn from_json(value: &Value) -> Option<CustomCommand> {
+        let command = value["command"].as_str().filter(|command| !command.trim().is_empty())?.to_string();
+
+        Some(CustomCommand {
+            name: value["name"].as_str().unwrap_or(&command).to_string(),
+            command,
+            interval: value["interval"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
 /// Settings for a device 1in client mode
 pub struct ClientConfig {
     /// Device ID given by the server
@@ -8,6 +86,22 @@ pub struct ClientConfig {
     pub device_name: String,
     /// Address of the server to connect to
     pub server_addr: String,
+    /// SHA-256 fingerprint of the server's TLS certificate, empty if the server doesn't use TLS
+    pub cert_fingerprint: String,
+    /// Secret given by the server during setup, used to sign every request
+    pub device_secret: String,
+    /// Seconds between reports sent to the server
+    pub interval: u64,
+    /// Seconds between samples, samples taken since the last report are sent together
+    pub sample_interval: u64,
+    /// Number of processes reported by CPU usage and by memory each sample, 0 to not report any
+    pub process_count: usize,
+    /// Whether only the executable of each process is reported instead of its whole command line
+    pub redact_cmdline: bool,
+    /// Settings of each collector by name, collectors that aren't listed run every sample
+    pub collectors: BTreeMap<String, CollectorConfig>,
+    /// Commands whose output is sent as custom metrics
+    pub commands: Vec<CustomCommand>,
 }
 
 impl ClientConfig {
@@ -17,31 +111,83 @@ impl ClientConfig {
     /// * `device_id: String` - ID of the device given by the server
     /// * `device_name`: String - Friendly name fot the device
     /// * `server_addr: String` - Address of the server to connect to
+    /// * `cert_fingerprint: String` - Pinned fingerprint of the server's certificate, empty for plaintext
+    /// * `device_secret: String` - Secret given by the server during setup
+    ///
+    /// Reports are sent every `LOOP_TIME_SECONDS`, samples are taken every `SAMPLE_TIME_SECONDS`
+    /// and the top `TOP_PROCESS_COUNT` processes are reported with their whole command line
     ///
     /// # Returns
     /// * A `ClientConfig` instance created from the arguments
-    pub fn new(device_id: String, device_name: String, server_addr: String) -> ClientConfig {
+    pub fn new(device_id: String, device_name: String, server_addr: String, cert_fingerprint: String, device_secret: String) -> ClientConfig {
         ClientConfig {
             device_id: device_id,
             device_name: device_name,
             server_addr: server_addr,
+            cert_fingerprint,
+            device_secret,
+            interval: LOOP_TIME_SECONDS,
+            sample_interval: SAMPLE_TIME_SECONDS,
+            process_count: TOP_PROCESS_COUNT,
+            redact_cmdline: false,
+            collectors: BTreeMap::new(),
+            commands: Vec::new(),
         }
     }
 
+    /// Returns the settings of a collector
+    ///
+    /// # Arguments
+    /// * `name: &str` - Name of the collector
+    pub fn collector(&self, name: &str) -> CollectorConfig {
+        self.collectors.get(name).copied().unwrap_or_default()
+    }
+
     /// Convert a `ClientConfig` instance to a `Value` instance
     pub fn to_json(&self) -> Value {
         json!({
             "deviceID"  : self.device_id,
             "deviceName": self.device_name,
-            "serverAddr": self.server_addr
+            "serverAddr": self.server_addr,
+            "certFingerprint": self.cert_fingerprint,
+            "deviceSecret": self.device_secret,
+            "interval": self.interval,
+            "sampleInterval": self.sample_interval,
+            "processCount": self.process_count,
+            "redactCommandLines": self.redact_cmdline,
+            "collectors": self.collectors.iter().map(|(name, collector)| (name.clone(), collector.to_json())).collect::<serde_json::Map<String, Value>>(),
+            "commands": self.commands.iter().map(CustomCommand::to_json).collect::<Vec<Value>>()
         })
     }
 
     /// Returns formatted string from the `ClientConfig` instance
     pub fn to_string(&self) -> String {
+        let collectors: Vec<String> = COLLECTORS
+            .iter()
+            .map(|name| match self.collector(name) {
+                CollectorConfig { enabled: false, .. } => format!("{name} (disabled)"),
+                CollectorConfig { interval: 0, .. } => name.to_string(),
+                CollectorConfig { interval, .. } => format!("{name} (every {interval}s)"),
+            })
+            .collect();
+
+        let commands: Vec<String> = self
+            .commands
+            .iter()
+            .map(|command| match command.interval {
+                0 => format!("{} ({})", command.name, command.command),
+                interval => format!("{} ({}, every {interval}s)", command.name, command.command),
+            })
+            .collect();
+
         format!(
-            "Device ID: {}\nDevice Name: {}\nServer Address: {}",
-            self.device_id, self.device_name, self.server_addr
+            "Device ID: {}\nDevice Name: {}\nServer Address: {}\nCertificate Fingerprint: {}\nInterval: {}s (sampling every {}s)\nTop Processes: {}{}\nCollectors: {}\nCommands: {}",
+            self.device_id, self.device_name, self.server_addr,
+            if self.cert_fingerprint.is_empty() { "None (plaintext)" } else { &self.cert_fingerprint },
+            self.interval, self.sample_interval,
+            self.process_count, if self.redact_cmdline { " (command lines redacted)" } else { "" },
+            collectors.join(", "),
+            if commands.is_empty() { "None".to_string() } else { commands.join(", ") }
         )
     }
 }
\ No newline at end of file
@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+
 use serde_json::{json, Value};
 
+use crate::constants::{DEFAULT_PORT, LOOP_TIME_SECONDS, MAX_SAMPLE_AGE_SECONDS, RAW_RETENTION_DAYS, ROLLUP_RETENTION_DAYS, TEMP_CRITICAL_CELSIUS, TEMP_WARNING_CELSIUS};
+
 #[derive(Clone)]
 /// Configuration for a device in server mode
 pub struct ServerConfig {
@@ -11,7 +15,125 @@ pub struct ServerConfig {
     pub admin_ids: Vec<String>,
 
     /// If this is the first run of the server, it will check if a DB exists, if it does, it will add all device IDs to the list of trusted devices
-    pub first_run: bool
+    pub first_run: bool,
+
+    /// Accept TLS connections using the certificate stored in the config dir
+    pub tls_enabled: bool,
+
+    /// Reject clients that don't connect with TLS
+    pub require_tls: bool,
+
+    /// Public key of each device used to check its signatures, keyed by device ID. The secrets they're made from
+    /// are only kept by the devices
+    pub device_keys: HashMap<String, String>,
+
+    /// Reject requests that aren't signed with a device secret
+    pub require_auth: bool,
+
+    /// Devices that ran setup and are waiting for an admin, keyed by device ID
+    pub pending_devices: HashMap<String, PendingDevice>,
+
+    /// New devices have to be approved by an admin before they can send data
+    pub require_approval: bool,
+
+    /// Code new devices have to supply during setup, empty to allow setup without one
+    pub enrollment_code: String,
+
+    /// Subnets in CIDR notation whose devices are approved without an admin
+    pub auto_approve_subnets: Vec<String>,
+
+    /// Addresses the server listens on, an address without a port uses `port`
+    pub bind_addrs: Vec<String>,
+
+    /// Port used for bind addresses that don't have one
+    pub port: u16,
+
+    /// Shortest time in seconds a device has to wait between reports
+    pub min_interval: u64,
+
+    /// Per device overrides of `min_interval`, keyed by device ID
+    pub device_intervals: HashMap<String, u64>,
+
+    /// Temperature in degrees Celsius the TUI marks as a warning
+    pub temp_warning: f64,
+
+    /// Temperature in degrees Celsius the TUI marks as critical
+    pub temp_critical: f64,
+
+    /// How long samples and rollups are kept
+    pub retention: Retention,
+
+    /// Per device overrides of `retention`, keyed by device ID
+    pub device_retention: HashMap<String, Retention>,
+
+    /// URL of the database to store everything in, such as `postgres://rlsd@localhost/rlsd`, empty to use the sqlite database
+    pub database_url: String
+}
+
+#[derive(Clone, Copy, PartialEq)]
+/// How long the data of a device is kept before the server prunes it
+pub struct Retention {
+    /// Days raw samples and their details are kept for, 0 keeps them forever
+    pub raw_days: u64,
+
+    /// Days rollups are kept for, 0 keeps them forever
+    pub rollup_days: u64
+}
+
+impl Retention {
+    /// Convert a `Retention` instance to a `Value` instance
+    pub fn to_json(&self) -> Value {
+        json!({
+            "rawDays": self.raw_days,
+            "rollupDays": self.rollup_days
+        })
+    }
+
+    /// Works out the times data older than is pruned
+    ///
+    /// Raw samples are kept at least as long as clients spool them, so late samples can still be rolled up
+    ///
+    /// # Arguments
+    /// * `now: i64` - Current unix timestamp
+    ///
+    /// # Returns
+    /// `(Option<i64>, Option<i64>)` - Unix timestamps raw samples and rollups older than are pruned, `None` if they're kept forever
+    pub fn cutoffs(&self, now: i64) -> (Option<i64>, Option<i64>) {
+        let days = |days: u64| (days > 0).then(|| (days as i64).saturating_mul(24 * 60 * 60));
+
+        (
+            days(self.raw_days).map(|age| now.saturating_sub(age.max(MAX_SAMPLE_AGE_SECONDS))),
+            days(self.rollup_days).map(|age| now.saturating_sub(age)),
+        )
+    }
+}
+
+#[derive(Clone)]
+/// A device that ran setup and is waiting for an admin to approve it
+pub struct PendingDevice {
+    /// Hostname the device reported during setup
+    pub hostname: String,
+
+    /// Address the setup request came from
+    pub ip: String,
+
+    /// Unix timestamp of the setup request
+    pub requested_at: i64,
+
+    /// Public key of the secret issued to the device, empty for legacy clients
+    pub public_key: String
+}
+
+impl PendingDevice {
+    /// Convert a `PendingDevice` instance to a `Value` instance
+    pub fn to_json(&self) -> Value {
+        json!({
+            "hostname": self.hostname,
+            "ip": self.ip,
+            "requestedAt": self.requested_at,
+            "publicKey": self.public_key
+        })
+    }
 }
 
 impl ServerConfig {
@@ -22,6 +144,11 @@ impl ServerConfig {
     /// * `admin_ids: Vec<String>` - List of device IDs that have admin access
     /// * `first_run: bool` - If this is the first run of the server
     /// 
+    /// TLS is enabled but not required, requests have to be signed and new devices have to be approved,
+    /// the server listens on every IPv4 address on `DEFAULT_PORT` and devices can report every `LOOP_TIME_SECONDS`,
+    /// temperatures are marked at `TEMP_WARNING_CELSIUS` and `TEMP_CRITICAL_CELSIUS`,
+    /// raw samples are kept for `RAW_RETENTION_DAYS` and rollups for `ROLLUP_RETENTION_DAYS` in the sqlite database
+    ///
     /// # Returns
     /// * A `ServerConfig` instance created from the arguments
     pub fn new(registered_device_ids: Vec<String>, admin_ids: Vec<String>, first_run: bool) -> ServerConfig {
@@ -29,15 +156,120 @@ impl ServerConfig {
             registered_device_ids,
             admin_ids,
             first_run,
+            tls_enabled: true,
+            require_tls: false,
+            device_keys: HashMap::new(),
+            require_auth: true,
+            pending_devices: HashMap::new(),
+            require_approval: true,
+            enrollment_code: String::new(),
+            auto_approve_subnets: Vec::new(),
+            bind_addrs: vec!["0.0.0.0".to_string()],
+            port: DEFAULT_PORT,
+            min_interval: LOOP_TIME_SECONDS,
+            device_intervals: HashMap::new(),
+            temp_warning: TEMP_WARNING_CELSIUS,
+            temp_critical: TEMP_CRITICAL_CELSIUS,
+            retention: Retention { raw_days: RAW_RETENTION_DAYS, rollup_days: ROLLUP_RETENTION_DAYS },
+            device_retention: HashMap::new(),
+            database_url: String::new(),
         }
     }
 
+    /// Returns the shortest time a device has to wait between reports
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the device
+    pub fn interval_for(&self, device_id: &str) -> u64 {
+        self.device_intervals.get(device_id).copied().unwrap_or(self.min_interval)
+    }
+
+    /// Returns how long the data of a device is kept
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the device
+    pub fn retention_for(&self, device_id: &str) -> Retention {
+        self.device_retention.get(device_id).copied().unwrap_or(self.retention)
+    }
+
+    /// Registers a pending device
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the pending device
+    ///
+    /// # Returns
+    /// `bool` - True if the device was pending
+    pub fn approve(&mut self, device_id: &str) -> bool {
+        match self.pending_devices.remove(device_id) {
+            Some(device) => {
+                self.registered_device_ids.push(device_id.to_string());
+
+                if !device.public_key.is_empty() {
+                    self.device_keys.insert(device_id.to_string(), device.public_key);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops a pending device, it will have to run setup again
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the pending device
+    ///
+    /// # Returns
+    /// `bool` - True if the device was pending
+    pub fn deny(&mut self, device_id: &str) -> bool {
+        self.pending_devices.remove(device_id).is_some()
+    }
+
+    /// Lists the pending devices, oldest first
+    ///
+    /// # Returns
+    /// `String` - One line per device with its ID, hostname, address and when it ran setup
+    pub fn pending_list(&self) -> String {
+        let mut devices: Vec<(&String, &PendingDevice)> = self.pending_devices.iter().collect();
+
+        devices.sort_by_key(|(_, device)| device.requested_at);
+
+        let mut msg = String::new();
+
+        for (id, device) in devices {
+            let requested_at = chrono::DateTime::from_timestamp(device.requested_at, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+
+            msg = format!("{msg}\n{id}: {} ({}) at {requested_at}", device.hostname, device.ip);
+        }
+
+        msg
+    }
+
     /// Convert a `ServerConfig` instance to a `Value` instance
     pub fn to_json(&self) -> Value {
         json!({
             "registeredDeviceIDs": self.registered_device_ids,
             "adminIDs": self.admin_ids,
-            "firstRun": self.first_run
+            "firstRun": self.first_run,
+            "tlsEnabled": self.tls_enabled,
+            "requireTLS": self.require_tls,
+            "deviceKeys": self.device_keys,
+            "requireAuth": self.require_auth,
+            "pendingDevices": self.pending_devices.iter().map(|(id, device)| (id.to_owned(), device.to_json())).collect::<serde_json::Map<String, Value>>(),
+            "requireApproval": self.require_approval,
+            "enrollmentCode": self.enrollment_code,
+            "autoApproveSubnets": self.auto_approve_subnets,
+            "bindAddr": self.bind_addrs,
+            "port": self.port,
+            "minInterval": self.min_interval,
+            "deviceIntervals": self.device_intervals,
+            "tempWarning": self.temp_warning,
+            "tempCritical": self.temp_critical,
+            "retention": self.retention.to_json(),
+            "deviceRetention": self.device_retention.iter().map(|(id, retention)| (id.to_owned(), retention.to_json())).collect::<serde_json::Map<String, Value>>(),
+            "databaseUrl": self.database_url
         })
     }
 }
\ No newline at end of file
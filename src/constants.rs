@@ -6,13 +6,128 @@ use once_cell::sync::OnceCell;
 static PROJ_DIRS: OnceCell<ProjectDirs> = OnceCell::new();
 
 // Client
+/// Default seconds between reports, also the server's default minimum interval
 pub const LOOP_TIME_SECONDS: u64 = 120;
+/// Port the server listens on unless it's configured otherwise
+pub const DEFAULT_PORT: u16 = 51347;
+/// Largest the spool of unsent samples can get before the oldest are dropped
+pub const SPOOL_MAX_BYTES: u64 = 8 * 1024 * 1024;
+/// Samples older than this are dropped from the spool, one week
+pub const SPOOL_MAX_AGE_SECONDS: i64 = 7 * 24 * 60 * 60;
+/// Samples sent per batch when the spool is replayed
+pub const SPOOL_BATCH_SIZE: usize = 100;
+/// Default seconds between samples, samples are collected and sent together every report
+pub const SAMPLE_TIME_SECONDS: u64 = 60;
+/// Fewest seconds between two samples of a device, clients don't sample more often and the server drops samples
+/// closer than this to another, so a device can't store more rows than this allows however it sends them
+pub const MIN_SAMPLE_INTERVAL_SECONDS: u64 = 10;
+/// Default number of processes reported by CPU usage and by memory each sample
+pub const TOP_PROCESS_COUNT: usize = 10;
+/// Filesystem types that don't store anything on a disk, their mounts aren't reported
+pub const IGNORED_FILESYSTEMS: [&str; 25] = [
+    "autofs", "binfmt_misc", "bpf", "cgroup", "cgroup2", "configfs", "debugfs", "devpts", "devtmpfs", "efivarfs",
+    "fusectl", "hugetlbfs", "mqueue", "nsfs", "overlay", "proc", "pstore", "ramfs", "rpc_pipefs", "securityfs",
+    "selinuxfs", "squashfs", "sysfs", "tmpfs", "tracefs",
+];
+/// Block devices starting with these names aren't disks, their I/O isn't reported
+pub const IGNORED_BLOCK_DEVICES: [&str; 2] = ["loop", "ram"];
+/// Kind of a sensor reporting a temperature in degrees Celsius
+pub const SENSOR_TEMPERATURE: &str = "temperature";
+/// Kind of a sensor reporting how charged the battery is in percent
+pub const SENSOR_BATTERY: &str = "battery";
+/// Kind of a sensor reporting 1 when AC power is plugged in and 0 otherwise
+pub const SENSOR_AC: &str = "ac";
+/// Names of the built-in collectors, each can be disabled or given its own interval in the client config
+pub const COLLECTORS: [&str; 8] = ["cpu", "memory", "filesystems", "diskIO", "network", "sensors", "processes", "push"];
+/// Seconds a custom metric command can run for before it is killed
+pub const COMMAND_TIMEOUT_SECONDS: u64 = 10;
+/// Longest name a custom metric can have
+pub const MAX_METRIC_NAME_LENGTH: usize = 100;
+
+// Metrics, each built-in metric fills a field of `Device`, any other is sent with the sample by name
+/// CPU usage from 0.0 to 1.0
+pub const METRIC_CPU_USAGE: &str = "cpu.usage";
+/// Usage of each core from 0.0 to 1.0
+pub const METRIC_CPU_CORES: &str = "cpu.cores";
+/// 1 minute load average
+pub const METRIC_LOAD_1: &str = "load.1";
+/// 5 minute load average
+pub const METRIC_LOAD_5: &str = "load.5";
+/// 15 minute load average
+pub const METRIC_LOAD_15: &str = "load.15";
+/// Current CPU frequency in MHz
+pub const METRIC_CPU_FREQ: &str = "cpu.freq";
+/// Maximum CPU frequency in MHz
+pub const METRIC_CPU_FREQ_MAX: &str = "cpu.freqMax";
+/// Installed RAM in bytes
+pub const METRIC_RAM_TOTAL: &str = "ram.total";
+/// RAM in use in bytes
+pub const METRIC_RAM_USED: &str = "ram.used";
+/// RAM used by kernel buffers in bytes
+pub const METRIC_RAM_BUFFERS: &str = "ram.buffers";
+/// RAM used by the page cache in bytes
+pub const METRIC_RAM_CACHED: &str = "ram.cached";
+/// Size of swap in bytes
+pub const METRIC_SWAP_TOTAL: &str = "swap.total";
+/// Swap in use in bytes
+pub const METRIC_SWAP_USED: &str = "swap.used";
+/// Size of the ZFS ARC in bytes
+pub const METRIC_ZFS_ARC: &str = "zfs.arc";
+/// Usage of each filesystem
+pub const METRIC_FILESYSTEMS: &str = "filesystems";
+/// Throughput of each disk
+pub const METRIC_DISK_IO: &str = "diskIO";
+/// Traffic of each network interface
+pub const METRIC_INTERFACES: &str = "network.interfaces";
+/// Temperatures, battery charge and AC state
+pub const METRIC_SENSORS: &str = "sensors";
+/// Number of processes running
+pub const METRIC_PROCESS_COUNT: &str = "processes.count";
+/// Busiest processes
+pub const METRIC_TOP_PROCESSES: &str = "processes.top";
 
 //Server
 pub const DOWN_SAMPLE_POINTS: u16 = 40;
 pub const OUTLIER_THRESHOLD: f64 = 0.5;
 pub const DO_INTERPOLATION: bool = true;
 pub const INTERPOLATION_STEPS: u16 = 64;
+/// Most samples the TUI loads for the time range it shows, longer ranges are loaded from rollups or thinned out evenly
+pub const TUI_MAX_SAMPLES: usize = 2000;
+/// Bucket sizes in seconds of the rollup tiers, finest first, each tier is made from the one before it
+pub const ROLLUP_RESOLUTIONS: [i64; 3] = [15 * 60, 60 * 60, 24 * 60 * 60];
+/// How often the server rolls new samples up into the rollup tiers
+pub const ROLLUP_INTERVAL_SECONDS: u64 = 300;
+/// Default days raw samples are kept for
+pub const RAW_RETENTION_DAYS: u64 = 30;
+/// Default days rollups are kept for
+pub const ROLLUP_RETENTION_DAYS: u64 = 2 * 365;
+/// How often the server prunes data older than its retention
+pub const PRUNE_INTERVAL_SECONDS: u64 = 60 * 60;
+/// Most rows removed from a table in one transaction while pruning, so inserts aren't held up for long
+pub const PRUNE_BATCH_SIZE: i64 = 5000;
+/// Default temperature in degrees Celsius the TUI shows as a warning
+pub const TEMP_WARNING_CELSIUS: f64 = 80.0;
+/// Default temperature in degrees Celsius the TUI shows as critical
+pub const TEMP_CRITICAL_CELSIUS: f64 = 95.0;
+/// How long a client has to send its whole request before it is dropped
+pub const READ_TIMEOUT_SECONDS: u64 = 10;
+/// How long a client has to accept the reply before it is dropped
+pub const WRITE_TIMEOUT_SECONDS: u64 = 10;
+/// How far a signed request's timestamp can be from the server's clock, nonces are remembered for this long
+pub const AUTH_WINDOW_SECONDS: i64 = 300;
+/// Most devices that can wait for approval at once, further setup requests are refused
+pub const MAX_PENDING_DEVICES: usize = 100;
+/// How much earlier than its interval a device can report, covers sampling time and network delay
+pub const INTERVAL_LEEWAY_SECONDS: i64 = 10;
+/// Most samples accepted in a single batch
+pub const MAX_BATCH_SAMPLES: usize = 500;
+/// Most custom metrics kept from a single sample, the rest are dropped
+pub const MAX_CUSTOM_METRICS: usize = 200;
+/// Largest difference between a client's clock and the server's that's put down to network delay,
+/// timestamps from clients that are further off are moved onto the server's clock
+pub const MAX_CLOCK_SKEW_SECONDS: i64 = 30;
+/// Oldest sample a batch can hold, matches how long the client keeps unsent samples
+pub const MAX_SAMPLE_AGE_SECONDS: i64 = SPOOL_MAX_AGE_SECONDS;
 
 pub fn setup() {
     PROJ_DIRS
@@ -57,6 +172,16 @@ pub fn get_server_config_path() -> String {
     format!("{}/server-config.json", get_config_dir())
 }
 
+/// Returns the path to the server's TLS certificate
+pub fn get_tls_cert_path() -> String {
+    format!("{}/server-cert.pem", get_config_dir())
+}
+
+/// Returns the path to the server's TLS private key
+pub fn get_tls_key_path() -> String {
+    format!("{}/server-key.pem", get_config_dir())
+}
+
 pub fn get_data_dir() -> String {
     let proj_dir = PROJ_DIRS.get().expect("ProjectDirs is not initialized :(");
 
@@ -67,6 +192,21 @@ pub fn get_data_dir() -> String {
     data_dir.to_string()
 } 
 
+/// Returns the path to the log of failed authentication attempts
+pub fn get_auth_log_path() -> String {
+    format!("{}/auth.log", get_data_dir())
+}
+
+/// Returns the path to the Unix socket the client receives pushed metrics on
+pub fn get_push_socket_path() -> String {
+    format!("{}/metrics.sock", get_data_dir())
+}
+
+/// Returns the path to the client's spool of unsent samples
+pub fn get_spool_path() -> String {
+    format!("{}/spool.jsonl", get_data_dir())
+}
+
 pub fn get_db_path() -> String {
     format!("{}/database.sqlite", get_data_dir())
 }
@@ -1,15 +1,15 @@
 //! This module is used for read and writing the json data used for the overlays and the app
 use std::{
-    fs::{self, File},
-    io::{BufReader, Read},
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Read, Write},
     path::Path,
 };
 
 use serde_json::{json, Value};
 
 use crate::{
-    config::{client::ClientConfig, server::ServerConfig},
-    constants::{self, get_client_config_path, get_server_config_path},
+    config::{client::{ClientConfig, CollectorConfig, CustomCommand}, server::{PendingDevice, Retention, ServerConfig}},
+    constants::{self, get_client_config_path, get_server_config_path, DEFAULT_PORT, LOOP_TIME_SECONDS, RAW_RETENTION_DAYS, ROLLUP_RETENTION_DAYS, SAMPLE_TIME_SECONDS, TOP_PROCESS_COUNT},
     stats_handling::device_info::Device,
 };
 
@@ -24,6 +24,23 @@ pub fn read_client_config_string(key: &str) -> String {
     read_json(key, &constants::get_client_config_path())
 }
 
+/// Reads the config json and returns the value of the requested key,
+/// or the default if the key is missing (used for keys added after a client was set up)
+///
+/// # Arguments
+/// * `key: &str` - The key to be read from the json file
+/// * `default: &str` - Value returned if the key is missing
+///
+/// # Returns
+/// * `String` - The data at the desired key
+pub fn read_client_config_string_or(key: &str, default: &str) -> String {
+    open_json(&constants::get_client_config_path())
+        .get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or(default)
+        .to_string()
+}
+
 /// Reads the server config as a `Value` instance
 /// 
 /// # Returns
@@ -119,7 +136,7 @@ pub fn init_json(path: &str) -> Value {
     };
 
     // Creating the JSON file
-    fs::write(
+    write_private(
         &path,
         serde_json::to_string_pretty(&json_data).expect(
             "Error 
@@ -132,6 +149,35 @@ pub fn init_json(path: &str) -> Value {
     open_json(path)
 }
 
+/// Writes a file only the user running rlsd can read, such as the configs holding secrets or the TLS private key
+///
+/// # Arguments
+/// * `path: &str` - Path to the file
+/// * `contents: String` - What to write to it
+pub fn write_private(path: &str, contents: String) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+
+    // Files written before this keep their old permissions until they're tightened
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(contents.as_bytes())
+}
+
 /// Writes to the JSON file at the supplied path
 ///
 /// # Arguments
@@ -150,7 +196,7 @@ pub fn write_json(path: &str, json_key: &str, value: Value) {
 
     json_data[json_key] = value;
 
-    fs::write(
+    write_private(
         path,
         serde_json::to_string_pretty(&json_data).expect("Error serializing to JSON"),
     )
@@ -159,7 +205,7 @@ pub fn write_json(path: &str, json_key: &str, value: Value) {
 
 /// Write to a file from a JSON value
 pub fn write_json_from_value(path: &str, json: Value) {
-    fs::write(
+    write_private(
         path,
         serde_json::to_string_pretty(&json).expect("Error serializing to json"),
     )
@@ -286,7 +332,7 @@ pub fn write_server_config(json_key: &str, value: Value) {
 /// # Arguments
 /// * `value: Value` - Configuration to set the whole config to
 pub fn write_server_config_all(value: Value) {
-    fs::write(
+    write_private(
         &constants::get_server_config_path(),
         serde_json::to_string_pretty(&value).expect("Error serializing to JSON"),
     )
@@ -387,8 +433,16 @@ pub fn reset_server_config() {
 fn get_default_client_json_data() -> Value {
     json!({
         "deviceID": "N/A",
-        "serverAddr": "127.0.0.1:51347",
-        "friendlyName": "No Config Present"
+        "serverAddr": format!("127.0.0.1:{DEFAULT_PORT}"),
+        "friendlyName": "No Config Present",
+        "certFingerprint": "",
+        "deviceSecret": "",
+        "interval": LOOP_TIME_SECONDS,
+        "sampleInterval": SAMPLE_TIME_SECONDS,
+        "processCount": TOP_PROCESS_COUNT,
+        "redactCommandLines": false,
+        "collectors": {},
+        "commands": []
     })
 }
 
@@ -397,7 +451,22 @@ fn get_default_server_json_data() -> Value {
     json!({
         "registeredDeviceIDs": [],
         "adminIDs": [],
-        "firstRun": true
+        "firstRun": true,
+        "tlsEnabled": true,
+        "requireTLS": false,
+        "deviceKeys": {},
+        "requireAuth": true,
+        "pendingDevices": {},
+        "requireApproval": true,
+        "enrollmentCode": "",
+        "autoApproveSubnets": [],
+        "bindAddr": "0.0.0.0",
+        "port": DEFAULT_PORT,
+        "minInterval": LOOP_TIME_SECONDS,
+        "deviceIntervals": {},
+        "retention": {"rawDays": RAW_RETENTION_DAYS, "rollupDays": ROLLUP_RETENTION_DAYS},
+        "deviceRetention": {},
+        "databaseUrl": ""
     })
 }
 
@@ -422,11 +491,28 @@ impl ToClientConfig for serde_json::Value {
     /// # Returns
     /// * A `ClientConfig` instance created from the JSON `Value`
     fn to_client(&self) -> ClientConfig {
-        ClientConfig::new(
+        let mut config = ClientConfig::new(
             self["deviceID"].as_str().unwrap_or_default().to_string(),
             self["deviceName"].as_str().unwrap_or_default().to_string(),
             self["serverAddr"].as_str().unwrap_or_default().to_string(),
-        )
+            self["certFingerprint"].as_str().unwrap_or_default().to_string(),
+            self["deviceSecret"].as_str().unwrap_or_default().to_string(),
+        );
+
+        config.interval = self["interval"].as_u64().unwrap_or(config.interval);
+        config.sample_interval = self["sampleInterval"].as_u64().unwrap_or(config.sample_interval);
+        config.process_count = self["processCount"].as_u64().map(|count| count as usize).unwrap_or(config.process_count);
+        config.redact_cmdline = self["redactCommandLines"].as_bool().unwrap_or(config.redact_cmdline);
+
+        if let Some(collectors) = self["collectors"].as_object() {
+            config.collectors = collectors.iter().map(|(name, collector)| (name.clone(), CollectorConfig::from_json(collector))).collect();
+        }
+
+        if let Some(commands) = self["commands"].as_array() {
+            config.commands = commands.iter().filter_map(CustomCommand::from_json).collect();
+        }
+
+        config
     }
 }
 
@@ -439,10 +525,72 @@ impl ToServerConfig for serde_json::Value {
         let registered_device_ids: Vec<String> = self["registeredDeviceIDs"].as_array().unwrap_or(&Vec::new()).iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect();
         let admin_ids: Vec<String> = self["adminIDs"].as_array().unwrap_or(&Vec::new()).iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect();
 
-        ServerConfig::new(
+        let mut config = ServerConfig::new(
             registered_device_ids,
             admin_ids,
             self["firstRun"].as_bool().unwrap_or(false),
-        )
+        );
+
+        config.tls_enabled = self["tlsEnabled"].as_bool().unwrap_or(config.tls_enabled);
+        config.require_tls = self["requireTLS"].as_bool().unwrap_or(config.require_tls);
+
+        if let Some(keys) = self["deviceKeys"].as_object() {
+            config.device_keys = keys.iter().map(|(id, key)| (id.to_owned(), key.as_str().unwrap_or_default().to_string())).collect();
+        }
+
+        // Configs written before device secrets existed keep accepting unsigned requests until requireAuth is turned on
+        config.require_auth = self["requireAuth"].as_bool().unwrap_or(false);
+
+        if let Some(pending) = self["pendingDevices"].as_object() {
+            config.pending_devices = pending.iter().map(|(id, device)| (id.to_owned(), PendingDevice {
+                hostname: device["hostname"].as_str().unwrap_or_default().to_string(),
+                ip: device["ip"].as_str().unwrap_or_default().to_string(),
+                requested_at: device["requestedAt"].as_i64().unwrap_or_default(),
+                public_key: device["publicKey"].as_str().unwrap_or_default().to_string(),
+            })).collect();
+        }
+
+        // Same as requireAuth, older configs keep registering devices straight away
+        config.require_approval = self["requireApproval"].as_bool().unwrap_or(false);
+        config.enrollment_code = self["enrollmentCode"].as_str().unwrap_or_default().to_string();
+        config.auto_approve_subnets = self["autoApproveSubnets"].as_array().unwrap_or(&Vec::new()).iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect();
+
+        // bindAddr can be a single address or a list of them
+        match &self["bindAddr"] {
+            Value::String(addr) => config.bind_addrs = vec![addr.to_owned()],
+            Value::Array(addrs) => config.bind_addrs = addrs.iter().filter_map(|v| v.as_str()).map(str::to_string).collect(),
+            _ => {}
+        }
+
+        config.port = self["port"].as_u64().and_then(|port| u16::try_from(port).ok()).unwrap_or(config.port);
+
+        config.min_interval = self["minInterval"].as_u64().unwrap_or(config.min_interval);
+
+        if let Some(intervals) = self["deviceIntervals"].as_object() {
+            config.device_intervals = intervals.iter().filter_map(|(id, interval)| Some((id.to_owned(), interval.as_u64()?))).collect();
+        }
+
+        config.temp_warning = self["tempWarning"].as_f64().unwrap_or(config.temp_warning);
+        config.temp_critical = self["tempCritical"].as_f64().unwrap_or(config.temp_critical);
+
+        // Configs written before retention existed keep everything until it's set, a missing setting keeps its default
+        let retention = |value: &Value, base: Retention| Retention {
+            raw_days: value["rawDays"].as_u64().unwrap_or(base.raw_days),
+            rollup_days: value["rollupDays"].as_u64().unwrap_or(base.rollup_days),
+        };
+
+        config.retention = match &self["retention"] {
+            Value::Object(_) => retention(&self["retention"], config.retention),
+            _ => Retention { raw_days: 0, rollup_days: 0 },
+        };
+
+        // Settings a device doesn't override come from the server wide retention
+        if let Some(devices) = self["deviceRetention"].as_object() {
+            config.device_retention = devices.iter().map(|(id, value)| (id.to_owned(), retention(value, config.retention))).collect();
+        }
+
+        config.database_url = self["databaseUrl"].as_str().unwrap_or_default().to_string();
+
+        config
     }
 }
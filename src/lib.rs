@@ -7,17 +7,29 @@ pub mod constants;
 pub mod macros;
 
 pub mod socket_handling {
+    pub mod auth;
     pub mod command_type;
+    pub mod frame;
+    pub mod response;
     pub mod server;
     pub mod client;
+    pub mod tls;
 }
 
 pub mod stats_handling {
+    pub mod collector;
     pub mod conversions;
+    pub mod custom_metrics;
     pub mod database;
     pub mod device_info;
+    #[cfg(feature = "postgres")]
+    pub mod postgres;
+    pub mod retention;
+    pub mod sensors;
+    pub mod spool;
     pub mod stats_getter;
     pub mod stats_loop;
+    pub mod storage;
 }
 
 pub mod json_handler;
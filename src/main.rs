@@ -2,13 +2,15 @@ use std::env;
 
 use crossterm::style::Stylize;
 use rlsd::{
-    config::client::ClientConfig,
-    constants::{self, get_client_config_path, get_server_config_path},
+    config::client::{ClientConfig, CustomCommand},
+    constants::{self, get_client_config_path, get_server_config_path, COLLECTORS, DEFAULT_PORT, MIN_SAMPLE_INTERVAL_SECONDS},
     input,
-    json_handler::{self, read_client_config_string, read_json_as_value, write_json_from_value, write_server_config},
-    socket_handling::{self, command_type::Commands, server::Server, client},
+    json_handler::{self, read_client_config_string, read_json_as_value, write_json_from_value, write_server_config, write_server_config_all, ToClientConfig, ToServerConfig},
+    socket_handling::{self, command_type::Commands, response::RlsdError, server::Server, client, tls},
     stats_handling::{
-        database::{self, get_all_device_uids, get_device_name_from_uid},
+        database,
+        retention,
+        stats_getter::get_unix_timestamp,
         stats_loop,
     },
     tui,
@@ -33,51 +35,130 @@ async fn main() {
 
 -h | --help => Prints this message
 
--l | --list => Lists all device uids and their names in the db (run as the user that runs the server)
+-l | --list => Lists all device uids and their names in the db with the hardware and OS they reported (run as the user that runs the server)
 
 --setup => Sets up the client config, used in the install script
 
 -c | --client => Runs rlsd in client or daemon mode to send data to the server
 
--s | --server => Runs the rlsd server on 0.0.0.0:51347 and launches the TUI
+-s | --server => Runs the rlsd server and launches the TUI, listens on bindAddr and port from the server config (0.0.0.0:51347 by default):
+    rlsd -s [--bind <ADDR>]... [--port <PORT>]
 
--st | --server-notui => Runs the rlsd server on 0.0.0.0:51347 without the TUI
+-st | --server-notui => Runs the rlsd server without the TUI, takes the same options as --server
 
 -a | --admin => Adds a device as an admin using the supplied id: rlsd -a <ID>
 
+--pending => Lists devices waiting for approval (run as the user that runs the server)
+
+--approve => Approves a pending device so it can send data: rlsd --approve <ID>
+
+--deny => Removes a device from the pending list: rlsd --deny <ID>
+
 -r | --remove => Removes the supplied id from the db (use --list to get the id): rlsd --remove <ID>    
 
+--prune => Removes samples and rollups older than their retention (run as the user that runs the server):
+    rlsd --prune [--dry-run]
+    --dry-run only reports what would be removed, retention is set in days by retention and deviceRetention in the server config,
+    0 keeps data forever and the server also prunes every hour
+
 -rrm | --remove-remote => (admin only) Removes the supplied id from the db on the configured server (use -rl to get the id):
     rlsd -rl <ID>
 
 -rl | --remote-list => (admin only) Lists all the devices on the server and their ids (does not include admin ids for security)
 
+-rp | --remote-pending => (admin only) Lists devices waiting for approval on the configured server
+
+-ra | --remote-approve => (admin only) Approves a pending device on the configured server: rlsd -ra <ID>
+
+-rd | --remote-deny => (admin only) Removes a device from the pending list on the configured server: rlsd -rd <ID>
+
 -rr | --remove-rename => (admin only) Renames the supplied id's device name on every row on the server's database (use -rl to get the id):
                 rlsd -rr <ID> <NAME>
 
---config => Configure the server address and device name of the client:
-    rlsd --config <name, server-addr> <value>"
+--fingerprint => Prints the fingerprint of the server's TLS certificate (run as the user that runs the server)
+
+--config => Configure the server address, device name and intervals of the client:
+    rlsd --config <name, server-addr, interval, sample-interval, process-count, redact-cmdline> <value>
+    interval is the seconds between reports, sample-interval is the seconds between samples sent with each report
+    process-count is the number of processes reported by CPU and by memory, redact-cmdline is true or false
+    rlsd --config collector <cpu, memory, filesystems, diskIO, network, sensors, processes, push> <on, off, seconds>
+    enables or disables a collector or sets the seconds between its runs, 0 runs it every sample
+    rlsd --config command add <NAME> <seconds> <COMMAND> | rlsd --config command remove <NAME>
+    adds or removes a command printing custom metrics as name=value lines or a JSON object,
+    programs can also push StatsD lines such as queue.depth:12|g to metrics.sock in the client's data directory
+
+Commands sent to the server exit with: 0 success, 1 server error, 2 server unreachable, 3 invalid reply,
+    4 authentication failed, 5 not allowed, 6 not found, 7 sending too often"
             )
         },
         // List, lists all the uids and their friendly names
         "-l" | "--list" => {
-            let ids = get_all_device_uids(&database).await;
+            for device in database.get_devices().await {
+                println!("{}: {}", device.device_name, device.device_id);
+
+                let last_seen = chrono::DateTime::from_timestamp(device.last_seen, 0)
+                    .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default();
 
-            for id in ids {
-                println!("{}: {}", get_device_name_from_uid(&database, &id).await, id)
+                println!("    Last seen: {last_seen}");
+
+                if let Some(inventory) = database.get_inventory(&device.device_id).await {
+                    for line in inventory.lines(get_unix_timestamp()) {
+                        println!("    {line}");
+                    }
+                }
             }
         }
         // Setup, sets the client config and gets the uid
         "--setup" => setup(),
+        // Fingerprint, prints the server certificate's fingerprint so it can be compared during setup
+        "--fingerprint" => match tls::server_fingerprint() {
+            Ok(fingerprint) => println!("{fingerprint}"),
+            Err(e) => eprintln!("{e}"),
+        },
         // Client, 1 minute loops for sending data
         "-c" | "--client" => stats_loop::start_stats_loop().await,
         // Remove, removes the supplied id from the local database
         "-r" | "--remove" => {
             match args.get(2) {
-                Some(id) => println!("{}", database::remove_device(&database, id).await),
+                Some(id) => match database.remove_device(id).await {
+                    Ok(0) => println!("No rows found matching id: {id}"),
+                    Ok(rows) => println!("Removed {rows} rows for device {id}"),
+                    Err(e) => eprintln!("Error removing device: {e}"),
+                },
                 None => eprintln!("Please specify a device id")
             }
         }
+        // Prune, removes data older than its retention from the local database
+        "--prune" => {
+            let dry_run = match args.get(2).map(String::as_str) {
+                None => false,
+                Some("--dry-run") => true,
+                Some(arg) => return eprintln!("Unknown prune option: {arg}"),
+            };
+
+            let config = read_json_as_value(&get_server_config_path()).to_server();
+
+            match retention::prune(database.as_ref(), &config, get_unix_timestamp(), dry_run).await {
+                Ok(pruned) => {
+                    for device in &pruned {
+                        println!("{}: {}", device.device_name, device.device_id);
+                        println!("    Samples: {}", prune_summary(device.samples, device.samples_before, dry_run));
+                        println!("    Rollups: {}", prune_summary(device.rollups, device.rollups_before, dry_run));
+                    }
+
+                    let samples: u64 = pruned.iter().map(|device| device.samples).sum();
+                    let rollups: u64 = pruned.iter().map(|device| device.rollups).sum();
+
+                    if dry_run {
+                        println!("Would remove {samples} samples and {rollups} rollup rows");
+                    } else {
+                        println!("Removed {samples} samples and {rollups} rollup rows");
+                    }
+                }
+                Err(e) => eprintln!("Error pruning: {e}"),
+            }
+        }
         // Remove a device on the remote server (admin) 
         "-rrm" | "--remove-remote" => {
             let removed_device_id = match args.get(2) {
@@ -90,13 +171,47 @@ async fn main() {
                 "removedDeviceID": removed_device_id
             });
 
-            println!("{}", client::send(Commands::REMOVE, payload));
+            print_reply(client::send(Commands::REMOVE, payload));
         }
         // List the devices on the remote server (admin)
         "-rl" | "--remote-list" => {
             let sha_device_id = sha256::digest(read_client_config_string("deviceID"));
 
-            println!("{}", client::send(Commands::LIST, json!({"deviceID": sha_device_id})));
+            print_reply(client::send(Commands::LIST, json!({"deviceID": sha_device_id})));
+        }
+        // List the devices waiting for approval on the remote server (admin)
+        "-rp" | "--remote-pending" => {
+            let sha_device_id = sha256::digest(read_client_config_string("deviceID"));
+
+            print_reply(client::send(Commands::PENDING, json!({"deviceID": sha_device_id})));
+        }
+        // Approve a pending device on the remote server (admin)
+        "-ra" | "--remote-approve" => {
+            let approved_device_id = match args.get(2) {
+                Some(id) => id,
+                None => return eprintln!("Please specify a device id")
+            };
+
+            let payload = json!({
+                "deviceID": sha256::digest(read_client_config_string("deviceID")),
+                "approvedDeviceID": approved_device_id
+            });
+
+            print_reply(client::send(Commands::APPROVE, payload));
+        }
+        // Deny a pending device on the remote server (admin)
+        "-rd" | "--remote-deny" => {
+            let denied_device_id = match args.get(2) {
+                Some(id) => id,
+                None => return eprintln!("Please specify a device id")
+            };
+
+            let payload = json!({
+                "deviceID": sha256::digest(read_client_config_string("deviceID")),
+                "deniedDeviceID": denied_device_id
+            });
+
+            print_reply(client::send(Commands::DENY, payload));
         }
         "-rr" | "--remote-rename" => {
             let sha_device_id = sha256::digest(read_client_config_string("deviceID"));
@@ -115,7 +230,7 @@ async fn main() {
                 "deviceName": device_name
             });
 
-            println!("{}", client::send(Commands::AdminRename, payload))
+            print_reply(client::send(Commands::AdminRename, payload));
         }
 
         // Configure settings for the client
@@ -133,9 +248,7 @@ async fn main() {
                                 "deviceName": device_name
                             });
 
-                            let msg = client::send(Commands::RENAME, payload);
-
-                            println!("{msg}")
+                            print_reply(client::send(Commands::RENAME, payload));
 
                         },
                         None => println!("Please supply the name of your device.")
@@ -144,18 +257,101 @@ async fn main() {
                 "server-addr" => {
                     match args.get(3) {
                         Some(v) => {
-                            let addr = if v.find(":").is_none() {
-                                format!("{}:51347", v)
-                            } else {
-                                v.to_string()
-                            };
+                            let addr = client::normalize_addr(v, DEFAULT_PORT);
 
                             json_handler::write_client_config("serverAddr", Value::String(addr))
                         },
                         None => println!("Please supply the ip address of your server machine")
                     }
                 }
-                _ => println!("Invalid format, use the following: rlsd --config <setting> <value>\nPossibly settings: name, server-ip")
+                "interval" => {
+                    match args.get(3).and_then(|v| v.parse::<u64>().ok()).filter(|v| *v > 0) {
+                        Some(interval) => json_handler::write_client_config("interval", Value::from(interval)),
+                        None => println!("Please supply the number of seconds between reports")
+                    }
+                }
+                "sample-interval" => {
+                    match args.get(3).and_then(|v| v.parse::<u64>().ok()).filter(|v| *v >= MIN_SAMPLE_INTERVAL_SECONDS) {
+                        Some(interval) => json_handler::write_client_config("sampleInterval", Value::from(interval)),
+                        None => println!("Please supply the number of seconds between samples, at least {MIN_SAMPLE_INTERVAL_SECONDS}")
+                    }
+                }
+                "process-count" => {
+                    match args.get(3).and_then(|v| v.parse::<u64>().ok()) {
+                        Some(count) => json_handler::write_client_config("processCount", Value::from(count)),
+                        None => println!("Please supply the number of processes to report, 0 to not report any")
+                    }
+                }
+                "redact-cmdline" => {
+                    match args.get(3).and_then(|v| v.parse::<bool>().ok()) {
+                        Some(redact) => json_handler::write_client_config("redactCommandLines", Value::from(redact)),
+                        None => println!("Please supply true or false")
+                    }
+                }
+                "collector" => {
+                    let name = match args.get(3).filter(|name| COLLECTORS.contains(&name.as_str())) {
+                        Some(name) => name,
+                        None => {
+                            println!("Please supply one of the following collectors: {}", COLLECTORS.join(", "));
+                            return;
+                        }
+                    };
+
+                    let mut config = read_json_as_value(&get_client_config_path()).to_client();
+                    let collector = config.collectors.entry(name.to_string()).or_default();
+
+                    match args.get(4).map(String::as_str) {
+                        Some("on") => collector.enabled = true,
+                        Some("off") => collector.enabled = false,
+                        other => match other.and_then(|seconds| seconds.parse::<u64>().ok()) {
+                            Some(seconds) => collector.interval = seconds,
+                            None => {
+                                println!("Please supply on, off or the number of seconds between runs");
+                                return;
+                            }
+                        },
+                    }
+
+                    json_handler::write_client_config("collectors", config.to_json()["collectors"].clone())
+                }
+                "command" => {
+                    let mut config = read_json_as_value(&get_client_config_path()).to_client();
+
+                    match (args.get(3).map(String::as_str), args.get(4)) {
+                        (Some("add"), Some(name)) => {
+                            let interval = args.get(5).and_then(|seconds| seconds.parse::<u64>().ok());
+                            let command = args.get(6..).map(|words| words.join(" ")).unwrap_or_default();
+
+                            match interval {
+                                Some(interval) if !command.is_empty() => {
+                                    config.commands.retain(|existing| &existing.name != name);
+                                    config.commands.push(CustomCommand { name: name.clone(), command, interval });
+                                }
+                                _ => {
+                                    println!("Please supply the seconds between runs and the command: rlsd --config command add <NAME> <seconds> <COMMAND>");
+                                    return;
+                                }
+                            }
+                        }
+                        (Some("remove"), Some(name)) => {
+                            let count = config.commands.len();
+
+                            config.commands.retain(|existing| &existing.name != name);
+
+                            if config.commands.len() == count {
+                                println!("There's no command named {name}");
+                                return;
+                            }
+                        }
+                        _ => {
+                            println!("Invalid format, use the following: rlsd --config command <add, remove> <NAME>");
+                            return;
+                        }
+                    }
+
+                    json_handler::write_client_config("commands", config.to_json()["commands"].clone())
+                }
+                _ => println!("Invalid format, use the following: rlsd --config <setting> <value>\nPossibly settings: name, server-addr, interval, sample-interval, process-count, redact-cmdline, collector, command")
             }
         },
         "-a" | "--admin" => {
@@ -174,16 +370,58 @@ async fn main() {
 
             println!("Added: {admin_id} to the admin list");
         }
+        // Pending, lists the devices waiting for approval in the local server config
+        "--pending" => {
+            let config = read_json_as_value(&get_server_config_path()).to_server();
+
+            println!("{}", config.pending_list());
+        }
+        // Approve, registers a pending device in the local server config
+        "--approve" => {
+            let approved_device_id = match args.get(2) {
+                Some(id) => id,
+                None => return eprintln!("Please specify a device id")
+            };
+
+            let mut config = read_json_as_value(&get_server_config_path()).to_server();
+
+            if config.approve(approved_device_id) {
+                write_server_config_all(config.to_json());
+
+                println!("Approved: {approved_device_id}");
+            } else {
+                eprintln!("{approved_device_id} isn't waiting for approval");
+            }
+        }
+        // Deny, removes a device from the pending list in the local server config
+        "--deny" => {
+            let denied_device_id = match args.get(2) {
+                Some(id) => id,
+                None => return eprintln!("Please specify a device id")
+            };
+
+            let mut config = read_json_as_value(&get_server_config_path()).to_server();
+
+            if config.deny(denied_device_id) {
+                write_server_config_all(config.to_json());
+
+                println!("Denied: {denied_device_id}");
+            } else {
+                eprintln!("{denied_device_id} isn't waiting for approval");
+            }
+        }
         // Server, starts the socket on a separate thread and then launches the TUI
         "-s" | "--server" => {
             let db_clone = database.clone();
 
+            let mut receiver = Server::new(db_clone, false);
+            apply_listen_args(&mut receiver, &args[2..]);
+
             let receiver_handle = tokio::spawn(async move {
-                let mut receiver = Server::new(db_clone, false);
                 receiver.start().await.unwrap();
             });
 
-            tui::start_tui(&database).await.unwrap();
+            tui::start_tui(database.as_ref()).await.unwrap();
 
             receiver_handle.await.unwrap();
 
@@ -192,6 +430,7 @@ async fn main() {
         // Start the server with no TUI
         "-st" | "--server-notui" => {
             let mut receiver = Server::new(database, true);
+            apply_listen_args(&mut receiver, &args[2..]);
             receiver.start().await.unwrap();
 
             loop {}
@@ -200,21 +439,101 @@ async fn main() {
     }
 }
 
+/// Prints the message of a reply from the server, or the error and exits with its code
+///
+/// # Arguments
+/// * `reply: Result<Value, RlsdError>` - Reply returned by `client::send`
+fn print_reply(reply: Result<Value, RlsdError>) {
+    match reply {
+        Ok(reply) => println!("{}", reply["message"].as_str().unwrap_or_default()),
+        Err(e) => {
+            eprintln!("{}", e.to_string().red());
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+/// Describes what pruning did to one kind of data of a device
+///
+/// # Arguments
+/// * `count: u64` - Number of rows removed or that would be removed
+/// * `before: Option<i64>` - Unix timestamp rows older than are removed, `None` if they're kept forever
+/// * `dry_run: bool` - Nothing was actually removed
+fn prune_summary(count: u64, before: Option<i64>, dry_run: bool) -> String {
+    match before {
+        Some(before) => {
+            let before = chrono::DateTime::from_timestamp(before, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+
+            format!("{} {count} older than {before}", if dry_run { "would remove" } else { "removed" })
+        }
+        None => "kept forever".to_string(),
+    }
+}
+
+/// Applies the `--bind` and `--port` options passed after `--server` or `--server-notui`
+///
+/// # Arguments
+/// * `receiver: &mut Server` - Server to configure
+/// * `args: &[String]` - Arguments after the server option, `--bind` can be passed more than once
+fn apply_listen_args(receiver: &mut Server, args: &[String]) {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--bind", Some(addr)) => receiver.bind_override.push(addr.to_owned()),
+            ("--port", Some(port)) => match port.parse() {
+                Ok(port) => receiver.port_override = Some(port),
+                Err(_) => eprintln!("Invalid port: {port}"),
+            },
+            (arg, _) => eprintln!("Unknown server option: {arg}"),
+        }
+    }
+}
+
 /// This function is used to setup a new device to connect to a server
 pub fn setup() {
     let device_name = input!("Name for your device to be shown: ");
 
-    let mut server_addr = input!("IP of the server machine (No CIDR)");
+    let server_addr = client::normalize_addr(&input!("IP of the server machine (No CIDR)"), DEFAULT_PORT);
 
-    if server_addr.find(":").is_none() {
-        server_addr = format!("{}:51347", server_addr);
-    }
+    // Pin the server's certificate if it supports TLS
+    let cert_fingerprint = match socket_handling::client::fetch_fingerprint(&server_addr) {
+        Ok(fingerprint) => {
+            println!("Server certificate fingerprint:\n{}", fingerprint.clone().yellow().bold());
+
+            match input!("Does this match the fingerprint printed by the server? (y/n)").to_lowercase().as_str() {
+                "y" | "yes" => fingerprint,
+                _ => {
+                    println!("Setup cancelled");
+                    return;
+                }
+            }
+        }
+        Err(e) => {
+            println!("{}", format!("TLS isn't available ({e}), the connection will not be encrypted").red());
+            String::new()
+        }
+    };
+
+    let enrollment_code = input!("Enrollment code (leave empty if the server doesn't use one)");
 
-    let device_id = socket_handling::client::setup(&server_addr);
+    let (device_id, device_secret, pending) = match socket_handling::client::setup(&server_addr, &cert_fingerprint, &enrollment_code) {
+        Ok(enrollment) => enrollment,
+        Err(e) => {
+            eprintln!("{}", format!("Setup failed: {e}").red());
+            std::process::exit(e.exit_code());
+        }
+    };
 
-    let client_conf = ClientConfig::new(device_id, device_name, server_addr);
+    let client_conf = ClientConfig::new(device_id, device_name, server_addr, cert_fingerprint, device_secret);
 
     write_json_from_value(&get_client_config_path(), client_conf.to_json());
 
-    println!("Device info:\n{}", client_conf.to_string().green().bold())
+    println!("Device info:\n{}", client_conf.to_string().green().bold());
+
+    if pending {
+        println!("{}", "This device has to be approved on the server before it can send data: rlsd --approve <ID>".yellow());
+    }
 }
@@ -0,0 +1,252 @@
+//! Per-request authentication using each device's secret
+//!
+//! The server hands every device a random secret during `SETUP`, requests are signed with an Ed25519 key made from that secret
+//! over the command, a timestamp, a nonce and the body. The server only keeps the public key, so its config can check
+//! signatures but can't make them
+use ring::signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::socket_handling::{command_type::Commands, frame::FrameError};
+
+/// Length of the random nonce sent with every request
+pub const NONCE_LEN: usize = 16;
+
+/// Length of the Ed25519 signature
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Authentication block carried by a signed frame
+#[derive(Clone)]
+pub struct Auth {
+    /// ID of the device that signed the request
+    pub device_id: String,
+    /// Unix timestamp the request was signed at
+    pub timestamp: i64,
+    /// Random value that is only accepted once
+    pub nonce: [u8; NONCE_LEN],
+    /// Ed25519 signature of the request
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl Auth {
+    /// Signs a request
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the device sending the request
+    /// * `device_secret: &str` - Secret the server gave the device during setup
+    /// * `command: Commands` - Command being sent
+    /// * `body: &[u8]` - Body of the request
+    /// * `timestamp: i64` - Current unix timestamp
+    pub fn sign(device_id: &str, device_secret: &str, command: Commands, body: &[u8], timestamp: i64) -> Auth {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+
+        let mut signature = [0; SIGNATURE_LEN];
+        signature.copy_from_slice(key_pair(device_secret).sign(&signed_bytes(device_id, command, body, timestamp, &nonce)).as_ref());
+
+        Auth {
+            device_id: device_id.to_string(),
+            timestamp,
+            nonce,
+            signature,
+        }
+    }
+
+    /// Checks the signature
+    ///
+    /// # Arguments
+    /// * `public_key: &str` - Public key of the device stored by the server, as hex
+    /// * `command: Commands` - Command that was sent
+    /// * `body: &[u8]` - Body of the request
+    ///
+    /// # Returns
+    /// `bool` - True if the signature matches
+    pub fn verify(&self, public_key: &str, command: Commands, body: &[u8]) -> bool {
+        let Ok(public_key) = hex::decode(public_key) else {
+            return false;
+        };
+
+        UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(&signed_bytes(&self.device_id, command, body, self.timestamp, &self.nonce), &self.signature)
+            .is_ok()
+    }
+
+    /// Encodes the block for the frame header
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + self.device_id.len() + 8 + NONCE_LEN + SIGNATURE_LEN);
+
+        buf.push(self.device_id.len() as u8);
+        buf.extend_from_slice(self.device_id.as_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.extend_from_slice(&self.nonce);
+        buf.extend_from_slice(&self.signature);
+
+        buf
+    }
+
+    /// Decodes a block read from the frame header
+    ///
+    /// # Arguments
+    /// * `buf: &[u8]` - Bytes of the block
+    pub fn decode(buf: &[u8]) -> Result<Auth, FrameError> {
+        let id_len = *buf.first().ok_or(FrameError::Short { expected: 1, received: 0 })? as usize;
+
+        let expected = 1 + id_len + 8 + NONCE_LEN + SIGNATURE_LEN;
+
+        if buf.len() != expected {
+            return Err(FrameError::Short { expected, received: buf.len() });
+        }
+
+        let (device_id, rest) = buf[1..].split_at(id_len);
+        let (timestamp, rest) = rest.split_at(8);
+        let (nonce, signature) = rest.split_at(NONCE_LEN);
+
+        Ok(Auth {
+            device_id: String::from_utf8_lossy(device_id).to_string(),
+            timestamp: i64::from_be_bytes(timestamp.try_into().unwrap_or_default()),
+            nonce: nonce.try_into().unwrap_or_default(),
+            signature: signature.try_into().unwrap_or([0; SIGNATURE_LEN]),
+        })
+    }
+}
+
+/// Makes a new random secret for a device
+///
+/// # Returns
+/// `String` - 32 random bytes as hex
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Makes the public key the server keeps to check a device's signatures
+///
+/// # Arguments
+/// * `device_secret: &str` - Secret given to the device
+///
+/// # Returns
+/// `String` - Ed25519 public key as hex
+pub fn public_key(device_secret: &str) -> String {
+    hex::encode(key_pair(device_secret).public_key())
+}
+
+/// Compares a code sent by a client with the one the server expects in constant time
+///
+/// Both are hashed first so neither how much of the code matched nor its length can be timed
+///
+/// # Arguments
+/// * `supplied: &str` - Code sent by the client
+/// * `expected: &str` - Code the server expects
+pub fn codes_match(supplied: &str, expected: &str) -> bool {
+    Sha256::digest(supplied).ct_eq(&Sha256::digest(expected)).into()
+}
+
+/// Makes the signing key of a device, the secret is hashed into the seed so secrets of any length work
+fn key_pair(device_secret: &str) -> Ed25519KeyPair {
+    Ed25519KeyPair::from_seed_unchecked(&Sha256::digest(device_secret)).expect("Any 32 bytes are an Ed25519 seed")
+}
+
+/// Puts together everything the signature covers
+fn signed_bytes(device_id: &str, command: Commands, body: &[u8], timestamp: i64, nonce: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(command.to_string().len() + device_id.len() + 10 + nonce.len() + body.len());
+
+    bytes.extend_from_slice(command.to_string().as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(device_id.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&timestamp.to_be_bytes());
+    bytes.extend_from_slice(nonce);
+    bytes.extend_from_slice(body);
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "device secret";
+
+    fn signed(body: &[u8]) -> Auth {
+        Auth::sign("device", SECRET, Commands::INPUT, body, 1_700_000_000)
+    }
+
+    fn key() -> String {
+        public_key(SECRET)
+    }
+
+    #[test]
+    fn signatures_made_with_the_secret_verify() {
+        assert!(signed(b"{}").verify(&key(), Commands::INPUT, b"{}"));
+    }
+
+    #[test]
+    fn signatures_made_with_another_secret_are_rejected() {
+        assert!(!signed(b"{}").verify(&public_key("another secret"), Commands::INPUT, b"{}"));
+        assert!(!signed(b"{}").verify("not a key", Commands::INPUT, b"{}"));
+    }
+
+    #[test]
+    fn the_stored_public_key_can_not_sign() {
+        let forged = Auth::sign("device", &key(), Commands::INPUT, b"{}", 1_700_000_000);
+
+        assert!(!forged.verify(&key(), Commands::INPUT, b"{}"));
+    }
+
+    #[test]
+    fn changing_anything_signed_breaks_the_signature() {
+        let auth = signed(b"{}");
+
+        assert!(!auth.verify(&key(), Commands::INPUT, b"{\"a\":1}"));
+        assert!(!auth.verify(&key(), Commands::REMOVE, b"{}"));
+
+        let mut moved = auth.clone();
+        moved.timestamp += 1;
+        assert!(!moved.verify(&key(), Commands::INPUT, b"{}"));
+
+        let mut renamed = auth.clone();
+        renamed.device_id = "other".to_string();
+        assert!(!renamed.verify(&key(), Commands::INPUT, b"{}"));
+
+        let mut renonced = auth.clone();
+        renonced.nonce[0] ^= 1;
+        assert!(!renonced.verify(&key(), Commands::INPUT, b"{}"));
+
+        let mut flipped = auth;
+        flipped.signature[SIGNATURE_LEN - 1] ^= 1;
+        assert!(!flipped.verify(&key(), Commands::INPUT, b"{}"));
+    }
+
+    #[test]
+    fn every_signature_gets_its_own_nonce() {
+        assert_ne!(signed(b"{}").nonce, signed(b"{}").nonce);
+    }
+
+    #[test]
+    fn only_the_same_code_matches() {
+        assert!(codes_match("enroll-me", "enroll-me"));
+        assert!(!codes_match("enroll-mf", "enroll-me"));
+        assert!(!codes_match("enroll", "enroll-me"));
+        assert!(!codes_match("", "enroll-me"));
+    }
+
+    #[test]
+    fn blocks_survive_encoding() {
+        let auth = signed(b"{}");
+
+        let decoded = Auth::decode(&auth.encode()).unwrap();
+
+        assert_eq!(decoded.device_id, auth.device_id);
+        assert_eq!(decoded.timestamp, auth.timestamp);
+        assert_eq!(decoded.nonce, auth.nonce);
+        assert_eq!(decoded.signature, auth.signature);
+        assert!(decoded.verify(&key(), Commands::INPUT, b"{}"));
+    }
+
+    #[test]
+    fn truncated_or_padded_blocks_are_rejected() {
+        let encoded = signed(b"{}").encode();
+
+        assert!(matches!(Auth::decode(&[]), Err(FrameError::Short { .. })));
+        assert!(matches!(Auth::decode(&encoded[..encoded.len() - 1]), Err(FrameError::Short { .. })));
+        assert!(matches!(Auth::decode(&[encoded.as_slice(), &[0]].concat()), Err(FrameError::Short { .. })));
+    }
+}
@@ -1,77 +1,173 @@
 use std::{
     io::{Read, Write},
-    net::TcpStream,
+    net::{IpAddr, SocketAddr, TcpStream},
 };
 
-use base64::{Engine, engine::general_purpose};
-use serde_json::Value;
+use rustls::{ClientConnection, StreamOwned};
+use serde_json::{json, Value};
 
-use crate::{json_handler::read_client_config_string, socket_handling::command_type::Commands};
+use crate::{json_handler::{read_client_config_string, read_client_config_string_or}, socket_handling::{auth::Auth, command_type::Commands, frame::{self, Frame, FrameError}, response::{self, RlsdError}, tls}, stats_handling::stats_getter::get_unix_timestamp};
+
+/// Any stream the client can send requests over, plaintext or TLS
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
 
 /// Sends data to the socket
-pub fn send(command: Commands, data: Value) -> String {
+///
+/// # Arguments
+/// * `command: Commands` - Command to run on the server
+/// * `data: Value` - Payload of the command
+///
+/// # Returns
+/// * `Ok(Value)` - The server's reply envelope, the command succeeded
+/// * `Err(RlsdError)` - The server couldn't be reached or refused the command
+pub fn send(command: Commands, data: Value) -> Result<Value, RlsdError> {
     let server_addr = read_client_config_string("serverAddr");
 
-    let mut connection = match connect(&server_addr) {
-        Ok(c) => c,
-        Err(e) => {eprintln!("{e}"); return "Error connecting...".to_string()}
-    };
+    let mut connection = connect(&server_addr).map_err(RlsdError::Connection)?;
 
-    let string_json = data.to_string();
+    let mut frame = Frame::new(command, data.to_string().into_bytes());
 
-    // All of these are made to preserve temporary values
-    let encoded_data = general_purpose::STANDARD.encode(string_json);
+    // Sign the request if the server issued this device a secret
+    let device_secret = read_client_config_string_or("deviceSecret", "");
 
-    let formatted = format!("{}{encoded_data}", command.to_string());
+    if !device_secret.is_empty() {
+        let device_id = read_client_config_string("deviceID");
 
-    let buf = formatted.as_bytes();
+        frame.auth = Some(Auth::sign(&device_id, &device_secret, command, &frame.body, get_unix_timestamp()));
+    }
 
-    // Writes the data to the stream from the buffer
-    connection.write_all(buf).unwrap();
+    response::parse(&request(connection.as_mut(), frame)?)
+}
 
-    let mut buf = [0; 1024];
+/// Gets a device id and secret from the server
+///
+/// # Arguments
+/// * `server_addr: &str` - Address of the server
+/// * `cert_fingerprint: &str` - Fingerprint pinned for the server, empty to connect without TLS
+/// * `enrollment_code: &str` - Code the server asks new devices for, can be empty
+///
+/// # Returns
+/// * `Ok((String, String, bool))` - The device id, its secret and if it's waiting for approval
+/// * `Err(RlsdError)` - The server couldn't be reached or refused the device
+pub fn setup(server_addr: &str, cert_fingerprint: &str, enrollment_code: &str) -> Result<(String, String, bool), RlsdError> {
+    // Used to get the device id
+    let mut connection = connect_with(server_addr, cert_fingerprint).map_err(RlsdError::Connection)?;
 
-    connection.read(&mut buf).unwrap();
+    let payload = json!({
+        "hostname": whoami::fallible::hostname().unwrap_or_else(|_| "N/A".to_string()),
+        "enrollmentCode": enrollment_code
+    });
 
-    String::from_utf8_lossy(&buf)
-        .trim()
-        .to_string()
-        .chars()
-        .filter(|&c| c != '\u{0000}')
-        .collect::<String>()
+    let reply = response::parse(&request(connection.as_mut(), Frame::new(Commands::SETUP, payload.to_string().into_bytes()))?)?;
+
+    let data = &reply["data"];
+
+    Ok((
+        data["deviceID"].as_str().unwrap_or_default().to_string(),
+        data["deviceSecret"].as_str().unwrap_or_default().to_string(),
+        data["pending"].as_bool().unwrap_or(false),
+    ))
 }
 
-pub fn setup(server_addr: &str) -> String {
-    // Used to get the device id
-    let mut connection = match connect(server_addr) {
-        Ok(c) => c,
-        Err(e) => {eprintln!("{e}"); return "Error".to_string();} 
+/// Connects to the server with TLS without checking its certificate and returns the certificate's fingerprint
+///
+/// Only used during setup so the user can confirm the fingerprint before it is pinned
+///
+/// # Arguments
+/// * `server_addr: &str` - Address of the server
+///
+/// # Returns
+/// * `Ok(String)` - Fingerprint of the server's certificate
+/// * `Err(String)` - The server couldn't be reached or doesn't support TLS
+pub fn fetch_fingerprint(server_addr: &str) -> Result<String, String> {
+    let mut socket = TcpStream::connect(server_addr).map_err(|e| e.to_string())?;
+
+    let mut connection = ClientConnection::new(tls::client_config(None)?, tls::server_name())
+        .map_err(|e| e.to_string())?;
+
+    while connection.is_handshaking() {
+        connection.complete_io(&mut socket).map_err(|e| e.to_string())?;
+    }
+
+    let cert_fingerprint = match connection.peer_certificates().and_then(|certs| certs.first()) {
+        Some(cert) => tls::fingerprint(cert),
+        None => return Err("The server didn't send a certificate".to_string()),
     };
 
-    connection
-        .write_all(Commands::SETUP.to_string().as_bytes())
-        .unwrap();
+    // Close the connection cleanly, no request is sent
+    connection.send_close_notify();
+    let _ = connection.complete_io(&mut socket);
 
-    let mut buf = [0; 1024];
+    Ok(cert_fingerprint)
+}
 
-    connection.read(&mut buf).unwrap();
+/// Writes the request frame and waits for the server's reply
+///
+/// # Arguments
+/// * `connection: &mut dyn Stream` - Stream connected to the server
+/// * `frame: Frame` - Request to send
+///
+/// # Returns
+/// * `Ok(String)` - Body of the reply
+/// * `Err(RlsdError)` - The request couldn't be sent or the reply couldn't be read
+fn request(connection: &mut dyn Stream, frame: Frame) -> Result<String, RlsdError> {
+    frame::write_frame(connection, &frame).map_err(|e| RlsdError::Protocol(format!("couldn't send the request: {e}")))?;
+
+    match frame::read_frame(connection) {
+        Ok(reply) => Ok(reply.body_string().trim().to_string()),
+        Err(FrameError::Short { received: 0, .. }) => Err(RlsdError::Protocol("the server closed the connection without replying".to_string())),
+        Err(e) => Err(RlsdError::Protocol(format!("couldn't read the reply: {e}"))),
+    }
+}
 
-    let device_id = String::from_utf8_lossy(&buf)
-        .trim()
-        .to_string()
-        .chars()
-        .filter(|&c| c != '\u{0000}')
-        .collect::<String>();
+/// Adds the port to an address if it doesn't have one, IPv6 addresses are wrapped in brackets
+///
+/// # Arguments
+/// * `addr: &str` - IP address or hostname, optionally with a port such as `10.0.0.2:6000` or `[fd00::2]:6000`
+/// * `default_port: u16` - Port used if the address doesn't have one
+///
+/// # Returns
+/// `String` - Address that can be connected to or bound, such as `10.0.0.2:51347` or `[::]:51347`
+pub fn normalize_addr(addr: &str, default_port: u16) -> String {
+    let addr = addr.trim();
+
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr.to_string();
+    }
 
-    device_id
+    match addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => format!("{ip}:{default_port}"),
+        Ok(IpAddr::V6(ip)) => format!("[{ip}]:{default_port}"),
+        // Hostnames
+        Err(_) if addr.contains(':') => addr.to_string(),
+        Err(_) => format!("{addr}:{default_port}"),
+    }
 }
 
-pub fn connect(server_addr: &str) -> Result<TcpStream, String> {    
-    match TcpStream::connect(format!("{}", server_addr)) {
-        Ok(c) => Ok(c),
-        Err(e) => {
-            eprintln!("{e}");
-            return Err("Error".to_string());
-        }
+/// Connects to the server using the fingerprint pinned in the client config
+///
+/// # Arguments
+/// * `server_addr: &str` - Address of the server
+pub fn connect(server_addr: &str) -> Result<Box<dyn Stream>, String> {
+    connect_with(server_addr, &read_client_config_string_or("certFingerprint", ""))
+}
+
+/// Connects to the server, with TLS if a fingerprint is supplied
+///
+/// # Arguments
+/// * `server_addr: &str` - Address of the server
+/// * `cert_fingerprint: &str` - Fingerprint the server's certificate has to match, empty for plaintext
+pub fn connect_with(server_addr: &str, cert_fingerprint: &str) -> Result<Box<dyn Stream>, String> {    
+    let socket = TcpStream::connect(server_addr).map_err(|e| format!("{server_addr}: {e}"))?;
+
+    if cert_fingerprint.is_empty() {
+        return Ok(Box::new(socket));
     }
-}
\ No newline at end of file
+
+    let connection = ClientConnection::new(tls::client_config(Some(cert_fingerprint))?, tls::server_name())
+        .map_err(|e| e.to_string())?;
+
+    Ok(Box::new(StreamOwned::new(connection, socket)))
+}
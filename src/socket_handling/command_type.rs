@@ -1,8 +1,12 @@
 /// Commands to be sent over the server
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Commands {
     /// Add data to the current stats
     INPUT,
+    /// Add several samples with their original timestamps, used to replay the client's spool
+    BatchInput,
+    /// Record what the device is, sent at startup and whenever it changes
+    INVENTORY,
     /// Rename device
     RENAME,
     /// Rename a device remotely
@@ -17,6 +21,12 @@ pub enum Commands {
     UpdateServer,
     /// Stop the server
     EXIT,
+    /// List devices waiting for approval
+    PENDING,
+    /// Approve a pending device
+    APPROVE,
+    /// Deny a pending device
+    DENY,
     /// Error, command probably wasn't found
     ERROR,
 }
@@ -25,6 +35,8 @@ impl Commands {
     pub fn to_string<'a>(self) -> &'a str {
         match self {
             Commands::INPUT         => "INPUT!",
+            Commands::BatchInput    => "BATCH_INPUT!",
+            Commands::INVENTORY     => "INVENTORY!",
             Commands::RENAME        => "RENAME!",
             Commands::AdminRename   => "AdminRename!",
             Commands::SETUP         => "SETUP!",
@@ -32,6 +44,9 @@ impl Commands {
             Commands::LIST          => "LIST!",
             Commands::UpdateServer  => "UpdateServer!",
             Commands::EXIT          => "EXIT!",
+            Commands::PENDING       => "PENDING!",
+            Commands::APPROVE       => "APPROVE!",
+            Commands::DENY          => "DENY!",
             Commands::ERROR         => "ERROR!",
         }
     }
@@ -45,6 +60,8 @@ impl CommandTraits for String {
     fn to_command(&self) -> Commands {
         match self.replace("!", "").as_str() {
             "INPUT"         => Commands::INPUT,
+            "BATCH_INPUT"   => Commands::BatchInput,
+            "INVENTORY"     => Commands::INVENTORY,
             "RENAME"        => Commands::RENAME,
             "AdminRename"   => Commands::AdminRename,
             "SETUP"         => Commands::SETUP,
@@ -52,6 +69,9 @@ impl CommandTraits for String {
             "LIST"          => Commands::LIST,
             "UpdateServer"  => Commands::UpdateServer,
             "EXIT"          => Commands::EXIT,
+            "PENDING"       => Commands::PENDING,
+            "APPROVE"       => Commands::APPROVE,
+            "DENY"          => Commands::DENY,
             _               => Commands::ERROR,
         }
     }
@@ -61,6 +81,8 @@ impl CommandTraits for str {
     fn to_command(&self) -> Commands {
         match self.replace("!", "").as_str() {
             "INPUT"         => Commands::INPUT,
+            "BATCH_INPUT"   => Commands::BatchInput,
+            "INVENTORY"     => Commands::INVENTORY,
             "RENAME"        => Commands::RENAME,
             "AdminRename"   => Commands::AdminRename,
             "SETUP"         => Commands::SETUP,
@@ -68,6 +90,9 @@ impl CommandTraits for str {
             "LIST"          => Commands::LIST,
             "UpdateServer"  => Commands::UpdateServer,
             "EXIT"          => Commands::EXIT,
+            "PENDING"       => Commands::PENDING,
+            "APPROVE"       => Commands::APPROVE,
+            "DENY"          => Commands::DENY,
             _               => Commands::ERROR,
         }
     }
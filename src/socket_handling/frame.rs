@@ -0,0 +1,664 @@
+//! Length-prefixed framing shared by the client and the server
+//!
+//! Every frame is laid out as:
+//!
+//! | Field          | Size               |
+//! |----------------|--------------------|
+//! | Magic `RLSD`   | 4 bytes            |
+//! | Version        | 1 byte             |
+//! | Command length | 1 byte             |
+//! | Command        | command length     |
+//! | Auth length    | 2 bytes big-endian |
+//! | Auth           | auth length        |
+//! | Body length    | 4 bytes big-endian |
+//! | Body           | body length        |
+//!
+//! The auth fields were added in version 2, version 1 frames are still read without them
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use base64::{engine::general_purpose, Engine};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::socket_handling::{auth::Auth, command_type::{CommandTraits, Commands}};
+
+/// Bytes every frame starts with
+pub const MAGIC: [u8; 4] = *b"RLSD";
+
+/// Version of the framing layer written by this build
+pub const VERSION: u8 = 2;
+
+/// Oldest version of the framing layer that can still be read
+pub const MIN_VERSION: u8 = 1;
+
+/// Largest auth block a frame is allowed to carry
+pub const MAX_AUTH_LEN: u16 = 512;
+
+/// Largest body a frame is allowed to carry (16 MiB)
+pub const MAX_BODY_LEN: u32 = 16 * 1024 * 1024;
+
+/// Largest request accepted from a legacy `COMMAND!base64` client (64 KiB)
+pub const MAX_LEGACY_LEN: usize = 64 * 1024;
+
+/// A single message sent over the socket
+pub struct Frame {
+    /// Command the frame is carrying
+    pub command: Commands,
+    /// Raw body of the frame, JSON for every command that has a payload
+    pub body: Vec<u8>,
+    /// Signature of the request, `None` for unsigned requests and replies
+    pub auth: Option<Auth>,
+    /// `true` if the frame was read from a legacy `COMMAND!base64` client
+    pub legacy: bool,
+}
+
+/// Errors that can happen while reading or writing a frame
+#[derive(Debug)]
+pub enum FrameError {
+    /// The connection closed before the whole frame was received
+    Short { expected: usize, received: usize },
+    /// The frame declared a body larger than `MAX_BODY_LEN`
+    Oversized { len: usize, max: usize },
+    /// The frame didn't start with `MAGIC`
+    BadMagic([u8; 4]),
+    /// The frame was written by an unsupported version of the protocol
+    UnsupportedVersion(u8),
+    /// A legacy `COMMAND!base64` request couldn't be decoded
+    InvalidLegacy(String),
+    /// The underlying stream failed
+    Io(io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Short { expected, received } => write!(f, "Short frame: expected {expected} bytes but only received {received}"),
+            Self::Oversized { len, max }       => write!(f, "Oversized frame: {len} bytes is larger than the {max} byte limit"),
+            Self::BadMagic(magic)              => write!(f, "Bad frame magic: {magic:?}"),
+            Self::UnsupportedVersion(version)  => write!(f, "Unsupported protocol version: {version}"),
+            Self::InvalidLegacy(e)             => write!(f, "Invalid legacy request: {e}"),
+            Self::Io(e)                        => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+impl Frame {
+    /// Makes a new `Frame` instance
+    ///
+    /// # Arguments
+    /// * `command: Commands` - Command the frame is carrying
+    /// * `body: Vec<u8>` - Body of the frame
+    pub fn new(command: Commands, body: Vec<u8>) -> Frame {
+        Frame {
+            command,
+            body,
+            auth: None,
+            legacy: false,
+        }
+    }
+
+    /// Encodes the frame into the bytes sent over the socket
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The encoded frame
+    /// * `Err(FrameError)` - The body is larger than `MAX_BODY_LEN`
+    pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        if self.body.len() > MAX_BODY_LEN as usize {
+            return Err(FrameError::Oversized { len: self.body.len(), max: MAX_BODY_LEN as usize });
+        }
+
+        let command = command_name(self.command);
+
+        let auth = self.auth.as_ref().map(Auth::encode).unwrap_or_default();
+
+        let mut buf = Vec::with_capacity(MAGIC.len() + 8 + command.len() + auth.len() + self.body.len());
+
+        buf.extend_from_slice(&MAGIC);
+        buf.push(VERSION);
+        buf.push(command.len() as u8);
+        buf.extend_from_slice(command.as_bytes());
+        buf.extend_from_slice(&(auth.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&auth);
+        buf.extend_from_slice(&(self.body.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.body);
+
+        Ok(buf)
+    }
+
+    /// Returns the body as a `String`, invalid UTF-8 is replaced
+    pub fn body_string(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+/// Writes a frame to the stream
+///
+/// # Arguments
+/// * `writer: &mut W` - Stream to write to
+/// * `frame: &Frame` - Frame to write
+pub fn write_frame<W: Write + ?Sized>(writer: &mut W, frame: &Frame) -> Result<(), FrameError> {
+    writer.write_all(&frame.encode()?)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a whole frame from the stream, looping until every byte has arrived
+///
+/// # Arguments
+/// * `reader: &mut R` - Stream to read from
+///
+/// # Returns
+/// * `Ok(Frame)` - The frame that was read
+/// * `Err(FrameError)` - The frame was short, oversized or malformed
+pub fn read_frame<R: Read + ?Sized>(reader: &mut R) -> Result<Frame, FrameError> {
+    let mut magic = [0; 4];
+    read_full(reader, &mut magic)?;
+
+    if magic != MAGIC {
+        return Err(FrameError::BadMagic(magic));
+    }
+
+    read_after_magic(reader)
+}
+
+/// Reads a request from a client, accepting both framed and legacy `COMMAND!base64` requests
+///
+/// # Arguments
+/// * `reader: &mut R` - Stream to read from
+///
+/// # Returns
+/// * `Ok(Frame)` - The request, `legacy` is set if it came from an old client
+/// * `Err(FrameError)` - The request couldn't be read
+pub async fn read_request<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame, FrameError> {
+    let mut magic = [0; 4];
+    let received = read_some_async(reader, &mut magic).await?;
+
+    // The client connected and left without sending anything
+    if received == 0 {
+        return Err(FrameError::Short { expected: magic.len(), received });
+    }
+
+    if received < magic.len() || magic != MAGIC {
+        return read_legacy(reader, magic[..received].to_vec()).await;
+    }
+
+    let mut buf = Vec::new();
+
+    loop {
+        match parse_frame(&mut buf)? {
+            Parsed::Frame(frame) => return Ok(frame),
+            Parsed::Needs(more) => {
+                let start = buf.len();
+                buf.resize(start + more, 0);
+                read_full_async(reader, &mut buf[start..]).await?;
+            }
+        }
+    }
+}
+
+/// Writes a reply to the client in the same format as its request
+///
+/// # Arguments
+/// * `writer: &mut W` - Stream to write to
+/// * `command: Commands` - Command the client sent
+/// * `legacy: bool` - If the client is a legacy client, the message is sent as raw bytes
+/// * `msg: &str` - Message to send
+pub async fn write_reply<W: AsyncWrite + Unpin>(writer: &mut W, command: Commands, legacy: bool, msg: &str) -> Result<(), FrameError> {
+    if legacy {
+        writer.write_all(msg.as_bytes()).await?;
+    } else {
+        writer.write_all(&Frame::new(command, msg.as_bytes().to_vec()).encode()?).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Returns the name of the command as it is sent in the frame header
+fn command_name(command: Commands) -> &'static str {
+    command.to_string().trim_end_matches('!')
+}
+
+/// Reads the rest of a frame once the magic has been checked
+fn read_after_magic<R: Read + ?Sized>(reader: &mut R) -> Result<Frame, FrameError> {
+    let mut buf = Vec::new();
+
+    loop {
+        match parse_frame(&mut buf)? {
+            Parsed::Frame(frame) => return Ok(frame),
+            Parsed::Needs(more) => {
+                let start = buf.len();
+                buf.resize(start + more, 0);
+                read_full(reader, &mut buf[start..])?;
+            }
+        }
+    }
+}
+
+/// What `parse_frame` made of the bytes read so far
+enum Parsed {
+    /// The whole frame has arrived
+    Frame(Frame),
+    /// This many more bytes have to be read before the next length can be checked
+    Needs(usize),
+}
+
+/// Parses the bytes that follow the magic, used by both the sync and the async readers
+///
+/// Every length is checked as soon as it has arrived so nothing is allocated for a bad header,
+/// the body is moved out of the buffer once the frame is complete
+///
+/// # Arguments
+/// * `buf: &mut Vec<u8>` - Bytes read after the magic so far
+fn parse_frame(buf: &mut Vec<u8>) -> Result<Parsed, FrameError> {
+    let needs = |buf: &[u8], end: usize| (buf.len() < end).then(|| Parsed::Needs(end - buf.len()));
+
+    let mut end = 2;
+    if let Some(needs) = needs(buf, end) {
+        return Ok(needs);
+    }
+
+    let (version, command_len) = check_header([buf[0], buf[1]])?;
+
+    let command = end..end + command_len;
+    end += command_len;
+
+    let mut auth = end..end;
+
+    if version >= 2 {
+        end += 2;
+        if let Some(needs) = needs(buf, end) {
+            return Ok(needs);
+        }
+
+        let auth_len = check_auth_len([buf[end - 2], buf[end - 1]])?;
+
+        auth = end..end + auth_len;
+        end += auth_len;
+    }
+
+    end += 4;
+    if let Some(needs) = needs(buf, end) {
+        return Ok(needs);
+    }
+
+    let mut body_len = [0; 4];
+    body_len.copy_from_slice(&buf[end - 4..end]);
+
+    let body_start = end;
+    end += check_body_len(body_len)?;
+    if let Some(needs) = needs(buf, end) {
+        return Ok(needs);
+    }
+
+    let body = buf.split_off(body_start);
+
+    Ok(Parsed::Frame(Frame {
+        command: String::from_utf8_lossy(&buf[command]).to_command(),
+        body,
+        auth: decode_auth(&buf[auth])?,
+        legacy: false,
+    }))
+}
+
+/// Checks the version and returns it along with the length of the command
+///
+/// # Arguments
+/// * `header: [u8; 2]` - Version byte and command length byte
+fn check_header(header: [u8; 2]) -> Result<(u8, usize), FrameError> {
+    let [version, command_len] = header;
+
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(FrameError::UnsupportedVersion(version));
+    }
+
+    Ok((version, command_len as usize))
+}
+
+/// Checks the declared auth length
+///
+/// # Arguments
+/// * `auth_len: [u8; 2]` - Big-endian length of the auth block
+fn check_auth_len(auth_len: [u8; 2]) -> Result<usize, FrameError> {
+    let auth_len = u16::from_be_bytes(auth_len);
+
+    if auth_len > MAX_AUTH_LEN {
+        return Err(FrameError::Oversized { len: auth_len as usize, max: MAX_AUTH_LEN as usize });
+    }
+
+    Ok(auth_len as usize)
+}
+
+/// Decodes the auth block, an empty block means the frame isn't signed
+fn decode_auth(auth: &[u8]) -> Result<Option<Auth>, FrameError> {
+    if auth.is_empty() {
+        return Ok(None);
+    }
+
+    Auth::decode(auth).map(Some)
+}
+
+/// Checks the declared body length before anything is allocated so a bad header can't exhaust memory
+///
+/// # Arguments
+/// * `body_len: [u8; 4]` - Big-endian length of the body
+fn check_body_len(body_len: [u8; 4]) -> Result<usize, FrameError> {
+    let body_len = u32::from_be_bytes(body_len);
+
+    if body_len > MAX_BODY_LEN {
+        return Err(FrameError::Oversized { len: body_len as usize, max: MAX_BODY_LEN as usize });
+    }
+
+    Ok(body_len as usize)
+}
+
+/// Tries to decode a legacy request
+///
+/// # Returns
+/// * `Ok(Some(Frame))` - The request is complete
+/// * `Ok(None)` - More bytes are needed
+/// * `Err(FrameError)` - The request isn't a legacy request
+fn decode_legacy(buf: &[u8]) -> Result<Option<Frame>, FrameError> {
+    let raw_string = String::from_utf8_lossy(buf);
+
+    // Finds where the ! is in the msg
+    let command_ending = match raw_string.find('!') {
+        Some(i) => i,
+        None if buf.len() > 32 => return Err(FrameError::InvalidLegacy("no command found".to_string())),
+        None => return Ok(None),
+    };
+
+    let (command, encoded_data) = raw_string.split_at(command_ending + 1);
+
+    let command = command.to_command();
+
+    // SETUP doesn't carry a payload
+    if encoded_data.trim().is_empty() {
+        return Ok(if command == Commands::SETUP { Some(Frame { command, body: Vec::new(), auth: None, legacy: true }) } else { None });
+    }
+
+    // The payload is only complete once it decodes to valid JSON
+    match general_purpose::STANDARD.decode(encoded_data.trim()) {
+        Ok(bytes) if serde_json::from_slice::<Value>(&bytes).is_ok() => Ok(Some(Frame { command, body: bytes, auth: None, legacy: true })),
+        _ => Ok(None),
+    }
+}
+
+/// Fills the buffer from the stream
+///
+/// # Returns
+/// * `Ok(())` - The buffer was filled
+/// * `Err(FrameError::Short)` - The stream ended first
+fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<(), FrameError> {
+    let received = read_some(reader, buf)?;
+
+    if received < buf.len() {
+        return Err(FrameError::Short { expected: buf.len(), received });
+    }
+
+    Ok(())
+}
+
+/// Reads into the buffer until it is full or the stream ends
+///
+/// # Returns
+/// * `Ok(usize)` - Number of bytes read
+fn read_some<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize, FrameError> {
+    let mut received = 0;
+
+    while received < buf.len() {
+        match reader.read(&mut buf[received..]) {
+            Ok(0) => break,
+            Ok(n) => received += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(FrameError::Io(e)),
+        }
+    }
+
+    Ok(received)
+}
+
+/// Reads a legacy `COMMAND!base64` request
+///
+/// Legacy clients don't send a length, so this keeps reading until the payload decodes,
+/// the client stops sending or `MAX_LEGACY_LEN` is reached
+///
+/// # Arguments
+/// * `reader: &mut R` - Stream to read from
+/// * `buf: Vec<u8>` - Bytes that were already read while checking for the magic
+async fn read_legacy<R: AsyncRead + Unpin>(reader: &mut R, mut buf: Vec<u8>) -> Result<Frame, FrameError> {
+    let mut chunk = [0; 1024];
+
+    loop {
+        if let Some(frame) = decode_legacy(&buf)? {
+            return Ok(frame);
+        }
+
+        if buf.len() >= MAX_LEGACY_LEN {
+            return Err(FrameError::Oversized { len: buf.len(), max: MAX_LEGACY_LEN });
+        }
+
+        let received = reader.read(&mut chunk).await?;
+
+        if received == 0 {
+            return match decode_legacy(&buf)? {
+                Some(frame) => Ok(frame),
+                None => Err(FrameError::InvalidLegacy("connection closed before the payload was complete".to_string())),
+            };
+        }
+
+        buf.extend_from_slice(&chunk[..received]);
+    }
+}
+
+/// Async version of `read_full`
+async fn read_full_async<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<(), FrameError> {
+    let received = read_some_async(reader, buf).await?;
+
+    if received < buf.len() {
+        return Err(FrameError::Short { expected: buf.len(), received });
+    }
+
+    Ok(())
+}
+
+/// Async version of `read_some`
+async fn read_some_async<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<usize, FrameError> {
+    let mut received = 0;
+
+    while received < buf.len() {
+        match reader.read(&mut buf[received..]).await {
+            Ok(0) => break,
+            Ok(n) => received += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(FrameError::Io(e)),
+        }
+    }
+
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket_handling::auth;
+
+    /// Encodes a frame by hand so the header can be anything
+    fn raw_frame(version: u8, command: &str, auth: &[u8], body_len: u32, body: &[u8]) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+
+        buf.push(version);
+        buf.push(command.len() as u8);
+        buf.extend_from_slice(command.as_bytes());
+
+        if version >= 2 {
+            buf.extend_from_slice(&(auth.len() as u16).to_be_bytes());
+            buf.extend_from_slice(auth);
+        }
+
+        buf.extend_from_slice(&body_len.to_be_bytes());
+        buf.extend_from_slice(body);
+
+        buf
+    }
+
+    fn signed_frame() -> Frame {
+        let mut frame = Frame::new(Commands::BatchInput, br#"{"deviceID":"device"}"#.to_vec());
+        frame.auth = Some(Auth::sign("device", "secret", frame.command, &frame.body, 1_700_000_000));
+        frame
+    }
+
+    async fn request(bytes: &[u8]) -> Result<Frame, FrameError> {
+        read_request(&mut &bytes[..]).await
+    }
+
+    #[test]
+    fn frames_survive_encoding() {
+        let frame = signed_frame();
+
+        let read = read_frame(&mut frame.encode().unwrap().as_slice()).unwrap();
+
+        assert_eq!(read.command, Commands::BatchInput);
+        assert_eq!(read.body, frame.body);
+        assert!(!read.legacy);
+        assert!(read.auth.unwrap().verify(&auth::public_key("secret"), Commands::BatchInput, &frame.body));
+
+        let unsigned = Frame::new(Commands::LIST, Vec::new());
+
+        let read = read_frame(&mut unsigned.encode().unwrap().as_slice()).unwrap();
+
+        assert_eq!(read.command, Commands::LIST);
+        assert!(read.body.is_empty() && read.auth.is_none());
+    }
+
+    #[tokio::test]
+    async fn requests_survive_encoding() {
+        let frame = signed_frame();
+
+        let read = request(&frame.encode().unwrap()).await.unwrap();
+
+        assert_eq!(read.command, Commands::BatchInput);
+        assert_eq!(read.body, frame.body);
+        assert!(!read.legacy && read.auth.is_some());
+    }
+
+    #[tokio::test]
+    async fn version_1_frames_are_read_without_auth() {
+        let bytes = raw_frame(1, "INPUT", &[], 2, b"{}");
+
+        let read = read_frame(&mut bytes.as_slice()).unwrap();
+        assert_eq!((read.command, read.body.as_slice()), (Commands::INPUT, &b"{}"[..]));
+        assert!(read.auth.is_none());
+
+        let read = request(&bytes).await.unwrap();
+        assert_eq!((read.command, read.body.as_slice()), (Commands::INPUT, &b"{}"[..]));
+    }
+
+    #[tokio::test]
+    async fn short_frames_are_rejected() {
+        let bytes = signed_frame().encode().unwrap();
+
+        // Every cut after the magic leaves a frame that can't be finished
+        for len in MAGIC.len()..bytes.len() {
+            assert!(matches!(read_frame(&mut &bytes[..len]), Err(FrameError::Short { .. })), "{len} bytes");
+            assert!(matches!(request(&bytes[..len]).await, Err(FrameError::Short { .. })), "{len} bytes");
+        }
+
+        assert!(matches!(read_frame(&mut &bytes[..2]), Err(FrameError::Short { expected: 4, received: 2 })));
+        assert!(matches!(request(&[]).await, Err(FrameError::Short { received: 0, .. })));
+    }
+
+    #[tokio::test]
+    async fn oversized_length_prefixes_are_rejected_before_reading_the_body() {
+        let body = raw_frame(VERSION, "INPUT", &[], MAX_BODY_LEN + 1, &[]);
+
+        assert!(matches!(read_frame(&mut body.as_slice()), Err(FrameError::Oversized { .. })));
+        assert!(matches!(request(&body).await, Err(FrameError::Oversized { .. })));
+
+        let mut auth = raw_frame(VERSION, "INPUT", &[], 0, &[]);
+        let auth_len = MAGIC.len() + 2 + "INPUT".len();
+        auth[auth_len..auth_len + 2].copy_from_slice(&(MAX_AUTH_LEN + 1).to_be_bytes());
+
+        assert!(matches!(read_frame(&mut auth.as_slice()), Err(FrameError::Oversized { .. })));
+        assert!(matches!(request(&auth).await, Err(FrameError::Oversized { .. })));
+    }
+
+    #[test]
+    fn oversized_bodies_are_not_encoded() {
+        let frame = Frame::new(Commands::INPUT, vec![0; MAX_BODY_LEN as usize + 1]);
+
+        assert!(matches!(frame.encode(), Err(FrameError::Oversized { .. })));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let mut bytes = signed_frame().encode().unwrap();
+        bytes[0] = b'X';
+
+        assert!(matches!(read_frame(&mut bytes.as_slice()), Err(FrameError::BadMagic(magic)) if magic == *b"XLSD"));
+    }
+
+    #[tokio::test]
+    async fn unsupported_versions_are_rejected() {
+        for version in [0, VERSION + 1, u8::MAX] {
+            let bytes = raw_frame(version, "INPUT", &[], 2, b"{}");
+
+            assert!(matches!(read_frame(&mut bytes.as_slice()), Err(FrameError::UnsupportedVersion(v)) if v == version));
+            assert!(matches!(request(&bytes).await, Err(FrameError::UnsupportedVersion(v)) if v == version));
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_auth_blocks_are_rejected() {
+        let bytes = raw_frame(VERSION, "INPUT", &[5, b'a'], 2, b"{}");
+
+        assert!(matches!(read_frame(&mut bytes.as_slice()), Err(FrameError::Short { .. })));
+        assert!(matches!(request(&bytes).await, Err(FrameError::Short { .. })));
+    }
+
+    #[tokio::test]
+    async fn legacy_requests_are_decoded() {
+        let payload = general_purpose::STANDARD.encode(br#"{"deviceID":"device"}"#);
+
+        let read = request(format!("INPUT!{payload}").as_bytes()).await.unwrap();
+
+        assert_eq!(read.command, Commands::INPUT);
+        assert_eq!(read.body, br#"{"deviceID":"device"}"#);
+        assert!(read.legacy && read.auth.is_none());
+
+        let read = request(b"SETUP!").await.unwrap();
+
+        assert_eq!(read.command, Commands::SETUP);
+        assert!(read.legacy && read.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn malformed_legacy_requests_are_rejected() {
+        // The payload never decodes to JSON before the client stops sending
+        assert!(matches!(request(b"INPUT!bm90IGpzb24=").await, Err(FrameError::InvalidLegacy(_))));
+
+        // Too short to be a frame and not a legacy command either
+        assert!(matches!(request(b"RL").await, Err(FrameError::InvalidLegacy(_))));
+
+        // There's no command in the first bytes
+        assert!(matches!(request(&[b'A'; 64]).await, Err(FrameError::InvalidLegacy(_))));
+
+        // The payload keeps going past the limit
+        let endless = [b"INPUT!".as_slice(), &vec![b'A'; MAX_LEGACY_LEN + 1024]].concat();
+
+        assert!(matches!(request(&endless).await, Err(FrameError::Oversized { max: MAX_LEGACY_LEN, .. })));
+    }
+}
@@ -0,0 +1,163 @@
+//! Replies sent by the server
+//!
+//! Every command gets exactly one reply, a JSON envelope such as
+//! `{"ok": false, "code": 403, "message": "You're not allowed to do that", "data": null}`.
+//! Legacy clients only get the message
+use std::fmt;
+
+use serde_json::{json, Value};
+
+/// Outcome of a request, the codes follow their HTTP equivalents
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Status {
+    /// The command succeeded
+    Ok,
+    /// The payload was missing or invalid
+    BadRequest,
+    /// The request wasn't signed by a registered device
+    Unauthorized,
+    /// The device isn't allowed to run the command
+    Forbidden,
+    /// The device or row the command refers to doesn't exist
+    NotFound,
+    /// The device is sending too often
+    TooManyRequests,
+    /// The server failed to run the command
+    ServerError,
+}
+
+impl Status {
+    /// Returns the numeric code sent to the client
+    pub fn code(self) -> u16 {
+        match self {
+            Status::Ok              => 200,
+            Status::BadRequest      => 400,
+            Status::Unauthorized    => 401,
+            Status::Forbidden       => 403,
+            Status::NotFound        => 404,
+            Status::TooManyRequests => 429,
+            Status::ServerError     => 500,
+        }
+    }
+}
+
+/// Reply to a single request
+pub struct Response {
+    /// Outcome of the request
+    pub status: Status,
+    /// Human readable description of the outcome
+    pub message: String,
+    /// Anything else the command returns, `null` if there's nothing
+    pub data: Value,
+}
+
+impl Response {
+    /// Makes a successful reply
+    ///
+    /// # Arguments
+    /// * `message: &str` - Description of what was done
+    pub fn ok(message: &str) -> Response {
+        Response::new(Status::Ok, message)
+    }
+
+    /// Makes a reply
+    ///
+    /// # Arguments
+    /// * `status: Status` - Outcome of the request
+    /// * `message: &str` - Description of the outcome
+    pub fn new(status: Status, message: &str) -> Response {
+        Response {
+            status,
+            message: message.to_string(),
+            data: Value::Null,
+        }
+    }
+
+    /// Attaches data to the reply
+    ///
+    /// # Arguments
+    /// * `data: Value` - Data returned by the command
+    pub fn with_data(mut self, data: Value) -> Response {
+        self.data = data;
+        self
+    }
+
+    /// Convert a `Response` instance to a `Value` instance
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ok": self.status == Status::Ok,
+            "code": self.status.code(),
+            "message": self.message,
+            "data": self.data
+        })
+    }
+}
+
+/// Errors returned to the client and CLI when a request doesn't succeed
+#[derive(Debug)]
+pub enum RlsdError {
+    /// The server couldn't be reached
+    Connection(String),
+    /// The request couldn't be sent or the reply couldn't be read
+    Protocol(String),
+    /// The server rejected the request, `data` holds anything it sent with the error such as when to retry
+    Server { code: u16, message: String, data: Value },
+}
+
+impl RlsdError {
+    /// Returns the exit code the CLI should use for this error
+    ///
+    /// * `2` - The server couldn't be reached
+    /// * `3` - The reply couldn't be read
+    /// * `4` - Authentication failed
+    /// * `5` - The device isn't allowed to do that
+    /// * `6` - Not found
+    /// * `7` - Sending too often
+    /// * `1` - Anything else
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RlsdError::Connection(_) => 2,
+            RlsdError::Protocol(_) => 3,
+            RlsdError::Server { code: 401, .. } => 4,
+            RlsdError::Server { code: 403, .. } => 5,
+            RlsdError::Server { code: 404, .. } => 6,
+            RlsdError::Server { code: 429, .. } => 7,
+            RlsdError::Server { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for RlsdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RlsdError::Connection(e) => write!(f, "Couldn't connect to the server: {e}"),
+            RlsdError::Protocol(e) => write!(f, "Invalid exchange with the server: {e}"),
+            RlsdError::Server { code, message, .. } => write!(f, "The server refused the request ({code}): {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RlsdError {}
+
+/// Parses a reply sent by the server
+///
+/// # Arguments
+/// * `reply: &str` - Body of the reply
+///
+/// # Returns
+/// * `Ok(Value)` - The whole envelope, the command succeeded
+/// * `Err(RlsdError)` - The reply wasn't an envelope or the command failed
+pub fn parse(reply: &str) -> Result<Value, RlsdError> {
+    let envelope: Value = serde_json::from_str(reply)
+        .map_err(|e| RlsdError::Protocol(format!("the reply isn't a JSON envelope ({e}): {reply}")))?;
+
+    if envelope["ok"].as_bool().unwrap_or(false) {
+        return Ok(envelope);
+    }
+
+    Err(RlsdError::Server {
+        code: envelope["code"].as_u64().and_then(|code| u16::try_from(code).ok()).unwrap_or_default(),
+        message: envelope["message"].as_str().unwrap_or_default().to_string(),
+        data: envelope["data"].clone(),
+    })
+}
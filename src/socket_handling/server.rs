@@ -1,89 +1,213 @@
 use std::{
-    collections::HashMap, io::{Read, Write}, net::{TcpListener, TcpStream}, time::Duration
+    collections::HashMap, fs::{self, OpenOptions}, io::Write, net::{IpAddr, SocketAddr}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, SystemTime}
 };
 
-use base64::{Engine, engine::general_purpose};
-use serde_json::Value;
-use sqlx::{Pool, Sqlite};
-use tokio::time::sleep;
+use serde_json::{json, Value};
+use tokio::{io::AsyncWriteExt, net::{TcpListener, TcpStream}, sync::{Mutex, RwLock}, time::{sleep, timeout}};
+use tokio_rustls::TlsAcceptor;
 use whoami::Arch;
 
 use crate::{
-    config::server::ServerConfig as ServerConfig, constants::get_server_config_path, json_handler::{self, write_server_config_all, ToDevice, ToServerConfig}, socket_handling::command_type::{CommandTraits, Commands}, stats_handling::{database, device_info::get_device_id, stats_getter}
+    config::server::{PendingDevice, ServerConfig}, constants::{get_auth_log_path, get_server_config_path, AUTH_WINDOW_SECONDS, INTERVAL_LEEWAY_SECONDS, MAX_BATCH_SAMPLES, MAX_CLOCK_SKEW_SECONDS, MAX_CUSTOM_METRICS, MAX_PENDING_DEVICES, MAX_SAMPLE_AGE_SECONDS, MIN_SAMPLE_INTERVAL_SECONDS, PRUNE_INTERVAL_SECONDS, READ_TIMEOUT_SECONDS, ROLLUP_INTERVAL_SECONDS, WRITE_TIMEOUT_SECONDS}, json_handler::{self, write_server_config_all, ToDevice, ToServerConfig}, socket_handling::{auth::{self, NONCE_LEN}, client, command_type::Commands, frame::{self, Frame, FrameError}, response::{Response, Status}, tls::{self, AsyncStream}}, stats_handling::{custom_metrics::is_valid_metric_name, database::SampleQuery, device_info::{get_device_id, Device, Inventory}, retention, stats_getter, storage::Storage}
 };
 
+/// Reply sent when a device tries to run an admin command
+const NOT_ALLOWED: &str = "You're not allowed to do that";
+
+/// A client's stream along with how replies to it should be encoded
+struct Connection {
+    /// Stream the client is connected to, plaintext or TLS
+    stream: Box<dyn AsyncStream>,
+    /// Command the client sent, echoed back in the reply frame
+    command: Commands,
+    /// If the client sent a legacy `COMMAND!base64` request, replies are sent as raw bytes
+    legacy: bool,
+}
+
 #[derive(Clone)]
 /// Configuration for the socket part of the server
+///
+/// Cloning a `Server` shares its state, every connection is handled on its own task with a clone
 pub struct Server {
-    /// `bool` - Should the server exit
-    pub exit: bool,
-    /// `Pool<Sqlite>` - Database to be used to execute SQL queries
-    pub database: Pool<Sqlite>,
+    /// `Arc<AtomicBool>` - Should the server exit
+    pub exit: Arc<AtomicBool>,
+    /// `Arc<dyn Storage>` - Database to be used to execute SQL queries, it's already safe to share between tasks
+    pub database: Arc<dyn Storage>,
     /// `bool` - Should messages be printed
     pub print: bool,
-    /// `HashMap<String, i64>` - Keeps track of when devices are sending data so it can't be spammed
-    device_times: HashMap<String, i64>,
-    /// `Server` - The server's config as an instance of `Server`
-    config: ServerConfig
+    /// `Vec<String>` - Addresses to listen on instead of `bindAddr` from the config, set from the CLI
+    pub bind_override: Vec<String>,
+    /// `Option<u16>` - Port to use instead of `port` from the config, set from the CLI
+    pub port_override: Option<u16>,
+    /// `Arc<Mutex<HashMap<String, i64>>>` - Keeps track of when devices are sending data so it can't be spammed
+    device_times: Arc<Mutex<HashMap<String, i64>>>,
+    /// `Arc<RwLock<ServerConfig>>` - The server's config, shared between every connection
+    config: Arc<RwLock<ServerConfig>>,
+    /// `Arc<Mutex<Option<SystemTime>>>` - When the config file was last loaded, used to pick up changes made by the CLI
+    config_modified: Arc<Mutex<Option<SystemTime>>>,
+    /// `Arc<Mutex<HashMap<[u8; NONCE_LEN], i64>>>` - Nonces of recently signed requests and their timestamps, used to reject replays
+    nonces: Arc<Mutex<HashMap<[u8; NONCE_LEN], i64>>>,
+    /// `Arc<Mutex<HashMap<String, i64>>>` - Oldest sample each device stored since its rollups were last rebuilt
+    rollups_due: Arc<Mutex<HashMap<String, i64>>>,
+    /// `Option<TlsAcceptor>` - Used to accept TLS connections, `None` until the server starts or if TLS is disabled
+    tls: Option<TlsAcceptor>
 }
 
 impl Server {
     /// Makes a new `Server` instance
     /// 
     /// # Arguments
-    /// * `database: Pool<Sqlite>` - Database to execute SQL queries on
+    /// * `database: Arc<dyn Storage>` - Database to execute SQL queries on
     /// * `print: bool` - Should messages be printed to the console (disable with the TUI)
-    pub fn new(database: Pool<Sqlite>, print: bool) -> Server {
+    pub fn new(database: Arc<dyn Storage>, print: bool) -> Server {
         let config = json_handler::read_json_as_value(&get_server_config_path()).to_server();
 
         Server {
-            exit: false,
-            database: database,
+            config_modified: Arc::new(Mutex::new(config_modified())),
+            ..Server::with_config(database, config, print)
+        }
+    }
+
+    /// Makes a new `Server` instance from a config that was already loaded
+    ///
+    /// # Arguments
+    /// * `database: Arc<dyn Storage>` - Database to execute SQL queries on
+    /// * `config: ServerConfig` - Config to start with
+    /// * `print: bool` - Should messages be printed to the console
+    fn with_config(database: Arc<dyn Storage>, config: ServerConfig, print: bool) -> Server {
+        Server {
+            exit: Arc::new(AtomicBool::new(false)),
+            database,
             print,
-            device_times: HashMap::new(),
-            config
+            bind_override: Vec::new(),
+            port_override: None,
+            device_times: Arc::new(Mutex::new(HashMap::new())),
+            config: Arc::new(RwLock::new(config)),
+            config_modified: Arc::new(Mutex::new(None)),
+            nonces: Arc::new(Mutex::new(HashMap::new())),
+            rollups_due: Arc::new(Mutex::new(HashMap::new())),
+            tls: None
         }
     }
 
     /// Starts the socket
     pub async fn start(&mut self) -> std::io::Result<()> {
-        if self.config.admin_ids.is_empty() && self.print {
+        if self.config.read().await.admin_ids.is_empty() && self.print {
             println!("No admin devices found, please add at least one to allow for server management");
             sleep(Duration::from_secs(1)).await;
         }
 
-        if self.config.registered_device_ids.is_empty() {
-            let ids = database::get_all_device_uids(&self.database).await;
+        {
+            let mut config = self.config.write().await;
+
+            if config.registered_device_ids.is_empty() {
+                let ids = self.database.get_all_device_uids().await;
+
+                for id in ids {
+                    config.registered_device_ids.push(id);
+                }
 
-            for id in ids {
-                self.config.registered_device_ids.push(id);
+                config.first_run = false;
+
+                write_server_config_all(config.to_json());
             }
+        }
+
+        let (tls_enabled, require_tls) = {
+            let config = self.config.read().await;
 
-            self.config.first_run = false;
+            (config.tls_enabled, config.require_tls)
+        };
 
-            write_server_config_all(self.config.to_json());
+        if tls_enabled {
+            match tls::server_acceptor() {
+                Ok((acceptor, fingerprint)) => {
+                    if self.print {
+                        println!("TLS certificate fingerprint: {fingerprint}");
+                    }
+                    self.tls = Some(acceptor);
+                }
+                Err(e) => {
+                    if require_tls {
+                        return Err(std::io::Error::other(format!("TLS is required but couldn't be set up: {e}")));
+                    }
+                    if self.print {
+                        eprintln!("Failed to set up TLS, only plaintext connections will be accepted: {e}");
+                    }
+                }
+            }
+        } else if require_tls {
+            return Err(std::io::Error::other("TLS is required but tlsEnabled is false in the server config"));
         }
 
-        match TcpListener::bind("0.0.0.0:51347") {
-            Ok(listener) => self.handle_connection(listener).await,
-            Err(e) => return Err(e),
+        let (bind_addrs, port) = {
+            let config = self.config.read().await;
+
+            (
+                if self.bind_override.is_empty() { config.bind_addrs.clone() } else { self.bind_override.clone() },
+                self.port_override.unwrap_or(config.port),
+            )
         };
 
+        // Bind every address before accepting so a bad address stops the server straight away
+        let mut listeners = Vec::new();
+
+        for addr in bind_addrs {
+            let addr = client::normalize_addr(&addr, port);
+
+            let listener = TcpListener::bind(&addr).await
+                .map_err(|e| std::io::Error::new(e.kind(), format!("Failed to listen on {addr}: {e}")))?;
+
+            if self.print {
+                println!("Listening on {addr}");
+            }
+
+            listeners.push(listener);
+        }
+
+        *self.rollups_due.lock().await = self.database.get_rollup_starts().await;
+
+        {
+            let server = self.clone();
+
+            tokio::spawn(async move { server.roll_up_loop().await });
+        }
+
+        {
+            let server = self.clone();
+
+            tokio::spawn(async move { server.prune_loop().await });
+        }
+
+        let handles: Vec<_> = listeners.into_iter().map(|listener| {
+            let server = self.clone();
+
+            tokio::spawn(async move { server.handle_connection(listener).await })
+        }).collect();
+
+        for handle in handles {
+            let _ = handle.await;
+        }
+
         Ok(())
     }
 
     /// For every stream, match it;
-    /// * If it's Ok, process it
+    /// * If it's Ok, spawn a task to process it
     /// * If it's Err, print the error
     /// 
     /// # Arguments
     /// * `listener: TcpListener` - Listener for incoming connections
-    async fn handle_connection(&mut self, listener: TcpListener) {
-        for stream in listener.incoming() {
+    async fn handle_connection(&self, listener: TcpListener) {
+        while !self.exit.load(Ordering::Relaxed) {
             // If the incoming traffic is valid then process it, otherwise print an error and continue to the next loop
-            match stream {
-                Ok(stream) => {
-                    self.process_request(stream).await;
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    let server = self.clone();
+
+                    tokio::spawn(async move {
+                        server.process_request(stream, addr).await;
+                    });
                 }
                 Err(e) => {
                     if self.print {
@@ -92,251 +216,666 @@ impl Server {
                     continue;
                 }
             }
+        }
+    }
+
+    /// Rebuilds the rollups of every device that stored samples since the last run, every `ROLLUP_INTERVAL_SECONDS`
+    async fn roll_up_loop(&self) {
+        while !self.exit.load(Ordering::Relaxed) {
+            let due: Vec<(String, i64)> = self.rollups_due.lock().await.drain().collect();
+
+            for (device_id, since) in due {
+                if let Err(e) = self.database.roll_up(&device_id, since).await {
+                    if self.print {
+                        eprintln!("Failed to roll up the samples of {device_id}: {e}");
+                    }
+
+                    // Tried again on the next run
+                    self.mark_for_rollup(&device_id, since).await;
+                }
+            }
+
+            sleep(Duration::from_secs(ROLLUP_INTERVAL_SECONDS)).await;
+        }
+    }
+
+    /// Removes samples and rollups older than their retention every `PRUNE_INTERVAL_SECONDS`
+    async fn prune_loop(&self) {
+        while !self.exit.load(Ordering::Relaxed) {
+            let config = self.config.read().await.clone();
 
-            if self.exit {
-                break;
+            match retention::prune(self.database.as_ref(), &config, stats_getter::get_unix_timestamp(), false).await {
+                Ok(pruned) => {
+                    let samples: u64 = pruned.iter().map(|device| device.samples).sum();
+                    let rollups: u64 = pruned.iter().map(|device| device.rollups).sum();
+
+                    if self.print && samples + rollups > 0 {
+                        println!("Pruned {samples} samples and {rollups} rollup rows past their retention");
+                    }
+                }
+                Err(e) => {
+                    if self.print {
+                        eprintln!("Failed to prune old data: {e}");
+                    }
+                }
             }
+
+            sleep(Duration::from_secs(PRUNE_INTERVAL_SECONDS)).await;
         }
     }
 
-    /// Sets the exit variable to true
-    fn exit(&mut self) {
-        self.exit = true;
+    /// Records that a device stored samples so its rollups are rebuilt from the oldest of them on the next run
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device that stored the samples
+    /// * `since: i64` - Unix timestamp of the oldest sample stored
+    async fn mark_for_rollup(&self, device_id: &str, since: i64) {
+        let mut rollups_due = self.rollups_due.lock().await;
+
+        let due = rollups_due.entry(device_id.to_string()).or_insert(since);
+
+        *due = (*due).min(since);
     }
 
-    /// Takes the stream and determines what command should be ran
+    /// Works out if the client is using TLS by peeking at the first byte and completes the handshake if it is
     ///
-    /// Decodes the base64 data to json
-    /// 
     /// # Arguments
-    /// * `mut stream: TcpStream` - Stream the client is connected to
-    async fn process_request(&mut self, mut stream: TcpStream) {
-        // Makes the buffer to store the data
-        let mut buf = [0; 1024];
-
-        // Read the data from teh stream
-        stream.read(&mut buf).unwrap();
-
-        // Raw string data
-        let raw_string = String::from_utf8_lossy(&buf).trim().to_string();
-
-        // Finds where the ! is in the msg
-        let command_ending = raw_string.find("!").unwrap();
-
-        // Gets the command the data that was encoded in base64
-        let (command, encoded_data) = raw_string.split_at(command_ending + 1);
-
-        // Decode the data from base64, the filtering removes empty bytes in the array
-        let decoded_bytes = general_purpose::STANDARD.decode(
-            encoded_data
-                .to_string()
-                .chars()
-                .filter(|&c| c != '\u{0000}')
-                .collect::<String>(),
-        );
-
-        // Convert the decode bytes to a string
-        let payload_string = match decoded_bytes {
-            Ok(bytes) => match String::from_utf8(bytes) {
-                Ok(s) => s,
-                Err(e) => {
+    /// * `stream: TcpStream` - Stream the client is connected to
+    /// * `addr: SocketAddr` - Address of the client
+    ///
+    /// # Returns
+    /// * `Some(Box<dyn AsyncStream>)` - Stream to read the request from
+    /// * `None` - The client was rejected or the handshake failed
+    async fn accept(&self, stream: TcpStream, addr: SocketAddr) -> Option<Box<dyn AsyncStream>> {
+        let mut first_byte = [0; 1];
+
+        let is_tls = matches!(
+            timeout(Duration::from_secs(READ_TIMEOUT_SECONDS), stream.peek(&mut first_byte)).await,
+            Ok(Ok(1))
+        ) && first_byte[0] == tls::HANDSHAKE_RECORD;
+
+        match (&self.tls, is_tls) {
+            (Some(acceptor), true) => {
+                match timeout(Duration::from_secs(READ_TIMEOUT_SECONDS), acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => Some(Box::new(stream)),
+                    Ok(Err(e)) => {
+                        if self.print {
+                            eprintln!("TLS handshake with {addr} failed: {e}");
+                        }
+                        None
+                    }
+                    Err(_) => {
+                        if self.print {
+                            eprintln!("Timed out during the TLS handshake with {addr}");
+                        }
+                        None
+                    }
+                }
+            }
+            (None, true) => {
+                if self.print {
+                    eprintln!("{addr} tried to connect with TLS but TLS isn't enabled");
+                }
+                None
+            }
+            (_, false) => {
+                if self.config.read().await.require_tls {
                     if self.print {
-                        eprintln!("Failed to convert decoded bytes to String: {}", e);
+                        eprintln!("{addr} tried to connect without TLS but TLS is required");
                     }
-                    return;
+                    return None;
                 }
-            },
-            Err(e) => {
+                Some(Box::new(stream))
+            }
+        }
+    }
+
+    /// Takes the stream and determines what command should be ran
+    ///
+    /// Reads the whole request frame and parses its body as json,
+    /// a client that doesn't finish sending within `READ_TIMEOUT_SECONDS` is dropped
+    /// 
+    /// # Arguments
+    /// * `stream: TcpStream` - Stream the client is connected to
+    /// * `addr: SocketAddr` - Address of the client
+    async fn process_request(&self, stream: TcpStream, addr: SocketAddr) {
+        let mut stream = match self.accept(stream, addr).await {
+            Some(s) => s,
+            None => return,
+        };
+
+        // Read the whole request, old clients still send COMMAND!base64
+        let request = match timeout(Duration::from_secs(READ_TIMEOUT_SECONDS), frame::read_request(&mut stream)).await {
+            Ok(Ok(f)) => f,
+            // Nothing was sent, such as when a client only fetches the certificate fingerprint
+            Ok(Err(FrameError::Short { received: 0, .. })) => return,
+            Ok(Err(e)) => {
+                if self.print {
+                    eprintln!("Failed to read request from {addr}: {e}");
+                }
+                return;
+            }
+            Err(_) => {
                 if self.print {
-                    eprintln!("Failed to decode base64 string: {}", e);
+                    eprintln!("Timed out reading request from {addr}");
                 }
                 return;
             }
         };
-        
-        let payload: Value = match serde_json::from_str(&payload_string) {
+
+        let command = request.command;
+
+        let mut stream = Connection { stream, command, legacy: request.legacy };
+        let stream = &mut stream;
+
+        let response = self.respond(&request, addr).await;
+
+        self.msg_client(stream, response).await;
+
+        // Lets TLS clients know the reply is complete
+        let _ = timeout(Duration::from_secs(WRITE_TIMEOUT_SECONDS), stream.stream.shutdown()).await;
+    }
+
+    /// Authenticates the request and runs its command
+    ///
+    /// # Arguments
+    /// * `request: &Frame` - Request sent by the client
+    /// * `addr: SocketAddr` - Address of the client
+    ///
+    /// # Returns
+    /// `Response` - The single reply to send back
+    async fn respond(&self, request: &Frame, addr: SocketAddr) -> Response {
+        let command = request.command;
+
+        let payload: Value = match serde_json::from_slice(&request.body) {
             Ok(v) => v,
+            // Legacy clients send SETUP without a body
+            Err(_) if command == Commands::SETUP => Value::Null,
             Err(e) => {
-                if self.print && command.to_command() != Commands::SETUP {
-                    eprintln!("Failed to parse JSON: {}", e);
+                if self.print {
+                    eprintln!("Failed to parse JSON from {addr}: {e}");
                 }
-                Value::String("N/A".to_string())
+                return Response::new(Status::BadRequest, "Invalid JSON payload");
             }
         };
 
+        self.reload_config().await;
+
+        // Every command apart from SETUP has to be signed by a registered device
+        if command != Commands::SETUP {
+            if let Err(reason) = self.authenticate(request, &payload).await {
+                self.log_auth_failure(addr, command, &reason);
+                return Response::new(Status::Unauthorized, "Authentication failed");
+            }
+        }
+
         // Match the command to the Commands enum
-        match command.to_command() {
-            Commands::INPUT         => self.input(stream, payload).await,
-            Commands::RENAME        => self.rename(stream, payload).await,
-            Commands::AdminRename   => self.admin_rename(stream, payload).await,
-            Commands::SETUP 		=> self.setup(stream).await,
-            Commands::REMOVE 		=> self.remove_device(stream, payload).await,
-            Commands::LIST 			=> self.list(stream, payload).await,
-            Commands::UpdateServer  => self.update_server(stream, payload).await,
+        match command {
+            Commands::INPUT         => self.input(payload).await,
+            Commands::BatchInput    => self.batch_input(payload).await,
+            Commands::INVENTORY     => self.inventory(payload).await,
+            Commands::RENAME        => self.rename(payload).await,
+            Commands::AdminRename   => self.admin_rename(payload).await,
+            Commands::SETUP 		=> self.setup(request.legacy, payload, addr).await,
+            Commands::REMOVE 		=> self.remove_device(payload).await,
+            Commands::LIST 			=> self.list(payload).await,
+            Commands::UpdateServer  => self.update_server(payload).await,
             Commands::EXIT 			=> self.exit(),
+            Commands::PENDING       => self.pending(payload).await,
+            Commands::APPROVE       => self.approve(payload).await,
+            Commands::DENY          => self.deny(payload).await,
             _ => self.error(),
         }
     }
 
+    /// Checks the signature on a request
+    ///
+    /// # Arguments
+    /// * `request: &Frame` - Request sent by the client
+    /// * `payload: &Value` - Payload parsed from the request
+    ///
+    /// # Returns
+    /// * `Ok(())` - The request was signed by a registered device, or is unsigned and `requireAuth` is off
+    /// * `Err(String)` - Why the request was rejected
+    async fn authenticate(&self, request: &Frame, payload: &Value) -> Result<(), String> {
+        let auth = match &request.auth {
+            Some(auth) => auth,
+            None if self.config.read().await.require_auth => return Err("request isn't signed".to_string()),
+            None => return Ok(()),
+        };
+
+        let now = stats_getter::get_unix_timestamp();
+
+        if (now - auth.timestamp).abs() > AUTH_WINDOW_SECONDS {
+            return Err(format!("{} sent a timestamp outside the allowed window: {}", auth.device_id, auth.timestamp));
+        }
+
+        let public_key = {
+            let config = self.config.read().await;
+
+            if config.pending_devices.contains_key(&auth.device_id) {
+                return Err(format!("{} is waiting for approval", auth.device_id));
+            }
+
+            match config.device_keys.get(&auth.device_id) {
+                Some(public_key) => public_key.clone(),
+                None => return Err(format!("{} doesn't have a public key", auth.device_id)),
+            }
+        };
+
+        if !auth.verify(&public_key, request.command, &request.body) {
+            return Err(format!("{} sent a bad signature", auth.device_id));
+        }
+
+        // The payload has to be about the device that signed it, admin commands send the hash of the ID
+        if let Some(claimed_id) = payload["deviceID"].as_str() {
+            if claimed_id != auth.device_id && claimed_id != sha256::digest(&auth.device_id) {
+                return Err(format!("{} signed a request for {claimed_id}", auth.device_id));
+            }
+        }
+
+        // Nonces are only stored once the signature is valid so forged requests can't fill the cache
+        let mut nonces = self.nonces.lock().await;
+
+        nonces.retain(|_, timestamp| now - *timestamp <= AUTH_WINDOW_SECONDS);
+
+        if nonces.insert(auth.nonce, auth.timestamp).is_some() {
+            return Err(format!("{} replayed a request", auth.device_id));
+        }
+
+        Ok(())
+    }
+
+    /// Reloads the config if the file was changed outside of the server, such as by `rlsd --approve`
+    async fn reload_config(&self) {
+        let modified = config_modified();
+
+        let mut last_modified = self.config_modified.lock().await;
+
+        if modified.is_some() && modified != *last_modified {
+            *self.config.write().await = json_handler::read_json_as_value(&get_server_config_path()).to_server();
+
+            *last_modified = modified;
+        }
+    }
+
+    /// Records a failed authentication attempt in the auth log, separate from malformed requests
+    ///
+    /// # Arguments
+    /// * `addr: SocketAddr` - Address of the client
+    /// * `command: Commands` - Command the client sent
+    /// * `reason: &str` - Why the request was rejected
+    fn log_auth_failure(&self, addr: SocketAddr, command: Commands, reason: &str) {
+        let line = format!("{} {addr} {} {reason}\n", chrono::Utc::now().to_rfc3339(), command.to_string());
+
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(get_auth_log_path()) {
+            let _ = file.write_all(line.as_bytes());
+        }
+
+        if self.print {
+            eprintln!("Authentication failed for {addr}: {reason}");
+        }
+    }
+
     /// Takes the json data as an input and adds it to the display data
-    async fn input(&mut self, mut stream: TcpStream, mut payload: Value) {
-        let device_id = payload["deviceID"].as_str().unwrap();
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload from the client
+    async fn input(&self, mut payload: Value) -> Response {
+        let device_id = payload["deviceID"].as_str().unwrap_or("N/A").to_string();
 
-        // If it has been less than 110 seconds since the last time data was inserted, 
-        if stats_getter::get_unix_timestamp() - self.device_times.get(device_id).unwrap_or(&0) < 110 {
+        if !self.config.read().await.registered_device_ids.contains(&device_id) {
+            if self.print {
+                println!("{device_id} tried to input data but is not registered");
+            }
+            return Response::new(Status::Forbidden, "Device isn't registered");
+        }
+
+        let interval = match self.check_interval(&device_id).await {
+            Ok(interval) => interval,
+            Err(response) => return response,
+        };
+
+        // Replaces the time with the server time
+        payload["time"] = Value::Number(stats_getter::get_unix_timestamp().into());
+
+        let mut device = payload.to_device();
+
+        if device.device_id == "N/A" {
+            return Response::new(Status::BadRequest, "Invalid device data");
+        }
+
+        limit_metrics(&mut device);
+
+        let time = device.time;
+
+        match self.database.input_data(&[device]).await {
+            Ok(_) => {
+                self.mark_for_rollup(&device_id, time).await;
+
+                Response::ok("Data inserted").with_data(json!({"interval": interval}))
+            }
+            Err(e) => {
+                if self.print {
+                    eprintln!("Failed to insert data from {device_id}: {e}");
+                }
+                Response::new(Status::ServerError, "Failed to insert data")
+            }
+        }
+    }
+
+    /// Stores what a device is, sent when its client starts and whenever it changes
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload from the client, holds the device id and the inventory
+    async fn inventory(&self, payload: Value) -> Response {
+        let device_id = payload["deviceID"].as_str().unwrap_or("N/A").to_string();
+
+        if !self.config.read().await.registered_device_ids.contains(&device_id) {
+            if self.print {
+                println!("{device_id} tried to send its inventory but is not registered");
+            }
+            return Response::new(Status::Forbidden, "Device isn't registered");
+        }
+
+        if !payload["inventory"].is_object() {
+            return Response::new(Status::BadRequest, "Invalid inventory");
+        }
+
+        let mut inventory = Inventory::from_json(&payload["inventory"]);
+
+        inventory.time = stats_getter::get_unix_timestamp();
+
+        match self.database.input_inventory(&device_id, &inventory).await {
+            Ok(true) => Response::ok("Inventory updated"),
+            Ok(false) => Response::ok("Inventory unchanged"),
+            Err(e) => {
+                if self.print {
+                    eprintln!("Failed to insert the inventory of {device_id}: {e}");
+                }
+                Response::new(Status::ServerError, "Failed to insert the inventory")
+            }
+        }
+    }
+
+    /// Checks the device isn't reporting more often than its interval allows and records the report
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - ID of the reporting device
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Interval the device is allowed to report at, sent back so the client can match it
+    /// * `Err(Response)` - The device reported too soon
+    async fn check_interval(&self, device_id: &str) -> Result<u64, Response> {
+        let interval = self.config.read().await.interval_for(device_id);
+
+        let mut device_times = self.device_times.lock().await;
+
+        let now = stats_getter::get_unix_timestamp();
+
+        let since_last = now - device_times.get(device_id).unwrap_or(&0);
+
+        if since_last < interval as i64 - INTERVAL_LEEWAY_SECONDS {
             if self.print {
                 println!("{device_id} tried to send data too soon");
             }
-            return;
-        } else {
-            self.device_times.insert(device_id.to_owned(), stats_getter::get_unix_timestamp());
+            return Err(Response::new(Status::TooManyRequests, "Data was sent too soon")
+                .with_data(json!({"retryAfter": interval as i64 - since_last, "interval": interval})));
         }
 
-        if !self.config.registered_device_ids.contains(&device_id.to_string()) {
+        device_times.insert(device_id.to_string(), now);
+
+        Ok(interval)
+    }
+
+    /// Adds several samples sent by one device, keeping the time each was taken
+    ///
+    /// The client's clock is checked against `sentAt`, if it's off by more than `MAX_CLOCK_SKEW_SECONDS`
+    /// the samples are moved onto the server's clock. Samples from the future or older than `MAX_SAMPLE_AGE_SECONDS` are rejected,
+    /// as are samples closer than `MIN_SAMPLE_INTERVAL_SECONDS` to another one of the device, the rest are inserted in one transaction.
+    /// Batches only holding samples taken before the device's last accepted report aren't held to its interval, so a spool can be
+    /// replayed in one go
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload from the client, holds the device id, when the batch was sent and an array of samples
+    async fn batch_input(&self, payload: Value) -> Response {
+        let device_id = payload["deviceID"].as_str().unwrap_or("N/A").to_string();
+
+        if !self.config.read().await.registered_device_ids.contains(&device_id) {
             if self.print {
                 println!("{device_id} tried to input data but is not registered");
             }
-            return;
+            return Response::new(Status::Forbidden, "Device isn't registered");
         }
 
-        // Replaces the time with the server time
-        payload["time"] = Value::Number(stats_getter::get_unix_timestamp().into());
+        let samples = match payload["samples"].as_array() {
+            Some(samples) => samples,
+            None => return Response::new(Status::BadRequest, "The batch doesn't have any samples"),
+        };
 
-        let device = payload.to_device();
+        if samples.len() > MAX_BATCH_SAMPLES {
+            return Response::new(Status::BadRequest, &format!("A batch can't have more than {MAX_BATCH_SAMPLES} samples"));
+        }
 
-        if device.device_id != "N/A" {
-            // println!("INPUT RECEIVED:\n{}\n{}\n{}", get_divider(), device.to_string().blue().bold(), get_divider());
-            
-            database::input_data(&self.database, device).await.ok();
+        let sent_at = match payload["sentAt"].as_i64() {
+            Some(sent_at) => sent_at,
+            None => return Response::new(Status::BadRequest, "The batch doesn't say when it was sent"),
+        };
 
-            match stream.write_all("Data inserted".as_bytes()) {
-                Ok(v) => v,
-                _ => {}
-            };
+        let mut devices: Vec<Device> = samples.iter().map(|sample| sample.to_device()).collect();
+
+        // The signature only covers the device id at the top of the payload
+        if devices.iter().any(|device| device.device_id != device_id) {
+            return Response::new(Status::BadRequest, "Every sample has to be from the sending device");
+        }
+
+        let now = stats_getter::get_unix_timestamp();
+
+        let skew = now - sent_at;
+
+        if skew.abs() > MAX_CLOCK_SKEW_SECONDS {
+            if self.print {
+                println!("{device_id}'s clock is off by {skew} seconds, adjusting its samples");
+            }
+
+            for device in devices.iter_mut() {
+                device.time += skew;
+            }
+        }
+
+        // Samples taken before the device's last accepted report are a backlog from its spool, which is replayed in back-to-back batches.
+        // Only batches that move the device's data forward have to wait for its interval, backlogs are bounded by the spacing check below
+        let last_report = self.device_times.lock().await.get(&device_id).copied();
+
+        let interval = if last_report.is_some_and(|last_report| devices.iter().all(|device| device.time <= last_report)) {
+            self.config.read().await.interval_for(&device_id)
+        } else {
+            match self.check_interval(&device_id).await {
+                Ok(interval) => interval,
+                Err(response) => return response,
+            }
+        };
+
+        let total = devices.len();
+
+        devices.retain(|device| device.time <= now + MAX_CLOCK_SKEW_SECONDS && device.time >= now - MAX_SAMPLE_AGE_SECONDS);
+
+        // However the samples are sent, a device can't store more than one every MIN_SAMPLE_INTERVAL_SECONDS
+        if let (Some(first), Some(last)) = (devices.iter().map(|device| device.time).min(), devices.iter().map(|device| device.time).max()) {
+            let spacing = MIN_SAMPLE_INTERVAL_SECONDS as i64;
+            let query = SampleQuery::new(&device_id, first - spacing + 1, last + spacing - 1).with_columns(&[]);
+            let stored: Vec<i64> = self.database.get_samples(&query).await.iter().map(|device| device.time).collect();
+
+            space_out(&mut devices, &stored, spacing);
+        }
+
+        let rejected = total - devices.len();
+
+        devices.iter_mut().for_each(limit_metrics);
+
+        if let Err(e) = self.database.input_data(&devices).await {
+            if self.print {
+                eprintln!("Failed to insert data from {device_id}: {e}");
+            }
+            return Response::new(Status::ServerError, "Failed to insert data");
         }
 
-        self.msg_client(stream, "Failed to insert data");
+        if let Some(oldest) = devices.iter().map(|device| device.time).min() {
+            self.mark_for_rollup(&device_id, oldest).await;
+        }
+
+        let data = json!({"inserted": devices.len(), "rejected": rejected, "clockSkew": skew, "interval": interval});
+
+        if rejected == 0 {
+            Response::ok(&format!("Inserted {} samples", devices.len())).with_data(data)
+        } else {
+            Response::ok(&format!("Inserted {} samples, rejected {rejected} out of range or too close to another", devices.len())).with_data(data)
+        }
     }
 
     /// Renames the supplied device id on the DB
     /// Sends the total amount of effected rows back to the client
     /// 
     /// # Arguments
-    /// * `mut steam: TcpStream` - Stream the client is connected to
     /// * `payload: Value` - Payload from the client
-    async fn rename(&mut self, mut stream: TcpStream, payload: Value) {
+    async fn rename(&self, payload: Value) -> Response {
         let device_id = json_handler::read_json_from_buf("deviceID", &payload);
         let device_name = json_handler::read_json_from_buf("deviceName", &payload);
 
-        let result = database::rename_device(&self.database, &device_id, &device_name).await;
-
-        stream.write_all(result.as_bytes()).unwrap();
+        match self.database.rename_device(&device_id, &device_name).await {
+            Ok(0) => Response::new(Status::NotFound, &format!("No rows found matching id: {device_id}")),
+            Ok(rows) => Response::ok(&format!("Changed device name for {device_id} to {device_name}\n{rows} rows affected"))
+                .with_data(json!({"rowsAffected": rows})),
+            Err(e) => {
+                if self.print {
+                    eprintln!("Failed to rename {device_id}: {e}");
+                }
+                Response::new(Status::ServerError, "Error renaming device")
+            }
+        }
     }
 
-    async fn admin_rename(&mut self, stream: TcpStream, mut payload: Value) {
+    /// Renames another device, admin only
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload from the client
+    async fn admin_rename(&self, mut payload: Value) -> Response {
         let device_id = json_handler::read_json_from_buf("deviceID", &payload);
 
-        if self.admin_check(&device_id) {
-            payload["deviceID"] = payload["renamedDeviceID"].clone();
-
-            self.rename(stream, payload).await;
-        } else {
-            self.msg_client(stream, "You're not allowed to do that");
+        if !self.admin_check(&device_id).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
         }
+
+        payload["deviceID"] = payload["renamedDeviceID"].clone();
+
+        self.rename(payload).await
     }
 
     /// Removes the supplied device from the registered devices and db
     /// Sends the total amount of effected rows back to the client
     /// 
     /// # Arguments
-    /// * `mut steam: TcpSteam` - Stream the client is connected to
     /// * `payload: Value` - Payload from the client
-    async fn remove_device(&mut self, mut stream: TcpStream, payload: Value) {
+    async fn remove_device(&self, payload: Value) -> Response {
         // Get the device id or set it to N/A
-        let device_id = payload.get("deviceID").unwrap().as_str().unwrap_or("N/A");
-        
-        // Return if the id is N/A
-        if device_id == "N/A" {return;}
+        let device_id = payload["deviceID"].as_str().unwrap_or("N/A");
+
+        // If that sha256 isn't in the admin list, stop
+        if !self.admin_check(device_id).await {
+            if self.print {
+                println!("{device_id} tried to remove device data without permission")
+            }
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
+        }
+
+        let removed_device_id = payload["removedDeviceID"].as_str().unwrap_or("N/A");
 
-        // If that sha256 exists in the admin list, continue
-        if self.admin_check(device_id) {
-            let removed_device_id = payload["removedDeviceID"].as_str().unwrap();
+        let rows = match self.database.remove_device(removed_device_id).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                if self.print {
+                    eprintln!("Failed to remove {removed_device_id}: {e}");
+                }
+                return Response::new(Status::ServerError, "Error removing device");
+            }
+        };
 
-            let msg = database::remove_device(&self.database, removed_device_id).await;
+        {
+            let mut config = self.config.write().await;
 
             // Vector to store the new ids
             let mut new_registered_device_ids: Vec<String> = Vec::new();
 
             // Populates the vector with all ids except the removed one
-            for id in self.config.registered_device_ids.iter() {
+            for id in config.registered_device_ids.iter() {
                 if id != removed_device_id {
                     new_registered_device_ids.push(id.to_owned());
                 }
             }
 
             // Apply the new config
-            self.config.registered_device_ids = new_registered_device_ids;
+            config.registered_device_ids = new_registered_device_ids;
+
+            config.device_keys.remove(removed_device_id);
 
             // Write the new config to the config file
-            write_server_config_all(self.config.to_json());
+            write_server_config_all(config.to_json());
+        }
 
-            // Try to send a message back to the client
-            match stream.write_all(msg.as_bytes()) {
-                Ok(s) => s,
-                Err(e) => if self.print {eprintln!("{e}")}
-            }
+        if rows == 0 {
+            Response::new(Status::NotFound, &format!("No rows found matching id: {removed_device_id}"))
         } else {
-            if self.print {
-                println!("{device_id} tried to remove device data without permission")
-            }
-            self.msg_client(stream, "You're not allowed to do that.");
+            Response::ok(&format!("Removed {rows} rows for device {removed_device_id}"))
+                .with_data(json!({"rowsAffected": rows}))
         }
     }
 
-    /// Lists all non-admin devices on the server and sends them back over the TcpSteam
+    /// Lists all non-admin devices on the server
     /// 
     /// # Arguments
-    /// * `mut stream: TcpSteam` - Stream the client is connected to
     /// * `payload: Value` - Payload sent by the client
-    async fn list(&mut self, mut stream: TcpStream, payload: Value) {
-        if !self.admin_check(payload["deviceID"].as_str().unwrap()) {
-            self.msg_client(stream, "You're not allowed to do that.");
-            return;
+    async fn list(&self, payload: Value) -> Response {
+        if !self.admin_check(payload["deviceID"].as_str().unwrap_or("N/A")).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
         }
 
-        let ids = database::get_all_device_uids(&self.database).await;
-
         let mut msg = String::new();
+        let mut devices = Vec::new();
 
         // Adds every device to the message except for admins
-        for id in ids {
-            if !self.admin_check(&sha256::digest(&id)) {
-                msg = format!("{msg}\n{}: {}", database::get_device_name_from_uid(&self.database, &id).await, id)
+        for device in self.database.get_devices().await {
+            if !self.admin_check(&sha256::digest(&device.device_id)).await {
+                msg = format!("{msg}\n{}: {}", device.device_name, device.device_id);
+                devices.push(json!({"deviceID": device.device_id, "deviceName": device.device_name, "lastSeen": device.last_seen}));
             }
         }
 
-        match stream.write_all(msg.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => if self.print {eprintln!("{e}")}
-        }
+        Response::ok(&msg).with_data(Value::Array(devices))
+    }
+
+    /// Stops the server once the current connections are done
+    fn exit(&self) -> Response {
+        self.exit.store(true, Ordering::Relaxed);
+
+        Response::ok("Server stopping")
     }
 
-    /// If the command sent isn't recognized, print a message
-    fn error(&mut self) {
+    /// Replies to a command that wasn't recognized
+    fn error(&self) -> Response {
         if self.print {
             eprintln!("Command not recognized!")
         }
+
+        Response::new(Status::BadRequest, "Command not recognized")
     }
 
     /// Updates the rlsd version on the server
     /// 
     /// # Arguments
-    /// * `mut stream: TcpStream` - Stream the client is connected to
     /// * `payload: Value` - Payload sent by the client
-    async fn update_server(&mut self, stream: TcpStream, payload: Value) {
-        if !self.admin_check(&json_handler::read_json_from_buf("deviceID", &payload)) {
-            self.msg_client(stream, "You're not allowed to do that.");
-            return;
+    async fn update_server(&self, payload: Value) -> Response {
+        if !self.admin_check(payload["deviceID"].as_str().unwrap_or("N/A")).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
         }
 
         let arch = whoami::arch();
@@ -367,25 +906,148 @@ impl Server {
         };
 
         match result {
-            Ok(_) => self.msg_client(stream, "Update success"),
-            Err(e) => {if self.print {
-                println!("{e}")
-            }}
-        };
+            Ok(_) => Response::ok("Update success"),
+            Err(e) => {
+                if self.print {
+                    println!("{e}")
+                }
+                Response::new(Status::ServerError, &format!("Update failed: {e}"))
+            }
+        }
     }
 
-    /// Makes a new id for the requesting device
+    /// Makes a new id and secret for the requesting device, only the public key made from the secret is kept in the server
+    /// config to check signatures
+    ///
+    /// The device is registered straight away if approval isn't required or it's in an auto-approved subnet,
+    /// otherwise it waits in the pending list until an admin approves it.
+    /// Legacy clients can't sign requests so they only get the id as the message
     /// 
     /// # Arguments
-    /// * `mut stream: TcpStream` - Stream the client is connected to
-    async fn setup(&mut self, stream: TcpStream) {
+    /// * `legacy: bool` - If the client sent a legacy request
+    /// * `payload: Value` - Payload from the client, holds its hostname and the enrollment code
+    /// * `addr: SocketAddr` - Address of the client
+    async fn setup(&self, legacy: bool, payload: Value, addr: SocketAddr) -> Response {
         let id = get_device_id().await;
 
-        self.config.registered_device_ids.push(id.clone());
+        // Legacy clients can't sign requests so nothing is stored for them
+        let secret = if legacy { String::new() } else { auth::generate_secret() };
+        let public_key = if legacy { String::new() } else { auth::public_key(&secret) };
+
+        let approved = {
+            let mut config = self.config.write().await;
+
+            let approved = !config.require_approval || config.auto_approve_subnets.iter().any(|subnet| in_subnet(subnet, addr.ip()));
+
+            if !config.enrollment_code.is_empty() && !auth::codes_match(payload["enrollmentCode"].as_str().unwrap_or_default(), &config.enrollment_code) {
+                self.log_auth_failure(addr, Commands::SETUP, "Invalid enrollment code");
+                return Response::new(Status::Unauthorized, "Invalid enrollment code");
+            }
+
+            if !approved && config.pending_devices.len() >= MAX_PENDING_DEVICES {
+                self.log_auth_failure(addr, Commands::SETUP, "Too many devices are waiting for approval");
+                return Response::new(Status::TooManyRequests, "Too many devices are waiting for approval");
+            }
+
+            if approved {
+                config.registered_device_ids.push(id.clone());
+
+                if !public_key.is_empty() {
+                    config.device_keys.insert(id.clone(), public_key);
+                }
+            } else {
+                let hostname: String = payload["hostname"].as_str().unwrap_or("N/A").chars().take(64).collect();
+
+                config.pending_devices.insert(id.clone(), PendingDevice {
+                    hostname,
+                    ip: addr.ip().to_canonical().to_string(),
+                    requested_at: stats_getter::get_unix_timestamp(),
+                    public_key,
+                });
+            }
+
+            write_server_config_all(config.to_json());
+
+            approved
+        };
+
+        if !approved && self.print {
+            println!("{id} from {addr} is waiting for approval");
+        }
+
+        if legacy {
+            return Response::ok(&id);
+        }
+
+        let msg = if approved { "Device registered" } else { "Device is waiting for approval" };
+
+        Response::ok(msg).with_data(json!({"deviceID": id, "deviceSecret": secret, "pending": !approved}))
+    }
+
+    /// Lists the devices waiting for approval
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload sent by the client
+    async fn pending(&self, payload: Value) -> Response {
+        if !self.admin_check(payload["deviceID"].as_str().unwrap_or("N/A")).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
+        }
+
+        let config = self.config.read().await;
+
+        let devices: Vec<Value> = config.pending_devices.iter()
+            .map(|(id, device)| {
+                let mut device = device.to_json();
+                device["deviceID"] = Value::String(id.to_owned());
+                device
+            })
+            .collect();
+
+        Response::ok(&config.pending_list()).with_data(Value::Array(devices))
+    }
+
+    /// Registers the supplied pending device
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload sent by the client
+    async fn approve(&self, payload: Value) -> Response {
+        if !self.admin_check(payload["deviceID"].as_str().unwrap_or("N/A")).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
+        }
+
+        let approved_device_id = payload["approvedDeviceID"].as_str().unwrap_or("N/A");
+
+        let mut config = self.config.write().await;
+
+        if config.approve(approved_device_id) {
+            write_server_config_all(config.to_json());
+
+            Response::ok(&format!("Approved: {approved_device_id}"))
+        } else {
+            Response::new(Status::NotFound, &format!("{approved_device_id} isn't waiting for approval"))
+        }
+    }
+
+    /// Removes the supplied device from the pending list
+    ///
+    /// # Arguments
+    /// * `payload: Value` - Payload sent by the client
+    async fn deny(&self, payload: Value) -> Response {
+        if !self.admin_check(payload["deviceID"].as_str().unwrap_or("N/A")).await {
+            return Response::new(Status::Forbidden, NOT_ALLOWED);
+        }
+
+        let denied_device_id = payload["deniedDeviceID"].as_str().unwrap_or("N/A");
 
-        write_server_config_all(self.config.to_json());
+        let mut config = self.config.write().await;
 
-        self.msg_client(stream, &id);
+        if config.deny(denied_device_id) {
+            write_server_config_all(config.to_json());
+
+            Response::ok(&format!("Denied: {denied_device_id}"))
+        } else {
+            Response::new(Status::NotFound, &format!("{denied_device_id} isn't waiting for approval"))
+        }
     }
 
     /// Checks to see if the supplied sha256 id is an admin
@@ -395,23 +1057,109 @@ impl Server {
     /// 
     /// # Returns
     /// `bool` - True if the device is an admin 
-    fn admin_check(&mut self, id: &str) -> bool {
-        if self.config.admin_ids.contains(&id.to_string()) {
-            true
-        } else {
-            false
+    async fn admin_check(&self, id: &str) -> bool {
+        self.config.read().await.admin_ids.contains(&id.to_string())
+    }
+
+    /// Sends the reply back to the client in the same format it sent its request,
+    /// gives up if the client doesn't accept it within `WRITE_TIMEOUT_SECONDS`
+    ///
+    /// Legacy clients only get the message, everyone else gets the whole JSON envelope
+    ///
+    /// # Arguments
+    /// * `stream: &mut Connection` - Connection to the client
+    /// * `response: Response` - Reply to send
+    async fn msg_client(&self, stream: &mut Connection, response: Response) {
+        let msg = if stream.legacy { response.message } else { response.to_json().to_string() };
+
+        let reply = frame::write_reply(&mut stream.stream, stream.command, stream.legacy, &msg);
+
+        match timeout(Duration::from_secs(WRITE_TIMEOUT_SECONDS), reply).await {
+            Ok(Ok(s)) => s,
+            Ok(Err(e)) => if self.print {println!("{e}")},
+            Err(_) => if self.print {println!("Timed out sending reply")}
         }
     }
+}
+
+/// Drops custom metrics with invalid names and any past the first `MAX_CUSTOM_METRICS` so a client can't flood the database
+///
+/// # Arguments
+/// * `device: &mut Device` - Sample sent by a client
+fn limit_metrics(device: &mut Device) {
+    let metrics = std::mem::take(&mut device.metrics);
+
+    device.metrics = metrics
+        .into_iter()
+        .filter(|(name, value)| is_valid_metric_name(name) && value.is_finite())
+        .take(MAX_CUSTOM_METRICS)
+        .collect();
+}
+
+/// Drops samples closer than `spacing` seconds to a stored sample or to an earlier sample that's kept, and sorts the rest by time
+///
+/// Samples at the same time as a stored one are kept, they were sent again and storing them is skipped
+///
+/// # Arguments
+/// * `devices: &mut Vec<Device>` - Samples sent by a client
+/// * `stored: &[i64]` - Times of the device's stored samples around the ones sent
+/// * `spacing: i64` - Fewest seconds between two samples
+fn space_out(devices: &mut Vec<Device>, stored: &[i64], spacing: i64) {
+    devices.sort_by_key(|device| device.time);
+
+    let mut last_kept: Option<i64> = None;
+
+    devices.retain(|device| {
+        let too_close = |time: i64| time != device.time && (time - device.time).abs() < spacing;
+
+        if last_kept.is_some_and(|last| device.time - last < spacing) || stored.iter().any(|time| too_close(*time)) {
+            return false;
+        }
 
-    fn msg_client(&mut self, mut stream: TcpStream, msg: &str) {
-        match stream.write_all(msg.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => if self.print {println!("{e}")}       
+        last_kept = Some(device.time);
+        true
+    });
+}
+
+/// Returns when the server config file was last modified
+fn config_modified() -> Option<SystemTime> {
+    fs::metadata(get_server_config_path()).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Checks if an address is inside a subnet written in CIDR notation, a bare address only matches itself
+///
+/// # Arguments
+/// * `subnet: &str` - Subnet such as `192.168.1.0/24` or `fd00::/8`
+/// * `addr: IpAddr` - Address to check
+fn in_subnet(subnet: &str, addr: IpAddr) -> bool {
+    let (network, prefix) = subnet.trim().split_once('/').unwrap_or((subnet.trim(), ""));
+
+    let network: IpAddr = match network.parse() {
+        Ok(network) => network,
+        Err(_) => return false,
+    };
+
+    // Clients connecting over an IPv6 socket show up as IPv4-mapped addresses
+    match (network, addr.to_canonical()) {
+        (IpAddr::V4(network), IpAddr::V4(addr)) => {
+            let prefix = match prefix { "" => 32, prefix => match prefix.parse::<u32>() { Ok(p) if p <= 32 => p, _ => return false } };
+
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+
+            u32::from(network) & mask == u32::from(addr) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(addr)) => {
+            let prefix = match prefix { "" => 128, prefix => match prefix.parse::<u32>() { Ok(p) if p <= 128 => p, _ => return false } };
+
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+
+            u128::from(network) & mask == u128::from(addr) & mask
         }
+        _ => false,
     }
 }
 
-fn download(_version: &str, _file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn download(_version: &str, _file_path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // let url = "http://raw.githubusercontent.com/MADMAN-Modding/rlsd/refs/heads/master/bin/";
 
     // let response = blocking::get(&format!("{url}{version}"))?;
@@ -420,4 +1168,192 @@ fn download(_version: &str, _file_path: &str) -> Result<(), Box<dyn std::error::
     // std::io::copy(&mut content.as_ref(), &mut dest)?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{socket_handling::auth::Auth, stats_handling::database::SqlStorage};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const SECRET: &str = "device secret";
+
+    /// Makes a server requiring signatures with one registered device, `device`, that has `SECRET`
+    async fn server() -> Server {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+
+        let database = SqlStorage::new(pool);
+        database.migrate().await.unwrap();
+
+        let mut config = ServerConfig::new(vec!["device".to_string()], Vec::new(), false);
+        config.device_keys.insert("device".to_string(), auth::public_key(SECRET));
+
+        Server::with_config(Arc::new(database), config, false)
+    }
+
+    /// Makes an INPUT request for a device, signed with a secret at a time
+    fn request(device_id: &str, secret: &str, timestamp: i64) -> (Frame, Value) {
+        let payload = json!({"deviceID": device_id});
+
+        let mut frame = Frame::new(Commands::INPUT, payload.to_string().into_bytes());
+        frame.auth = Some(Auth::sign(device_id, secret, Commands::INPUT, &frame.body, timestamp));
+
+        (frame, payload)
+    }
+
+    fn now() -> i64 {
+        stats_getter::get_unix_timestamp()
+    }
+
+    /// Makes a BATCH_INPUT payload for `device` with a sample at each time
+    fn batch(times: impl Iterator<Item = i64>) -> Value {
+        let samples: Vec<Value> = times.map(|time| Device::new("device", "device", 1024, 4096, 0.5, 10, 100, 200, time).to_json()).collect();
+
+        json!({"deviceID": "device", "sentAt": now(), "samples": samples})
+    }
+
+    #[tokio::test]
+    async fn requests_signed_with_the_device_secret_are_accepted() {
+        let (frame, payload) = request("device", SECRET, now());
+
+        assert!(server().await.authenticate(&frame, &payload).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn bad_signatures_are_rejected() {
+        let server = server().await;
+
+        let (frame, payload) = request("device", "another secret", now());
+        assert!(server.authenticate(&frame, &payload).await.is_err());
+
+        let (mut frame, payload) = request("device", SECRET, now());
+        frame.body = json!({"deviceID": "device", "extra": true}).to_string().into_bytes();
+        assert!(server.authenticate(&frame, &payload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn replayed_nonces_are_rejected() {
+        let server = server().await;
+
+        let (frame, payload) = request("device", SECRET, now());
+
+        assert!(server.authenticate(&frame, &payload).await.is_ok());
+        assert!(server.authenticate(&frame, &payload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn timestamps_outside_the_window_are_rejected() {
+        let server = server().await;
+
+        let (old, payload) = request("device", SECRET, now() - AUTH_WINDOW_SECONDS - 1);
+        assert!(server.authenticate(&old, &payload).await.is_err());
+
+        let (future, payload) = request("device", SECRET, now() + AUTH_WINDOW_SECONDS + 1);
+        assert!(server.authenticate(&future, &payload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pending_devices_are_rejected_until_approved() {
+        let server = server().await;
+
+        server.config.write().await.pending_devices.insert("new".to_string(), PendingDevice {
+            hostname: "new".to_string(),
+            ip: "127.0.0.1".to_string(),
+            requested_at: now(),
+            public_key: auth::public_key("new secret"),
+        });
+
+        let (frame, payload) = request("new", "new secret", now());
+        assert!(server.authenticate(&frame, &payload).await.is_err());
+
+        assert!(server.config.write().await.approve("new"));
+
+        let (frame, payload) = request("new", "new secret", now());
+        assert!(server.authenticate(&frame, &payload).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn unknown_devices_are_rejected() {
+        let (frame, payload) = request("unknown", SECRET, now());
+
+        assert!(server().await.authenticate(&frame, &payload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_about_another_device_are_rejected() {
+        let (frame, _) = request("device", SECRET, now());
+
+        assert!(server().await.authenticate(&frame, &json!({"deviceID": "other"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unsigned_requests_are_only_accepted_when_auth_is_optional() {
+        let server = server().await;
+
+        let frame = Frame::new(Commands::INPUT, b"{}".to_vec());
+
+        assert!(server.authenticate(&frame, &json!({})).await.is_err());
+
+        server.config.write().await.require_auth = false;
+
+        assert!(server.authenticate(&frame, &json!({})).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn spooled_batches_are_accepted_back_to_back() {
+        let server = server().await;
+
+        // A day of 60 second samples, replayed oldest first in batches of 100
+        let start = now() - 24 * 60 * 60;
+        let times: Vec<i64> = (0..24 * 60).map(|index| start + index * 60).chain([now()]).collect();
+
+        for chunk in times.chunks(100) {
+            let response = server.batch_input(batch(chunk.iter().copied())).await;
+
+            assert_eq!(response.status, Status::Ok, "{}", response.message);
+            assert_eq!(response.data["inserted"], chunk.len());
+        }
+
+        assert_eq!(server.database.get_device_stats_after("device", 0).await.len(), times.len());
+
+        // Sending the backlog again stores nothing new
+        assert_eq!(server.batch_input(batch(times[..100].iter().copied())).await.status, Status::Ok);
+        assert_eq!(server.database.get_device_stats_after("device", 0).await.len(), times.len());
+    }
+
+    #[tokio::test]
+    async fn backdated_floods_are_rejected() {
+        let server = server().await;
+
+        assert_eq!(server.batch_input(batch([now()].into_iter())).await.status, Status::Ok);
+
+        // A full batch of samples a second apart, all before the last report so it isn't held to the interval
+        let start = now() - 60 * 60;
+        let response = server.batch_input(batch((0..MAX_BATCH_SAMPLES as i64).map(|index| start + index))).await;
+
+        let kept = MAX_BATCH_SAMPLES.div_ceil(MIN_SAMPLE_INTERVAL_SECONDS as usize);
+
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.data["inserted"], kept);
+        assert_eq!(response.data["rejected"], MAX_BATCH_SAMPLES - kept);
+
+        // Sending the rejected samples again adds nothing, they fall between the stored ones
+        let rejected = (0..MAX_BATCH_SAMPLES as i64).filter(|index| index % MIN_SAMPLE_INTERVAL_SECONDS as i64 != 0);
+        let response = server.batch_input(batch(rejected.map(|index| start + index))).await;
+
+        assert_eq!(response.data["inserted"], 0);
+        assert_eq!(server.database.get_device_stats_after("device", 0).await.len(), kept + 1);
+    }
+
+    #[tokio::test]
+    async fn new_samples_still_wait_for_the_interval() {
+        let server = server().await;
+
+        assert_eq!(server.batch_input(batch([now() - 60].into_iter())).await.status, Status::Ok);
+
+        let response = server.batch_input(batch([now() + 1].into_iter())).await;
+
+        assert_eq!(response.status, Status::TooManyRequests);
+        assert!(response.data["retryAfter"].as_i64().unwrap() > 0);
+    }
+}
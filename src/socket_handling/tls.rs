@@ -0,0 +1,262 @@
+//! TLS support for the connection between the client and the server
+//!
+//! The server uses a self-signed certificate generated on first run,
+//! clients pin the certificate's SHA-256 fingerprint during `--setup` instead of trusting a CA
+use std::{fs, path::Path, sync::Arc};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    constants::{get_tls_cert_path, get_tls_key_path},
+    json_handler::write_private,
+};
+
+/// First byte of a TLS handshake record, used to tell TLS clients apart from plaintext ones
+pub const HANDSHAKE_RECORD: u8 = 0x16;
+
+/// Name the server certificate is issued for, clients verify the fingerprint rather than the name
+pub const SERVER_NAME: &str = "rlsd";
+
+/// Any stream the server can read requests from, plaintext or TLS
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+/// Returns the crypto provider used by both sides
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+/// Returns the SHA-256 fingerprint of a DER encoded certificate as hex
+///
+/// # Arguments
+/// * `cert: &[u8]` - DER encoded certificate
+pub fn fingerprint(cert: &[u8]) -> String {
+    sha256::digest(cert)
+}
+
+/// Loads the server's certificate and key, generating them on first run
+///
+/// # Returns
+/// * `Ok((CertificateDer, PrivateKeyDer))` - DER encoded certificate and its private key
+/// * `Err(String)` - The files couldn't be read or generated
+fn load_or_create_cert() -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>), String> {
+    let cert_path = get_tls_cert_path();
+    let key_path = get_tls_key_path();
+
+    if !Path::new(&cert_path).exists() || !Path::new(&key_path).exists() {
+        let names = vec![SERVER_NAME.to_string(), whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string())];
+
+        let certified = rcgen::generate_simple_self_signed(names).map_err(|e| format!("Failed to generate certificate: {e}"))?;
+
+        fs::write(&cert_path, certified.cert.pem()).map_err(|e| format!("Failed to write {cert_path}: {e}"))?;
+
+        // Only the user running the server should be able to read the key
+        write_private(&key_path, certified.key_pair.serialize_pem()).map_err(|e| format!("Failed to write {key_path}: {e}"))?;
+    }
+
+    let cert = CertificateDer::from_pem_file(&cert_path).map_err(|e| format!("Failed to read {cert_path}: {e}"))?;
+    let key = PrivateKeyDer::from_pem_file(&key_path).map_err(|e| format!("Failed to read {key_path}: {e}"))?;
+
+    Ok((cert, key))
+}
+
+/// Makes the acceptor used by the server for TLS connections
+///
+/// # Returns
+/// * `Ok((TlsAcceptor, String))` - The acceptor and the fingerprint of the server certificate
+/// * `Err(String)` - The certificate couldn't be loaded
+pub fn server_acceptor() -> Result<(TlsAcceptor, String), String> {
+    let (cert, key) = load_or_create_cert()?;
+
+    let cert_fingerprint = fingerprint(&cert);
+
+    let config = rustls::ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .map_err(|e| e.to_string())?;
+
+    Ok((TlsAcceptor::from(Arc::new(config)), cert_fingerprint))
+}
+
+/// Returns the fingerprint of the server's certificate, generating it if needed
+pub fn server_fingerprint() -> Result<String, String> {
+    load_or_create_cert().map(|(cert, _)| fingerprint(&cert))
+}
+
+/// Makes the config used by the client for TLS connections
+///
+/// # Arguments
+/// * `pinned_fingerprint: Option<&str>` - Fingerprint the server's certificate has to match,
+///   `None` accepts any certificate and is only used to fetch the fingerprint during setup
+pub fn client_config(pinned_fingerprint: Option<&str>) -> Result<Arc<rustls::ClientConfig>, String> {
+    let provider = provider();
+
+    let verifier = PinnedCertVerifier {
+        fingerprint: pinned_fingerprint.map(|f| f.to_lowercase()),
+        provider: provider.clone(),
+    };
+
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+/// Returns the name the client uses when connecting
+pub fn server_name() -> ServerName<'static> {
+    ServerName::try_from(SERVER_NAME).expect("Invalid server name")
+}
+
+/// Verifies the server by comparing its certificate's fingerprint with the pinned one
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    /// Expected fingerprint, `None` trusts any certificate
+    fingerprint: Option<String>,
+    /// Provider used to check handshake signatures
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match &self.fingerprint {
+            Some(expected) if *expected != fingerprint(end_entity) => Err(rustls::Error::General(
+                "Server certificate doesn't match the pinned fingerprint".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    /// Makes a self-signed certificate like the server's
+    fn certificate() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).unwrap();
+
+        (certified.cert.der().clone(), PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()))
+    }
+
+    /// Runs a handshake against a server using `cert` with a client pinned to `pinned_fingerprint`
+    ///
+    /// # Returns
+    /// `bool` - True if the client connected and got the server's reply
+    async fn handshake(cert: (CertificateDer<'static>, PrivateKeyDer<'static>), pinned_fingerprint: Option<&str>) -> bool {
+        let server_config = rustls::ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.0], cert.1)
+            .unwrap();
+
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let connector = TlsConnector::from(client_config(pinned_fingerprint).unwrap());
+
+        let (client, server) = duplex(16 * 1024);
+
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(server).await.ok()?;
+            stream.write_all(b"ok").await.ok()?;
+            stream.shutdown().await.ok()
+        });
+
+        let mut reply = Vec::new();
+
+        let connected = match connector.connect(server_name(), client).await {
+            Ok(mut stream) => stream.read_to_end(&mut reply).await.is_ok(),
+            Err(_) => false,
+        };
+
+        let _ = server.await;
+
+        connected && reply == b"ok"
+    }
+
+    #[tokio::test]
+    async fn the_pinned_certificate_is_accepted() {
+        let cert = certificate();
+        let pinned = fingerprint(&cert.0);
+
+        assert!(handshake((cert.0.clone(), cert.1.clone_key()), Some(&pinned)).await);
+
+        // Fingerprints pasted in upper case still match
+        assert!(handshake(cert, Some(&pinned.to_uppercase())).await);
+    }
+
+    #[tokio::test]
+    async fn other_certificates_are_rejected() {
+        let pinned = fingerprint(&certificate().0);
+
+        assert!(!handshake(certificate(), Some(&pinned)).await);
+    }
+
+    #[tokio::test]
+    async fn any_certificate_is_accepted_without_a_pin() {
+        assert!(handshake(certificate(), None).await);
+    }
+
+    #[test]
+    fn the_verifier_compares_the_whole_fingerprint() {
+        let (cert, _) = certificate();
+        let pinned = fingerprint(&cert);
+
+        let verifier = |fingerprint: &str| PinnedCertVerifier { fingerprint: Some(fingerprint.to_string()), provider: provider() };
+
+        let verify = |verifier: PinnedCertVerifier| verifier.verify_server_cert(&cert, &[], &server_name(), &[], UnixTime::now()).is_ok();
+
+        // The last hex digit changed to another one
+        let last = if pinned.ends_with('0') { "1" } else { "0" };
+        let changed = format!("{}{last}", &pinned[..pinned.len() - 1]);
+
+        assert!(verify(verifier(&pinned)));
+        assert!(!verify(verifier(&changed)));
+        assert!(!verify(verifier(&pinned[..pinned.len() - 1])));
+        assert!(!verify(verifier("")));
+    }
+}
@@ -0,0 +1,434 @@
+//! Collectors the client samples the device with
+//!
+//! Each collector produces named metrics, the registry runs the enabled collectors at their own interval
+//! and fills a `Device` with what they produced. Metrics that aren't built in are sent by name so adding one
+//! only takes a new collector
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use systemstat::Platform;
+
+use crate::{
+    config::client::{ClientConfig, CollectorConfig},
+    constants::{
+        get_push_socket_path, METRIC_CPU_CORES, METRIC_CPU_FREQ, METRIC_CPU_FREQ_MAX, METRIC_CPU_USAGE, METRIC_DISK_IO, METRIC_FILESYSTEMS,
+        METRIC_INTERFACES, METRIC_LOAD_1, METRIC_LOAD_15, METRIC_LOAD_5, METRIC_PROCESS_COUNT, METRIC_RAM_BUFFERS,
+        METRIC_RAM_CACHED, METRIC_RAM_TOTAL, METRIC_RAM_USED, METRIC_SENSORS, METRIC_SWAP_TOTAL, METRIC_SWAP_USED,
+        METRIC_TOP_PROCESSES, METRIC_ZFS_ARC,
+    },
+    stats_handling::{
+        custom_metrics::CommandCollector,
+        device_info::{Device, DiskIo, FilesystemUsage, NetworkInterface, ProcessInfo, Sensor},
+        sensors::get_sensors,
+        stats_getter::{
+            get_cpu_frequency, get_cpu_usage, get_filesystems, get_load_average, get_memory, DiskIoTracker,
+            NetworkTracker, ProcessTracker,
+        },
+    },
+};
+
+/// Value of a metric
+#[derive(Clone)]
+pub enum MetricValue {
+    Float(f64),
+    Integer(i64),
+    Cores(Vec<f32>),
+    Filesystems(Vec<FilesystemUsage>),
+    DiskIo(Vec<DiskIo>),
+    Interfaces(Vec<NetworkInterface>),
+    Sensors(Vec<Sensor>),
+    Processes(Vec<ProcessInfo>),
+    /// Number from the user's own scripts, always kept by name even if it has the name of a built-in metric
+    Custom(f64),
+}
+
+/// A named value produced by a collector
+#[derive(Clone)]
+pub struct Metric {
+    /// Name of the metric, one of the `constants::METRIC_*` names for built-in metrics
+    pub name: String,
+    /// Value of the metric
+    pub value: MetricValue,
+}
+
+impl Metric {
+    /// Makes a new `Metric`
+    ///
+    /// # Arguments
+    /// * `name: &str` - Name of the metric
+    /// * `value: MetricValue` - Value of the metric
+    pub fn new(name: &str, value: MetricValue) -> Metric {
+        Metric { name: name.to_string(), value }
+    }
+}
+
+/// Takes part of a sample
+pub trait Collector<P: Platform> {
+    /// Name of the collector, used to find its settings in the client config
+    fn name(&self) -> &str;
+
+    /// Collects the metrics of the collector
+    ///
+    /// # Arguments
+    /// * `sys: &P` - Platform to read from
+    ///
+    /// # Returns
+    /// `Vec<Metric>` - Metrics that could be read, metrics that failed are left out
+    fn collect(&mut self, sys: &P) -> Vec<Metric>;
+}
+
+/// A collector with when it last ran
+struct Scheduled<P: Platform> {
+    collector: Box<dyn Collector<P>>,
+    interval: Duration,
+    last_run: Option<Instant>,
+}
+
+/// Runs collectors at their own interval
+pub struct Registry<P: Platform> {
+    collectors: Vec<Scheduled<P>>,
+}
+
+impl<P: Platform> Default for Registry<P> {
+    fn default() -> Registry<P> {
+        Registry { collectors: Vec::new() }
+    }
+}
+
+impl<P: Platform> Registry<P> {
+    /// Makes a registry with every built-in collector and every custom metric command, set up from the client config
+    ///
+    /// Metrics pushed to the socket at `get_push_socket_path` are collected too unless the `push` collector is disabled
+    ///
+    /// # Arguments
+    /// * `config: &ClientConfig` - Settings of each collector
+    /// * `root: &Path` - Root of the filesystem procfs and sysfs are read from, `/` outside of tests
+    pub fn from_config(config: &ClientConfig, root: &Path) -> Registry<P> {
+        let mut collectors: Vec<Box<dyn Collector<P>>> = vec![
+            Box::new(CpuCollector::new(root)),
+            Box::new(MemoryCollector::new(root)),
+            Box::new(FilesystemCollector),
+            Box::new(DiskIoCollector::new(root)),
+            Box::new(NetworkCollector::new(root)),
+            Box::new(SensorCollector::new(root)),
+            Box::new(ProcessCollector::new(config.process_count, config.redact_cmdline)),
+        ];
+
+        #[cfg(unix)]
+        if config.collector("push").enabled {
+            match crate::stats_handling::custom_metrics::PushCollector::listen(&get_push_socket_path()) {
+                Ok(collector) => collectors.push(Box::new(collector)),
+                Err(e) => eprintln!("Couldn't listen for pushed metrics on {}: {e}", get_push_socket_path()),
+            }
+        }
+
+        let mut registry = Registry::default();
+
+        for collector in collectors {
+            let settings = config.collector(collector.name());
+
+            registry.register(collector, settings);
+        }
+
+        // Commands have their own interval and can't be disabled other than by removing them
+        for command in &config.commands {
+            let settings = CollectorConfig { enabled: true, interval: command.interval };
+
+            registry.register(Box::new(CommandCollector::new(command.clone())), settings);
+        }
+
+        registry
+    }
+
+    /// Adds a collector, it isn't added if it's disabled
+    ///
+    /// # Arguments
+    /// * `collector: Box<dyn Collector<P>>` - Collector to run
+    /// * `settings: CollectorConfig` - Whether it's enabled and how often it runs
+    pub fn register(&mut self, collector: Box<dyn Collector<P>>, settings: CollectorConfig) {
+        if settings.enabled {
+            self.collectors.push(Scheduled {
+                collector,
+                interval: Duration::from_secs(settings.interval),
+                last_run: None,
+            });
+        }
+    }
+
+    /// Returns the names of the collectors that are run, in the order they're run
+    pub fn names(&self) -> Vec<&str> {
+        self.collectors.iter().map(|scheduled| scheduled.collector.name()).collect()
+    }
+
+    /// Runs every collector that's due and returns their metrics
+    ///
+    /// Collectors that aren't due yet give nothing, so their values are missing from the sample instead of repeated
+    ///
+    /// # Arguments
+    /// * `sys: &P` - Platform the collectors read from
+    pub fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+
+        for scheduled in &mut self.collectors {
+            if scheduled.last_run.is_none_or(|last_run| last_run.elapsed() >= scheduled.interval) {
+                metrics.extend(scheduled.collector.collect(sys));
+                scheduled.last_run = Some(Instant::now());
+            }
+        }
+
+        metrics
+    }
+
+    /// Fills a sample with the metrics of every collector that's due
+    ///
+    /// # Arguments
+    /// * `sys: &P` - Platform the collectors read from
+    /// * `device: &mut Device` - Sample to fill
+    pub fn sample(&mut self, sys: &P, device: &mut Device) {
+        for metric in self.collect(sys) {
+            apply(device, metric);
+        }
+    }
+}
+
+/// Puts a metric in the field of a sample it belongs to, metrics that aren't built in are kept by name
+///
+/// # Arguments
+/// * `device: &mut Device` - Sample to fill
+/// * `metric: Metric` - Metric to put in the sample
+pub fn apply(device: &mut Device, metric: Metric) {
+    match (metric.name.as_str(), metric.value) {
+        (METRIC_CPU_USAGE, MetricValue::Float(value)) => device.cpu_usage = Some(value as f32),
+        (METRIC_CPU_CORES, MetricValue::Cores(cores)) => device.cpu_cores = cores,
+        (METRIC_LOAD_1, MetricValue::Float(value)) => device.load_1 = Some(value as f32),
+        (METRIC_LOAD_5, MetricValue::Float(value)) => device.load_5 = Some(value as f32),
+        (METRIC_LOAD_15, MetricValue::Float(value)) => device.load_15 = Some(value as f32),
+        (METRIC_CPU_FREQ, MetricValue::Integer(value)) => device.cpu_freq = Some(value),
+        (METRIC_CPU_FREQ_MAX, MetricValue::Integer(value)) => device.cpu_freq_max = Some(value),
+        (METRIC_RAM_TOTAL, MetricValue::Integer(value)) => device.ram_total = Some(value),
+        (METRIC_RAM_USED, MetricValue::Integer(value)) => device.ram_used = Some(value),
+        (METRIC_RAM_BUFFERS, MetricValue::Integer(value)) => device.ram_buffers = Some(value),
+        (METRIC_RAM_CACHED, MetricValue::Integer(value)) => device.ram_cached = Some(value),
+        (METRIC_SWAP_TOTAL, MetricValue::Integer(value)) => device.swap_total = Some(value),
+        (METRIC_SWAP_USED, MetricValue::Integer(value)) => device.swap_used = Some(value),
+        (METRIC_ZFS_ARC, MetricValue::Integer(value)) => device.zfs_arc = Some(value),
+        (METRIC_FILESYSTEMS, MetricValue::Filesystems(filesystems)) => device.filesystems = filesystems,
+        (METRIC_DISK_IO, MetricValue::DiskIo(disks)) => device.disk_io = disks,
+        (METRIC_INTERFACES, MetricValue::Interfaces(interfaces)) => {
+            // The totals over every interface have columns of their own, they're left out when there's nothing to add up
+            // such as on the first run, which has no earlier counters to compare against
+            if !interfaces.is_empty() {
+                device.network_in = Some(interfaces.iter().map(|interface| interface.rx_bytes).sum());
+                device.network_out = Some(interfaces.iter().map(|interface| interface.tx_bytes).sum());
+            }
+            device.interfaces = interfaces;
+        }
+        (METRIC_SENSORS, MetricValue::Sensors(sensors)) => device.sensors = sensors,
+        (METRIC_PROCESS_COUNT, MetricValue::Integer(value)) => device.processes = Some(i32::try_from(value).unwrap_or(i32::MAX)),
+        (METRIC_TOP_PROCESSES, MetricValue::Processes(processes)) => device.top_processes = processes,
+        (_, MetricValue::Float(value) | MetricValue::Custom(value)) => {
+            device.metrics.insert(metric.name, value);
+        }
+        (_, MetricValue::Integer(value)) => {
+            device.metrics.insert(metric.name, value as f64);
+        }
+        (name, _) => eprintln!("The metric {name} isn't a number so it can't be sent, it's been left out"),
+    }
+}
+
+/// CPU usage of the whole CPU and of each core, load averages and frequency
+pub struct CpuCollector {
+    root: PathBuf,
+}
+
+impl CpuCollector {
+    /// Makes a new `CpuCollector`
+    ///
+    /// # Arguments
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn new(root: &Path) -> CpuCollector {
+        CpuCollector { root: root.to_path_buf() }
+    }
+}
+
+impl<P: Platform> Collector<P> for CpuCollector {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        let (usage, cores) = get_cpu_usage(sys);
+        let (load_1, load_5, load_15) = get_load_average(sys);
+        let (freq, freq_max) = get_cpu_frequency(&self.root);
+
+        vec![
+            Metric::new(METRIC_CPU_USAGE, MetricValue::Float(usage as f64)),
+            Metric::new(METRIC_CPU_CORES, MetricValue::Cores(cores)),
+            Metric::new(METRIC_LOAD_1, MetricValue::Float(load_1 as f64)),
+            Metric::new(METRIC_LOAD_5, MetricValue::Float(load_5 as f64)),
+            Metric::new(METRIC_LOAD_15, MetricValue::Float(load_15 as f64)),
+            Metric::new(METRIC_CPU_FREQ, MetricValue::Integer(freq)),
+            Metric::new(METRIC_CPU_FREQ_MAX, MetricValue::Integer(freq_max)),
+        ]
+    }
+}
+
+/// RAM and swap usage
+pub struct MemoryCollector {
+    root: PathBuf,
+}
+
+impl MemoryCollector {
+    /// Makes a new `MemoryCollector`
+    ///
+    /// # Arguments
+    /// * `root: &Path` - Root of the filesystem procfs is read from, `/` outside of tests
+    pub fn new(root: &Path) -> MemoryCollector {
+        MemoryCollector { root: root.to_path_buf() }
+    }
+}
+
+impl<P: Platform> Collector<P> for MemoryCollector {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        let memory = get_memory(sys, &self.root);
+
+        vec![
+            Metric::new(METRIC_RAM_TOTAL, MetricValue::Integer(memory.total)),
+            Metric::new(METRIC_RAM_USED, MetricValue::Integer(memory.used)),
+            Metric::new(METRIC_RAM_BUFFERS, MetricValue::Integer(memory.buffers)),
+            Metric::new(METRIC_RAM_CACHED, MetricValue::Integer(memory.cached)),
+            Metric::new(METRIC_SWAP_TOTAL, MetricValue::Integer(memory.swap_total)),
+            Metric::new(METRIC_SWAP_USED, MetricValue::Integer(memory.swap_used)),
+            Metric::new(METRIC_ZFS_ARC, MetricValue::Integer(memory.zfs_arc)),
+        ]
+    }
+}
+
+/// Usage of every mounted filesystem
+pub struct FilesystemCollector;
+
+impl<P: Platform> Collector<P> for FilesystemCollector {
+    fn name(&self) -> &str {
+        "filesystems"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        vec![Metric::new(METRIC_FILESYSTEMS, MetricValue::Filesystems(get_filesystems(sys)))]
+    }
+}
+
+/// Throughput of every disk since the previous run
+pub struct DiskIoCollector {
+    root: PathBuf,
+    tracker: DiskIoTracker,
+}
+
+impl DiskIoCollector {
+    /// Makes a new `DiskIoCollector`, it has no throughput to report until its second run
+    ///
+    /// # Arguments
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn new(root: &Path) -> DiskIoCollector {
+        DiskIoCollector { root: root.to_path_buf(), tracker: DiskIoTracker::default() }
+    }
+}
+
+impl<P: Platform> Collector<P> for DiskIoCollector {
+    fn name(&self) -> &str {
+        "diskIO"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        vec![Metric::new(METRIC_DISK_IO, MetricValue::DiskIo(self.tracker.sample(sys, &self.root)))]
+    }
+}
+
+/// Traffic of every network interface since the previous run
+pub struct NetworkCollector {
+    root: PathBuf,
+    tracker: NetworkTracker,
+}
+
+impl NetworkCollector {
+    /// Makes a new `NetworkCollector`, it has no traffic to report until its second run
+    ///
+    /// # Arguments
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn new(root: &Path) -> NetworkCollector {
+        NetworkCollector { root: root.to_path_buf(), tracker: NetworkTracker::default() }
+    }
+}
+
+impl<P: Platform> Collector<P> for NetworkCollector {
+    fn name(&self) -> &str {
+        "network"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        vec![Metric::new(METRIC_INTERFACES, MetricValue::Interfaces(self.tracker.sample(sys, &self.root)))]
+    }
+}
+
+/// Temperatures, battery charge and AC state
+pub struct SensorCollector {
+    root: PathBuf,
+}
+
+impl SensorCollector {
+    /// Makes a new `SensorCollector`
+    ///
+    /// # Arguments
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn new(root: &Path) -> SensorCollector {
+        SensorCollector { root: root.to_path_buf() }
+    }
+}
+
+impl<P: Platform> Collector<P> for SensorCollector {
+    fn name(&self) -> &str {
+        "sensors"
+    }
+
+    fn collect(&mut self, sys: &P) -> Vec<Metric> {
+        vec![Metric::new(METRIC_SENSORS, MetricValue::Sensors(get_sensors(sys, &self.root)))]
+    }
+}
+
+/// Number of processes and the busiest of them, read with sysinfo rather than the platform
+pub struct ProcessCollector {
+    tracker: ProcessTracker,
+    count: usize,
+    redact_cmdline: bool,
+}
+
+impl ProcessCollector {
+    /// Makes a new `ProcessCollector`
+    ///
+    /// # Arguments
+    /// * `count: usize` - Number of processes to report by CPU usage and by memory
+    /// * `redact_cmdline: bool` - Whether only the executable is reported instead of the whole command line
+    pub fn new(count: usize, redact_cmdline: bool) -> ProcessCollector {
+        ProcessCollector { tracker: ProcessTracker::default(), count, redact_cmdline }
+    }
+}
+
+impl<P: Platform> Collector<P> for ProcessCollector {
+    fn name(&self) -> &str {
+        "processes"
+    }
+
+    fn collect(&mut self, _sys: &P) -> Vec<Metric> {
+        let (count, top) = self.tracker.sample(self.count, self.redact_cmdline);
+
+        vec![
+            Metric::new(METRIC_PROCESS_COUNT, MetricValue::Integer(count as i64)),
+            Metric::new(METRIC_TOP_PROCESSES, MetricValue::Processes(top)),
+        ]
+    }
+}
@@ -39,6 +39,12 @@ pub enum Unit {
     /// PiB (1024^5)
     PEBIBYTE,
     Percentage,
+    /// MHz
+    MEGAHERTZ,
+    /// °C
+    CELSIUS,
+    /// Plain number without a unit, such as load averages
+    Number,
     /// s (60^1)
     SECOND,
     /// min (60^1)
@@ -69,6 +75,9 @@ impl Unit {
             Self::TEBIBYTE 		=> byte_to_unit::TEBIBYTE,
             Self::PEBIBYTE 		=> byte_to_unit::PEBIBYTE,
             Self::Percentage    => 100,
+            Self::MEGAHERTZ     => 1,
+            Self::CELSIUS       => 1,
+            Self::Number        => 1,
             Self::SECOND 		=> time_to_second::SECOND,
             Self::MINUTE 		=> time_to_second::MINUTE,
             Self::HOUR 			=> time_to_second::HOUR,
@@ -93,6 +102,9 @@ impl Unit {
             Self::TEBIBYTE 		=> Self::PEBIBYTE,
             Self::PEBIBYTE 		=> Self::PEBIBYTE,
             Self::Percentage    => Self::Percentage,
+            Self::MEGAHERTZ     => Self::MEGAHERTZ,
+            Self::CELSIUS       => Self::CELSIUS,
+            Self::Number        => Self::Number,
             Self::SECOND 		=> Self::MINUTE,
             Self::MINUTE 		=> Self::HOUR,
             Self::HOUR 			=> Self::DAY,
@@ -112,6 +124,9 @@ impl Unit {
             Self::TEBIBYTE 		=> "TiB",
             Self::PEBIBYTE 		=> "PiB",
             Self::Percentage    => "%",
+            Self::MEGAHERTZ     => "MHz",
+            Self::CELSIUS       => "°C",
+            Self::Number        => "",
             Self::SECOND 		=> "s",
             Self::MINUTE 		=> "minutes",
             Self::HOUR 			=> "hours",
@@ -133,6 +148,9 @@ impl std::fmt::Display for Unit {
             Self::TEBIBYTE      => write!(f, "{}", self.to_str()),
             Self::PEBIBYTE      => write!(f, "{}", self.to_str()),
             Self::Percentage    => write!(f, "{}", self.to_str()),
+            Self::MEGAHERTZ     => write!(f, "{}", self.to_str()),
+            Self::CELSIUS       => write!(f, "{}", self.to_str()),
+            Self::Number        => write!(f, "{}", self.to_str()),
             Self::SECOND        => write!(f, "{}", self.to_str()),
             Self::MINUTE        => write!(f, "{}", self.to_str()),
             Self::HOUR          => write!(f, "{}", self.to_str()),
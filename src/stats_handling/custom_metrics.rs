@@ -0,0 +1,347 @@
+//! Custom metrics from the user's own scripts and programs
+//!
+//! Commands are run on an interval and print `name=value` lines or a JSON object of numbers,
+//! programs can also push metrics to a Unix socket with StatsD lines such as `queue.depth:12|g`
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+use systemstat::Platform;
+
+use crate::{
+    config::client::CustomCommand,
+    constants::{COMMAND_TIMEOUT_SECONDS, MAX_METRIC_NAME_LENGTH},
+    stats_handling::collector::{Collector, Metric, MetricValue},
+};
+
+/// Checks a custom metric name only has letters, digits, `.`, `_` and `-` and isn't too long
+///
+/// # Arguments
+/// * `name: &str` - Name of the metric
+pub fn is_valid_metric_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_METRIC_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Parses the output of a custom metric command
+///
+/// The output is either a JSON object, where nested objects are joined to their keys with `.`,
+/// or one `name=value` per line. Anything that isn't a number with a valid name is left out
+///
+/// # Arguments
+/// * `output: &str` - What the command printed
+///
+/// # Returns
+/// `Vec<(String, f64)>` - Name and value of each metric
+pub fn parse_command_output(output: &str) -> Vec<(String, f64)> {
+    let mut metrics = Vec::new();
+
+    if let Ok(Value::Object(object)) = serde_json::from_str::<Value>(output) {
+        flatten_json("", &Value::Object(object), &mut metrics);
+    } else {
+        for line in output.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if let Some((name, value)) = line.split_once('=') {
+                if let Ok(value) = value.trim().parse::<f64>() {
+                    metrics.push((name.trim().to_string(), value));
+                }
+            }
+        }
+    }
+
+    metrics.retain(|(name, value)| is_valid_metric_name(name) && value.is_finite());
+
+    metrics
+}
+
+/// Adds every number in a JSON value to `metrics`, named by the keys leading to it
+fn flatten_json(prefix: &str, value: &Value, metrics: &mut Vec<(String, f64)>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let name = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+
+                flatten_json(&name, value, metrics);
+            }
+        }
+        Value::Number(number) => metrics.extend(number.as_f64().map(|number| (prefix.to_string(), number))),
+        Value::Bool(flag) => metrics.push((prefix.to_string(), if *flag { 1.0 } else { 0.0 })),
+        _ => {}
+    }
+}
+
+/// Runs a custom metric command through the shell, killing it if it takes longer than `COMMAND_TIMEOUT_SECONDS`
+///
+/// # Arguments
+/// * `command: &str` - Command line to run
+///
+/// # Returns
+/// * `Ok(String)` - What the command printed
+/// * `Err(String)` - The command couldn't be run, timed out or failed
+pub fn run_command(command: &str) -> Result<String, String> {
+    #[cfg(unix)]
+    let mut shell = Command::new("sh");
+    #[cfg(unix)]
+    shell.arg("-c");
+
+    #[cfg(windows)]
+    let mut shell = Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("couldn't be started: {e}"))?;
+
+    // Read on another thread so a command printing more than the pipe holds doesn't block until it's killed
+    let mut stdout = child.stdout.take().ok_or("has no output")?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(COMMAND_TIMEOUT_SECONDS);
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+
+                return Err(format!("took longer than {COMMAND_TIMEOUT_SECONDS} seconds"));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let output = reader
+        .join()
+        .map_err(|_| "output couldn't be read".to_string())?
+        .map_err(|e| format!("output couldn't be read: {e}"))?;
+
+    if !status.success() {
+        return Err(format!("exited with {status}"));
+    }
+
+    Ok(output)
+}
+
+/// Runs a configured command and sends the numbers it prints
+pub struct CommandCollector {
+    command: CustomCommand,
+}
+
+impl CommandCollector {
+    /// Makes a new `CommandCollector`
+    ///
+    /// # Arguments
+    /// * `command: CustomCommand` - Command to run
+    pub fn new(command: CustomCommand) -> CommandCollector {
+        CommandCollector { command }
+    }
+}
+
+impl<P: Platform> Collector<P> for CommandCollector {
+    fn name(&self) -> &str {
+        &self.command.name
+    }
+
+    fn collect(&mut self, _sys: &P) -> Vec<Metric> {
+        match run_command(&self.command.command) {
+            Ok(output) => parse_command_output(&output)
+                .into_iter()
+                .map(|(name, value)| Metric::new(&name, MetricValue::Custom(value)))
+                .collect(),
+            Err(e) => {
+                eprintln!("The command {} {e}", self.command.name);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Kind of a pushed metric, set by what follows the `|` in a StatsD line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PushKind {
+    /// `g`, the latest value is kept until another is pushed. Values starting with `+` or `-` change the current one
+    Gauge,
+    /// `c`, values are added up and the total since the previous sample is sent
+    Counter,
+    /// `ms`, `h` or `d`, the average of the values pushed since the previous sample is sent
+    Timer,
+}
+
+/// A single value pushed to the socket
+#[derive(Clone, PartialEq, Debug)]
+pub struct PushedValue {
+    /// Name of the metric
+    pub name: String,
+    /// Value, counters are already scaled by their sample rate
+    pub value: f64,
+    /// How the value is combined with the others
+    pub kind: PushKind,
+    /// Whether a gauge value changes the current one rather than replacing it
+    pub relative: bool,
+}
+
+/// Parses a StatsD line such as `queue.depth:12|g`, `jobs.done:1|c|@0.5` or `backup.time:320|ms`
+///
+/// # Arguments
+/// * `line: &str` - Line pushed to the socket
+///
+/// # Returns
+/// `Option<PushedValue>` - The value, `None` if the line isn't valid
+pub fn parse_statsd_line(line: &str) -> Option<PushedValue> {
+    let (name, rest) = line.trim().split_once(':')?;
+    let mut fields = rest.split('|');
+
+    let raw = fields.next()?.trim();
+    let value: f64 = raw.parse().ok().filter(|value: &f64| value.is_finite())?;
+
+    let kind = match fields.next()?.trim() {
+        "g" => PushKind::Gauge,
+        "c" => PushKind::Counter,
+        "ms" | "h" | "d" => PushKind::Timer,
+        _ => return None,
+    };
+
+    // Counters sent for only some events are scaled back up, `@0.1` means one in ten were sent
+    let rate = fields
+        .find_map(|field| field.trim().strip_prefix('@'))
+        .and_then(|rate| rate.parse::<f64>().ok())
+        .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+        .unwrap_or(1.0);
+
+    is_valid_metric_name(name).then(|| PushedValue {
+        name: name.to_string(),
+        value: if kind == PushKind::Counter { value / rate } else { value },
+        kind,
+        relative: kind == PushKind::Gauge && (raw.starts_with('+') || raw.starts_with('-')),
+    })
+}
+
+/// Values pushed to the socket that haven't been sampled yet
+#[derive(Default)]
+pub struct PushedMetrics {
+    gauges: BTreeMap<String, f64>,
+    counters: BTreeMap<String, f64>,
+    timers: BTreeMap<String, (f64, u64)>,
+}
+
+impl PushedMetrics {
+    /// Adds a pushed value
+    ///
+    /// # Arguments
+    /// * `pushed: PushedValue` - Value to add
+    pub fn add(&mut self, pushed: PushedValue) {
+        match pushed.kind {
+            PushKind::Gauge if pushed.relative => *self.gauges.entry(pushed.name).or_default() += pushed.value,
+            PushKind::Gauge => {
+                self.gauges.insert(pushed.name, pushed.value);
+            }
+            PushKind::Counter => *self.counters.entry(pushed.name).or_default() += pushed.value,
+            PushKind::Timer => {
+                let (sum, count) = self.timers.entry(pushed.name).or_default();
+                *sum += pushed.value;
+                *count += 1;
+            }
+        }
+    }
+
+    /// Returns the value of every metric and starts the next sample
+    ///
+    /// Gauges keep their value, counters go back to 0 and timers are only sent again once something is pushed to them
+    pub fn take(&mut self) -> Vec<(String, f64)> {
+        let mut metrics: Vec<(String, f64)> = self.gauges.iter().map(|(name, value)| (name.clone(), *value)).collect();
+
+        for (name, value) in self.counters.iter_mut() {
+            metrics.push((name.clone(), std::mem::take(value)));
+        }
+
+        for (name, (sum, count)) in std::mem::take(&mut self.timers) {
+            metrics.push((name, sum / count as f64));
+        }
+
+        metrics
+    }
+}
+
+/// Sends the metrics pushed to a Unix socket, the socket is read on its own thread
+#[cfg(unix)]
+pub struct PushCollector {
+    pushed: std::sync::Arc<std::sync::Mutex<PushedMetrics>>,
+}
+
+#[cfg(unix)]
+impl PushCollector {
+    /// Starts listening for pushed metrics, any file already at `path` is replaced
+    ///
+    /// Each datagram can hold several StatsD lines, lines that aren't valid are left out
+    ///
+    /// # Arguments
+    /// * `path: &str` - Where to make the socket
+    pub fn listen(path: &str) -> std::io::Result<PushCollector> {
+        use std::os::unix::net::UnixDatagram;
+
+        // A socket left behind by a client that didn't stop cleanly would make binding fail
+        let _ = std::fs::remove_file(path);
+
+        let socket = UnixDatagram::bind(path)?;
+        let pushed = std::sync::Arc::new(std::sync::Mutex::new(PushedMetrics::default()));
+        let received = pushed.clone();
+
+        thread::spawn(move || {
+            let mut buffer = [0; 8192];
+
+            loop {
+                match socket.recv(&mut buffer) {
+                    Ok(length) => {
+                        let lines = String::from_utf8_lossy(&buffer[..length]).to_string();
+                        let mut pushed = received.lock().unwrap_or_else(|e| e.into_inner());
+
+                        for line in lines.lines().filter(|line| !line.trim().is_empty()) {
+                            match parse_statsd_line(line) {
+                                Some(value) => pushed.add(value),
+                                None => eprintln!("Ignoring an invalid pushed metric: {line}"),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Stopped receiving pushed metrics: {e}");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(PushCollector { pushed })
+    }
+}
+
+#[cfg(unix)]
+impl<P: Platform> Collector<P> for PushCollector {
+    fn name(&self) -> &str {
+        "push"
+    }
+
+    fn collect(&mut self, _sys: &P) -> Vec<Metric> {
+        self.pushed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .into_iter()
+            .map(|(name, value)| Metric::new(&name, MetricValue::Custom(value)))
+            .collect()
+    }
+}
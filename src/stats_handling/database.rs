@@ -1,209 +1,1173 @@
-use std::{collections::HashSet, env};
+use std::{collections::{BTreeMap, HashMap, HashSet}, env, path::Path, sync::Arc};
 
+use async_trait::async_trait;
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
-    Pool, Row, Sqlite,
+    migrate::MigrateError,
+    sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqlitePoolOptions, SqliteQueryResult},
+    ColumnIndex, Database, Decode, Encode, Executor, FromRow, IntoArguments, Pool, Row, Sqlite, Type,
 };
 
-use crate::{constants::{self}, stats_handling::device_info::Device};
+#[cfg(feature = "postgres")]
+use crate::stats_handling::postgres;
+use crate::{
+    constants::{self},
+    json_handler::{read_server_config_value, ToServerConfig},
+    stats_handling::{
+        device_info::{Device, DeviceRecord, DiskIo, FilesystemUsage, Inventory, NetworkInterface, ProcessInfo, Rollup, Sensor},
+        storage::Storage,
+    },
+};
+
+/// Tables that hold per sample details and the inventory of a device, rows in them are removed along with the device
+const DETAIL_TABLES: [&str; 8] =
+    ["cpu_cores", "filesystems", "disk_io", "network_interfaces", "sensors", "processes", "metrics", "device_inventory"];
+
+/// Columns of the `samples` table that can be picked in a `SampleQuery`, with the value the ones that aren't picked are left at,
+/// typed like the column so every backend reads it back the same way
+const SAMPLE_COLUMNS: [(&str, &str); 16] = [
+    ("ram_used", "CAST(NULL AS BIGINT)"),
+    ("ram_total", "CAST(NULL AS BIGINT)"),
+    ("cpu_usage", "CAST(NULL AS REAL)"),
+    ("processes", "CAST(NULL AS INTEGER)"),
+    ("network_in", "CAST(NULL AS BIGINT)"),
+    ("network_out", "CAST(NULL AS BIGINT)"),
+    ("load_1", "CAST(NULL AS REAL)"),
+    ("load_5", "CAST(NULL AS REAL)"),
+    ("load_15", "CAST(NULL AS REAL)"),
+    ("cpu_freq", "CAST(NULL AS BIGINT)"),
+    ("cpu_freq_max", "CAST(NULL AS BIGINT)"),
+    ("ram_buffers", "CAST(NULL AS BIGINT)"),
+    ("ram_cached", "CAST(NULL AS BIGINT)"),
+    ("swap_total", "CAST(NULL AS BIGINT)"),
+    ("swap_used", "CAST(NULL AS BIGINT)"),
+    ("zfs_arc", "CAST(NULL AS BIGINT)"),
+];
+
+/// Which samples of a device to load and what to load of them
+pub struct SampleQuery {
+    /// Device to load the samples of
+    pub device_id: String,
+    /// Unix timestamp of the oldest sample to load
+    pub from: i64,
+    /// Unix timestamp of the newest sample to load
+    pub to: i64,
+    /// Columns of the `samples` table and per sample details, named after their tables such as `cpu_cores` or `metrics`,
+    /// to load. `None` loads everything, anything not loaded is left at `None` or empty
+    pub columns: Option<Vec<String>>,
+    /// Most samples to load, longer ranges are loaded from the finest rollup tier that fits or thinned out evenly
+    /// so the whole range is still covered. `None` loads every sample
+    pub limit: Option<usize>,
+}
+
+impl SampleQuery {
+    /// Makes a query for every sample of a device between two times, both included
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to load the samples of
+    /// * `from: i64` - Unix timestamp of the oldest sample to load
+    /// * `to: i64` - Unix timestamp of the newest sample to load
+    pub fn new(device_id: &str, from: i64, to: i64) -> SampleQuery {
+        SampleQuery {
+            device_id: device_id.to_string(),
+            from,
+            to,
+            columns: None,
+            limit: None,
+        }
+    }
+
+    /// Only loads the supplied columns and details, the device id, name and time are always loaded
+    ///
+    /// # Arguments
+    /// * `columns: &[&str]` - Columns and details to load
+    pub fn with_columns(mut self, columns: &[&str]) -> SampleQuery {
+        self.columns = Some(columns.iter().map(|column| column.to_string()).collect());
+        self
+    }
+
+    /// Caps the number of samples loaded
+    ///
+    /// # Arguments
+    /// * `limit: usize` - Most samples to load
+    pub fn with_limit(mut self, limit: usize) -> SampleQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Checks if a column or detail should be loaded
+    fn wants(&self, column: &str) -> bool {
+        self.columns.as_ref().is_none_or(|columns| columns.iter().any(|wanted| wanted == column))
+    }
+
+    /// Picks the finest rollup tier that covers the query's range in no more buckets than its limit
+    ///
+    /// # Returns
+    /// * `Some(i64)` - Bucket size of the tier
+    /// * `None` - There's no limit or even the coarsest tier has too many buckets
+    fn rollup_resolution(&self) -> Option<i64> {
+        let limit = self.limit? as i64;
+
+        constants::ROLLUP_RESOLUTIONS
+            .into_iter()
+            .find(|resolution| self.to.saturating_sub(self.from) / resolution < limit)
+    }
+}
+
+/// Makes the condition picking the rows of a table that belong to the samples a query loads
+///
+/// `$1` is the device id and `$2` and `$3` the time range. Every `step`th sample is kept when the range has more than the query's limit
+///
+/// # Arguments
+/// * `table: &str` - Table the rows are taken from
+/// * `step: i64` - Keeps one sample out of this many
+fn sample_filter(table: &str, step: i64) -> String {
+    if step <= 1 {
+        return format!("{table}.device_id = $1 AND {table}.time >= $2 AND {table}.time <= $3");
+    }
+
+    format!(
+        r#"{table}.device_id = $1 AND {table}.time IN (
+            SELECT time FROM (
+                SELECT time, ROW_NUMBER() OVER (ORDER BY time) AS position
+                FROM samples
+                WHERE device_id = $1 AND time >= $2 AND time <= $3
+            ) AS ranked
+            WHERE (position - 1) % {step} = 0
+        )"#
+    )
+}
+
+/// Sets a column of a sample from its name, whole number columns are rounded
+///
+/// # Arguments
+/// * `device: &mut Device` - Sample to set the column of
+/// * `column: &str` - Name of the column in the `samples` table
+/// * `value: f64` - Value to set it to
+fn set_sample_column(device: &mut Device, column: &str, value: f64) {
+    let whole = value.round() as i64;
 
-/// Connects to the sqlite database and runs migrations
+    match column {
+        "ram_used" => device.ram_used = Some(whole),
+        "ram_total" => device.ram_total = Some(whole),
+        "cpu_usage" => device.cpu_usage = Some(value as f32),
+        "processes" => device.processes = Some(whole as i32),
+        "network_in" => device.network_in = Some(whole),
+        "network_out" => device.network_out = Some(whole),
+        "load_1" => device.load_1 = Some(value as f32),
+        "load_5" => device.load_5 = Some(value as f32),
+        "load_15" => device.load_15 = Some(value as f32),
+        "cpu_freq" => device.cpu_freq = Some(whole),
+        "cpu_freq_max" => device.cpu_freq_max = Some(whole),
+        "ram_buffers" => device.ram_buffers = Some(whole),
+        "ram_cached" => device.ram_cached = Some(whole),
+        "swap_total" => device.swap_total = Some(whole),
+        "swap_used" => device.swap_used = Some(whole),
+        "zfs_arc" => device.zfs_arc = Some(whole),
+        _ => {}
+    }
+}
+
+/// Turns the buckets of a rollup tier into samples, each holding the averages of its bucket and timed at the start of it
+///
+/// # Arguments
+/// * `query: &SampleQuery` - What to load of the buckets, the range and limit are ignored
+/// * `device_name: &str` - Name of the device in the registry
+/// * `rollups: Vec<Rollup>` - Buckets of the device, oldest first
 ///
 /// # Returns
-/// `Pool<Sqlite>` - Interact with the database
-pub async fn start_db() -> Pool<Sqlite> {
+/// `Vec<Device>` - A sample per bucket, oldest first
+fn rollups_to_samples(query: &SampleQuery, device_name: &str, rollups: Vec<Rollup>) -> Vec<Device> {
+    let mut samples: BTreeMap<i64, Device> = BTreeMap::new();
+
+    // Details are keyed by bucket and series so they come out in the same order as from their tables
+    let mut disks: BTreeMap<(i64, String), DiskIo> = BTreeMap::new();
+    let mut interfaces: BTreeMap<(i64, String), NetworkInterface> = BTreeMap::new();
+    let mut sensors: BTreeMap<(i64, String), Sensor> = BTreeMap::new();
+
+    for Rollup { time, source, series, field, avg_value: value, .. } in rollups {
+        let device = samples
+            .entry(time)
+            .or_insert_with(|| Device::empty(&query.device_id, device_name, time));
+
+        if !query.wants(if source == "samples" { &field } else { &source }) {
+            continue;
+        }
+
+        let whole = value.round() as i64;
+
+        match source.as_str() {
+            "samples" => set_sample_column(device, &field, value),
+            "cpu_cores" => {
+                if let Ok(core) = series.parse::<usize>() {
+                    if device.cpu_cores.len() <= core {
+                        device.cpu_cores.resize(core + 1, 0.0);
+                    }
+                    device.cpu_cores[core] = value as f32;
+                }
+            }
+            "disk_io" => {
+                let disk = disks.entry((time, series.clone())).or_insert_with(|| DiskIo { disk: series, ..Default::default() });
+
+                match field.as_str() {
+                    "read_bytes" => disk.read_bytes = whole,
+                    "write_bytes" => disk.write_bytes = whole,
+                    "read_ops" => disk.read_ops = whole,
+                    "write_ops" => disk.write_ops = whole,
+                    _ => {}
+                }
+            }
+            "network_interfaces" => {
+                let interface = interfaces
+                    .entry((time, series.clone()))
+                    .or_insert_with(|| NetworkInterface { interface: series, ..Default::default() });
+
+                match field.as_str() {
+                    "rx_bytes" => interface.rx_bytes = whole,
+                    "tx_bytes" => interface.tx_bytes = whole,
+                    "rx_packets" => interface.rx_packets = whole,
+                    "tx_packets" => interface.tx_packets = whole,
+                    "rx_errors" => interface.rx_errors = value as f32,
+                    "tx_errors" => interface.tx_errors = value as f32,
+                    "rx_drops" => interface.rx_drops = value as f32,
+                    "tx_drops" => interface.tx_drops = value as f32,
+                    _ => {}
+                }
+            }
+            "sensors" => {
+                sensors.insert((time, series.clone()), Sensor { label: series, kind: field, value: value as f32 });
+            }
+            "metrics" => {
+                device.metrics.insert(series, value);
+            }
+            _ => {}
+        }
+    }
+
+    for ((time, _), disk) in disks {
+        if let Some(device) = samples.get_mut(&time) {
+            device.disk_io.push(disk);
+        }
+    }
+
+    for ((time, _), interface) in interfaces {
+        if let Some(device) = samples.get_mut(&time) {
+            device.interfaces.push(interface);
+        }
+    }
+
+    for ((time, _), sensor) in sensors {
+        if let Some(device) = samples.get_mut(&time) {
+            device.sensors.push(sensor);
+        }
+    }
+
+    samples.into_values().collect()
+}
+
+/// A detail table whose values are rolled up
+struct RollupDetail {
+    /// Table the values are read from
+    table: &'static str,
+    /// Column telling apart the rows of one sample
+    series: &'static str,
+    /// Column naming the value instead of the column it's read from
+    field: Option<&'static str>,
+    /// Columns holding the values
+    columns: &'static [&'static str],
+}
+
+/// Detail tables that are rolled up along with the columns of `samples`, filesystems and processes are only shown for the latest sample
+const ROLLUP_DETAILS: [RollupDetail; 5] = [
+    RollupDetail { table: "cpu_cores", series: "core", field: None, columns: &["core_usage"] },
+    RollupDetail { table: "disk_io", series: "disk", field: None, columns: &["read_bytes", "write_bytes", "read_ops", "write_ops"] },
+    RollupDetail {
+        table: "network_interfaces",
+        series: "interface",
+        field: None,
+        columns: &["rx_bytes", "tx_bytes", "rx_packets", "tx_packets", "rx_errors", "tx_errors", "rx_drops", "tx_drops"],
+    },
+    RollupDetail { table: "sensors", series: "label", field: Some("kind"), columns: &["value"] },
+    RollupDetail { table: "metrics", series: "name", field: None, columns: &["value"] },
+];
+
+/// Makes the statement rolling a column of a table up into the finest tier
+///
+/// `$1` is the device id, `$2` the start of the first bucket and `$3` the bucket size, rows missing the value are left out
+///
+/// # Arguments
+/// * `table: &str` - Table the values are read from
+/// * `series: Option<&str>` - Column telling apart the rows of one sample, `None` for `samples`
+/// * `field: Option<&str>` - Column naming the value, `None` names it after `column`
+/// * `column: &str` - Column holding the values
+fn roll_up_column(table: &str, series: Option<&str>, field: Option<&str>, column: &str) -> String {
+    let group: String = series.iter().chain(field.iter()).map(|group| format!("{group}, ")).collect();
+    let join: String = series.iter().chain(field.iter()).map(|key| format!(" AND latest.{key} = grouped.{key}")).collect();
+
+    format!(
+        r#"
+        INSERT INTO rollups (device_id, resolution, time, source, series, field, min_value, max_value, avg_value, last_value, sample_count)
+        SELECT grouped.device_id, $3, grouped.bucket, '{table}', CAST(grouped.series_name AS VARCHAR(255)), grouped.field_name,
+            grouped.min_value, grouped.max_value, grouped.avg_value, latest.{column}, grouped.sample_count
+        FROM (
+            SELECT device_id, {group}{} AS series_name, {} AS field_name, time / $3 * $3 AS bucket,
+                MIN({column}) AS min_value, MAX({column}) AS max_value, AVG({column}) AS avg_value,
+                COUNT(*) AS sample_count, MAX(time) AS last_time
+            FROM {table}
+            WHERE device_id = $1 AND time >= $2 AND {column} IS NOT NULL
+            GROUP BY device_id, {group}time / $3 * $3
+        ) AS grouped
+        JOIN {table} AS latest ON latest.device_id = grouped.device_id AND latest.time = grouped.last_time{join}
+        "#,
+        series.unwrap_or("''"),
+        field.map_or(format!("'{column}'"), str::to_string),
+    )
+}
+
+/// Tables pruned along with `samples`, the inventory is only stored when it changes so it's kept
+fn pruned_tables() -> Vec<&'static str> {
+    std::iter::once("samples").chain(DETAIL_TABLES.into_iter().filter(|table| *table != "device_inventory")).collect()
+}
+
+/// A database the SQL storage can run on, the queries are shared and only what differs between databases is here
+#[async_trait]
+pub trait Backend: Database {
+    /// Runs the migrations that haven't been applied yet
+    ///
+    /// # Arguments
+    /// * `pool: &Pool<Self>` - Database to migrate
+    async fn migrate(pool: &Pool<Self>) -> Result<(), MigrateError>;
+
+    /// Hands the space freed by pruning back to the filesystem
+    ///
+    /// # Arguments
+    /// * `pool: &Pool<Self>` - Database to vacuum
+    async fn reclaim_space(pool: &Pool<Self>) -> Result<(), sqlx::Error>;
+
+    /// Number of rows a statement changed
+    ///
+    /// # Arguments
+    /// * `result: &Self::QueryResult` - Result of the statement
+    fn rows_affected(result: &Self::QueryResult) -> u64;
+}
+
+#[async_trait]
+impl Backend for Sqlite {
+    async fn migrate(pool: &Pool<Sqlite>) -> Result<(), MigrateError> {
+        sqlx::migrate!("./migrations").run(pool).await
+    }
+
+    /// New databases are made with incremental auto vacuum, older ones are rebuilt with a full `VACUUM` once to switch them over
+    async fn reclaim_space(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        // The mode only changes for the connection that sets it, so everything runs on one
+        let mut connection = pool.acquire().await?;
+
+        let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(&mut *connection).await?;
+
+        // 2 is incremental
+        if auto_vacuum == 2 {
+            sqlx::query("PRAGMA incremental_vacuum").execute(&mut *connection).await?;
+        } else {
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL").execute(&mut *connection).await?;
+            sqlx::query("VACUUM").execute(&mut *connection).await?;
+        }
+
+        Ok(())
+    }
+
+    fn rows_affected(result: &SqliteQueryResult) -> u64 {
+        result.rows_affected()
+    }
+}
+
+/// `Storage` kept in a SQL database
+pub struct SqlStorage<DB: Database> {
+    /// Connections to the database, the pool is already safe to share between tasks
+    pool: Pool<DB>,
+}
+
+impl<DB: Database> SqlStorage<DB> {
+    /// Makes a new `SqlStorage` instance, migrations aren't run until `migrate` is called
+    ///
+    /// # Arguments
+    /// * `pool: Pool<DB>` - Database to store everything in
+    pub fn new(pool: Pool<DB>) -> SqlStorage<DB> {
+        SqlStorage { pool }
+    }
+
+    /// Returns the database the storage executes its queries on
+    pub fn pool(&self) -> &Pool<DB> {
+        &self.pool
+    }
+}
+
+/// Connects to the database set by `databaseUrl` in the server config, or the sqlite database if there's none, and runs migrations
+///
+/// # Returns
+/// `Arc<dyn Storage>` - Interact with the database
+pub async fn start_db() -> Arc<dyn Storage> {
     unsafe {
         env::set_var("DATABASE_URL", "sqlite://database.sqlite");
     }
 
-    let db_path = if cfg!(debug_assertions) {
-        "database.sqlite".to_string()
+    // Clients don't have a server config, reading it would make one
+    let database_url = if Path::new(&constants::get_server_config_path()).exists() {
+        read_server_config_value().to_server().database_url
     } else {
-        constants::get_db_path()
+        String::new()
     };
 
-    let database = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect_with(
-            SqliteConnectOptions::new()
-                .filename(db_path)
-                .create_if_missing(true),
-        )
-        .await
-        .expect("Couldn't connect to database");
+    let storage: Arc<dyn Storage> = if database_url.starts_with("postgres") {
+        connect_postgres(&database_url).await
+    } else {
+        let db_path = if cfg!(debug_assertions) {
+            "database.sqlite".to_string()
+        } else {
+            constants::get_db_path()
+        };
 
+        let database = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(db_path)
+                    .create_if_missing(true)
+                    .auto_vacuum(SqliteAutoVacuum::Incremental),
+            )
+            .await
+            .expect("Couldn't connect to database");
 
+        Arc::new(SqlStorage::new(database))
+    };
 
-    match sqlx::migrate!("./migrations").run(&database).await {
+    match storage.migrate().await {
         Ok(_) => {}
         Err(e) => eprintln!("Migration Error: {}", e),
     };
 
-    database
+    storage
 }
 
-/// Inserts data into the database
+/// Connects to a PostgreSQL database
 ///
 /// # Arguments
-/// * `database: &Pool<Sqlite>` - Database to use to execute
-/// * `device: Device` - Struct to insert
-///
-/// # Returns
-/// * `Ok()` - Insertion succeeds
-/// * `Err(sqlx::Error)` - Insertion fails
-pub async fn input_data(database: &Pool<Sqlite>, device: Device) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        r#"
-        INSERT INTO devices (device_id, device_name, ram_used, ram_total, cpu_usage, processes, network_in, network_out, time)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-        "#
-    )
-    .bind(device.device_id)
-    .bind(device.device_name)
-    .bind(device.ram_used)
-    .bind(device.ram_total)
-    .bind(device.cpu_usage)
-    .bind(device.processes)
-    .bind(device.network_in)
-    .bind(device.network_out)
-    .bind(device.time).execute(&*database)
-    .await?;
-
-    Ok(())
-}
-
-pub async fn check_device_id_exists(id: &String, database: &Pool<Sqlite>) -> bool {
-    let query = r#"
-        SELECT * FROM devices
-        WHERE id = ?1
-        ORDER BY RANDOM()
-        LIMIT 1
-    "#;
-
-    match sqlx::query_scalar::<_, String>(query)
-        .bind(id)
-        .fetch_optional(&*database)
+/// * `url: &str` - URL of the database, such as `postgres://rlsd@localhost/rlsd`
+#[cfg(feature = "postgres")]
+async fn connect_postgres(url: &str) -> Arc<dyn Storage> {
+    Arc::new(SqlStorage::new(postgres::connect(url).await))
+}
+
+/// Exits, PostgreSQL support wasn't built in
+#[cfg(not(feature = "postgres"))]
+async fn connect_postgres(_url: &str) -> Arc<dyn Storage> {
+    eprintln!("databaseUrl is set to a PostgreSQL database but rlsd was built without the postgres feature");
+    std::process::exit(1)
+}
+
+// Queries use `$N` parameters, which every backend accepts, and cast what a backend could read back as a different type
+#[async_trait]
+impl<DB> Storage for SqlStorage<DB>
+where
+    DB: Backend,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'q> i32: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> f32: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> f64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> Option<i32>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<f32>: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'r> Device: FromRow<'r, DB::Row>,
+    for<'r> DeviceRecord: FromRow<'r, DB::Row>,
+    for<'r> DiskIo: FromRow<'r, DB::Row>,
+    for<'r> FilesystemUsage: FromRow<'r, DB::Row>,
+    for<'r> Inventory: FromRow<'r, DB::Row>,
+    for<'r> NetworkInterface: FromRow<'r, DB::Row>,
+    for<'r> ProcessInfo: FromRow<'r, DB::Row>,
+    for<'r> Rollup: FromRow<'r, DB::Row>,
+    for<'r> Sensor: FromRow<'r, DB::Row>,
+    for<'s> &'s str: ColumnIndex<DB::Row>,
+    usize: ColumnIndex<DB::Row>,
+{
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        DB::migrate(&self.pool).await
+    }
+
+    async fn input_data(&self, devices: &[Device]) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        for device in devices {
+            // Spooled samples can arrive after newer ones, so the times only ever widen
+            sqlx::query(
+                r#"
+                INSERT INTO devices (device_id, device_name, created_at, last_seen, metadata)
+                VALUES ($1, $2, $3, $3, '{}')
+                ON CONFLICT (device_id) DO UPDATE SET
+                    created_at = CASE WHEN excluded.created_at < devices.created_at THEN excluded.created_at ELSE devices.created_at END,
+                    last_seen = CASE WHEN excluded.last_seen > devices.last_seen THEN excluded.last_seen ELSE devices.last_seen END
+                "#
+            )
+            .bind(&device.device_id)
+            .bind(&device.device_name)
+            .bind(device.time)
+            .execute(&mut *transaction)
+            .await?;
+
+            // A batch the server stored but couldn't confirm is sent again from the client's spool,
+            // samples that are already stored are skipped along with their details so the replay succeeds
+            let inserted = sqlx::query(
+                r#"
+                INSERT INTO samples (device_id, ram_used, ram_total, cpu_usage, processes, network_in, network_out, time,
+                    load_1, load_5, load_15, cpu_freq, cpu_freq_max, ram_buffers, ram_cached, swap_total, swap_used, zfs_arc)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+                ON CONFLICT (device_id, time) DO NOTHING
+                "#
+            )
+            .bind(&device.device_id)
+            .bind(device.ram_used)
+            .bind(device.ram_total)
+            .bind(device.cpu_usage)
+            .bind(device.processes)
+            .bind(device.network_in)
+            .bind(device.network_out)
+            .bind(device.time)
+            .bind(device.load_1)
+            .bind(device.load_5)
+            .bind(device.load_15)
+            .bind(device.cpu_freq)
+            .bind(device.cpu_freq_max)
+            .bind(device.ram_buffers)
+            .bind(device.ram_cached)
+            .bind(device.swap_total)
+            .bind(device.swap_used)
+            .bind(device.zfs_arc)
+            .execute(&mut *transaction)
+            .await?;
+
+            if DB::rows_affected(&inserted) == 0 {
+                continue;
+            }
+
+            for (core, usage) in device.cpu_cores.iter().enumerate() {
+                sqlx::query("INSERT INTO cpu_cores (device_id, time, core, core_usage) VALUES ($1, $2, $3, $4)")
+                    .bind(&device.device_id)
+                    .bind(device.time)
+                    .bind(core as i64)
+                    .bind(usage)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+
+            for filesystem in &device.filesystems {
+                sqlx::query(
+                    r#"
+                    INSERT INTO filesystems (device_id, time, mount_point, fs_type, total, used, free, inodes_total, inodes_used)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                    "#
+                )
+                .bind(&device.device_id)
+                .bind(device.time)
+                .bind(&filesystem.mount_point)
+                .bind(&filesystem.fs_type)
+                .bind(filesystem.total)
+                .bind(filesystem.used)
+                .bind(filesystem.free)
+                .bind(filesystem.inodes_total)
+                .bind(filesystem.inodes_used)
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            for disk in &device.disk_io {
+                sqlx::query(
+                    r#"
+                    INSERT INTO disk_io (device_id, time, disk, read_bytes, write_bytes, read_ops, write_ops)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    "#
+                )
+                .bind(&device.device_id)
+                .bind(device.time)
+                .bind(&disk.disk)
+                .bind(disk.read_bytes)
+                .bind(disk.write_bytes)
+                .bind(disk.read_ops)
+                .bind(disk.write_ops)
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            for interface in &device.interfaces {
+                sqlx::query(
+                    r#"
+                    INSERT INTO network_interfaces (device_id, time, interface, rx_bytes, tx_bytes, rx_packets, tx_packets,
+                        rx_errors, tx_errors, rx_drops, tx_drops)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                    "#
+                )
+                .bind(&device.device_id)
+                .bind(device.time)
+                .bind(&interface.interface)
+                .bind(interface.rx_bytes)
+                .bind(interface.tx_bytes)
+                .bind(interface.rx_packets)
+                .bind(interface.tx_packets)
+                .bind(interface.rx_errors)
+                .bind(interface.tx_errors)
+                .bind(interface.rx_drops)
+                .bind(interface.tx_drops)
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            for sensor in &device.sensors {
+                sqlx::query("INSERT INTO sensors (device_id, time, label, kind, value) VALUES ($1, $2, $3, $4, $5)")
+                    .bind(&device.device_id)
+                    .bind(device.time)
+                    .bind(&sensor.label)
+                    .bind(&sensor.kind)
+                    .bind(sensor.value)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+
+            for process in &device.top_processes {
+                sqlx::query(
+                    "INSERT INTO processes (device_id, time, pid, name, user_name, cpu_usage, rss, cmdline) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                )
+                .bind(&device.device_id)
+                .bind(device.time)
+                .bind(process.pid)
+                .bind(&process.name)
+                .bind(&process.user_name)
+                .bind(process.cpu_usage)
+                .bind(process.rss)
+                .bind(&process.cmdline)
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            for (name, value) in &device.metrics {
+                sqlx::query("INSERT INTO metrics (device_id, time, name, value) VALUES ($1, $2, $3, $4)")
+                    .bind(&device.device_id)
+                    .bind(device.time)
+                    .bind(name)
+                    .bind(value)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+        }
+
+        transaction.commit().await
+    }
+
+    async fn check_device_id_exists(&self, id: &str) -> bool {
+        sqlx::query_scalar::<_, String>("SELECT device_id FROM devices WHERE device_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .is_ok_and(|row| row.is_some())
+    }
+
+    async fn get_all_device_uids(&self) -> HashSet<String> {
+        let mut uids = HashSet::new();
+
+        let rows = sqlx::query("SELECT device_id FROM devices")
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch device IDs");
+
+        for row in rows {
+            let device_id = row.get::<String, _>("device_id");
+
+            uids.insert(device_id);
+        }
+
+        uids
+    }
+
+    async fn get_devices(&self) -> Vec<DeviceRecord> {
+        sqlx::query_as::<_, DeviceRecord>("SELECT * FROM devices ORDER BY device_name ASC, device_id ASC")
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch devices")
+    }
+
+    async fn get_device_name_from_uid(&self, device_id: &str) -> String {
+        let row = sqlx::query("SELECT device_name FROM devices WHERE device_id = $1")
+            .bind(device_id)
+            .fetch_one(&self.pool)
+            .await
+            .expect("Name not found");
+
+        row.get("device_name")
+    }
+
+    async fn get_samples(&self, query: &SampleQuery) -> Vec<Device> {
+        let step = match query.limit {
+            Some(limit) => {
+                let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM samples WHERE device_id = $1 AND time >= $2 AND time <= $3")
+                    .bind(&query.device_id)
+                    .bind(query.from)
+                    .bind(query.to)
+                    .fetch_one(&self.pool)
+                    .await
+                    .expect("Failed to count samples");
+
+                // Averages over buckets keep the peaks and dips that thinning out would skip,
+                // samples are only thinned out if the range hasn't been rolled up yet
+                if count as usize > limit {
+                    if let Some(resolution) = query.rollup_resolution() {
+                        let rollups = self.get_rollups(&query.device_id, resolution, query.from, query.to).await;
+
+                        if !rollups.is_empty() {
+                            let device_name: String = sqlx::query_scalar("SELECT device_name FROM devices WHERE device_id = $1")
+                                .bind(&query.device_id)
+                                .fetch_optional(&self.pool)
+                                .await
+                                .expect("Failed to fetch the device name")
+                                .unwrap_or_default();
+
+                            return rollups_to_samples(query, &device_name, rollups);
+                        }
+                    }
+                }
+
+                (count as usize).div_ceil(limit.max(1)).max(1) as i64
+            }
+            None => 1,
+        };
+
+        let columns: Vec<String> = SAMPLE_COLUMNS
+            .iter()
+            .map(|(column, zero)| if query.wants(column) { format!("samples.{column}") } else { format!("{zero} AS {column}") })
+            .collect();
+
+        let mut rows = sqlx::query_as::<_, Device>(&format!(
+            r#"
+            SELECT samples.device_id, samples.time, devices.device_name, {}
+            FROM samples
+            JOIN devices ON devices.device_id = samples.device_id
+            WHERE {}
+            ORDER BY samples.time ASC
+            "#,
+            columns.join(", "),
+            sample_filter("samples", step)
+        ))
+        .bind(&query.device_id)
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(&self.pool)
         .await
-    {
-        // Found an entry matching this id
-        Ok(_) => false,
-        // Didn't find an entry matching this id
-        Err(_) => true,
+        .expect("Failed to fetch device stats");
+
+        // Groups each detail by the sample it belongs to
+        let mut cores: HashMap<i64, Vec<f32>> = HashMap::new();
+
+        if query.wants("cpu_cores") {
+            let core_rows = sqlx::query(&format!(
+                "SELECT time, core_usage FROM cpu_cores WHERE {} ORDER BY time ASC, core ASC",
+                sample_filter("cpu_cores", step)
+            ))
+            .bind(&query.device_id)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch core usage");
+
+            for row in core_rows {
+                cores.entry(row.get("time")).or_default().push(row.get("core_usage"));
+            }
+        }
+
+        let mut disks: HashMap<i64, Vec<DiskIo>> = HashMap::new();
+
+        if query.wants("disk_io") {
+            let disk_rows = sqlx::query(&format!(
+                "SELECT * FROM disk_io WHERE {} ORDER BY time ASC, disk ASC",
+                sample_filter("disk_io", step)
+            ))
+            .bind(&query.device_id)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch disk I/O");
+
+            for row in disk_rows {
+                let disk = DiskIo::from_row(&row).expect("Invalid disk I/O row");
+
+                disks.entry(row.get("time")).or_default().push(disk);
+            }
+        }
+
+        let mut interfaces: HashMap<i64, Vec<NetworkInterface>> = HashMap::new();
+
+        if query.wants("network_interfaces") {
+            let interface_rows = sqlx::query(&format!(
+                "SELECT * FROM network_interfaces WHERE {} ORDER BY time ASC, interface ASC",
+                sample_filter("network_interfaces", step)
+            ))
+            .bind(&query.device_id)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch network interfaces");
+
+            for row in interface_rows {
+                let interface = NetworkInterface::from_row(&row).expect("Invalid network interface row");
+
+                interfaces.entry(row.get("time")).or_default().push(interface);
+            }
+        }
+
+        let mut sensors: HashMap<i64, Vec<Sensor>> = HashMap::new();
+
+        if query.wants("sensors") {
+            let sensor_rows = sqlx::query(&format!(
+                "SELECT * FROM sensors WHERE {} ORDER BY time ASC, label ASC",
+                sample_filter("sensors", step)
+            ))
+            .bind(&query.device_id)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch sensors");
+
+            for row in sensor_rows {
+                let sensor = Sensor::from_row(&row).expect("Invalid sensor row");
+
+                sensors.entry(row.get("time")).or_default().push(sensor);
+            }
+        }
+
+        let mut metrics: HashMap<i64, BTreeMap<String, f64>> = HashMap::new();
+
+        if query.wants("metrics") {
+            let metric_rows = sqlx::query(&format!(
+                "SELECT time, name, CAST(value AS DOUBLE PRECISION) AS value FROM metrics WHERE {}",
+                sample_filter("metrics", step)
+            ))
+            .bind(&query.device_id)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&self.pool)
+            .await
+            .expect("Failed to fetch custom metrics");
+
+            for row in metric_rows {
+                metrics.entry(row.get("time")).or_default().insert(row.get("name"), row.get("value"));
+            }
+        }
+
+        for device in rows.iter_mut() {
+            device.cpu_cores = cores.remove(&device.time).unwrap_or_default();
+            device.disk_io = disks.remove(&device.time).unwrap_or_default();
+            device.interfaces = interfaces.remove(&device.time).unwrap_or_default();
+            device.sensors = sensors.remove(&device.time).unwrap_or_default();
+            device.metrics = metrics.remove(&device.time).unwrap_or_default();
+        }
+
+        rows
     }
-}
 
-/// Get all the different device uids
-///
-/// # Arguments
-/// * `database: &Pool<Sqlite>` - Database to execute the query
-///
-/// # Returns
-/// `HashSet<String>` - Contains all the different device uids
-pub async fn get_all_device_uids(database: &Pool<Sqlite>) -> HashSet<String> {
-    let mut uids = HashSet::new();
+    async fn get_rollups(&self, device_id: &str, resolution: i64, from: i64, to: i64) -> Vec<Rollup> {
+        sqlx::query_as::<_, Rollup>(
+            r#"
+            SELECT time, source, series, field, CAST(min_value AS DOUBLE PRECISION) AS min_value,
+                CAST(max_value AS DOUBLE PRECISION) AS max_value, CAST(avg_value AS DOUBLE PRECISION) AS avg_value,
+                CAST(last_value AS DOUBLE PRECISION) AS last_value, sample_count
+            FROM rollups
+            WHERE device_id = $1 AND resolution = $2 AND time >= $3 AND time <= $4
+            ORDER BY time ASC, source ASC, series ASC, field ASC
+            "#,
+        )
+        .bind(device_id)
+        .bind(resolution)
+        .bind(from / resolution * resolution)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to fetch rollups")
+    }
 
-    let rows = sqlx::query("SELECT device_id FROM devices")
-        .fetch_all(database)
+    async fn get_latest_filesystems(&self, device_id: &str) -> Vec<FilesystemUsage> {
+        sqlx::query_as::<_, FilesystemUsage>(
+            r#"
+            SELECT mount_point, fs_type, total, used, free, inodes_total, inodes_used
+            FROM filesystems
+            WHERE device_id = $1 AND time = (SELECT MAX(time) FROM filesystems WHERE device_id = $1)
+            ORDER BY mount_point ASC
+            "#,
+        )
+        .bind(device_id)
+        .fetch_all(&self.pool)
         .await
-        .expect("Failed to fetch device IDs");
+        .expect("Failed to fetch filesystems")
+    }
+
+    async fn input_inventory(&self, device_id: &str, inventory: &Inventory) -> Result<bool, sqlx::Error> {
+        if self.get_inventory(device_id).await.is_some_and(|latest| !inventory.changed_from(&latest)) {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO device_inventory (device_id, time, hostname, os, kernel, arch, cpu_model, cpu_cores, cpu_threads, ram_total, boot_time, version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+        .bind(device_id)
+        .bind(inventory.time)
+        .bind(&inventory.hostname)
+        .bind(&inventory.os)
+        .bind(&inventory.kernel)
+        .bind(&inventory.arch)
+        .bind(&inventory.cpu_model)
+        .bind(inventory.cpu_cores)
+        .bind(inventory.cpu_threads)
+        .bind(inventory.ram_total)
+        .bind(inventory.boot_time)
+        .bind(&inventory.version)
+        .execute(&self.pool)
+        .await?;
 
-    for row in rows {
-        let device_id = row.get::<String, _>("device_id");
+        Ok(true)
+    }
 
-        uids.insert(device_id);
+    async fn get_inventory(&self, device_id: &str) -> Option<Inventory> {
+        sqlx::query_as::<_, Inventory>(
+            r#"
+            SELECT *
+            FROM device_inventory
+            WHERE device_id = $1
+            ORDER BY time DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(device_id)
+        .fetch_optional(&self.pool)
+        .await
+        .expect("Failed to fetch inventory")
     }
 
-    uids
-}
+    async fn get_processes_at(&self, device_id: &str, time: i64) -> Vec<ProcessInfo> {
+        sqlx::query_as::<_, ProcessInfo>(
+            r#"
+            SELECT pid, name, user_name, cpu_usage, rss, cmdline
+            FROM processes
+            WHERE device_id = $1 AND time = (SELECT MAX(time) FROM processes WHERE device_id = $1 AND time <= $2)
+            ORDER BY cpu_usage DESC, rss DESC
+            "#,
+        )
+        .bind(device_id)
+        .bind(time)
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to fetch processes")
+    }
+
+    async fn remove_device(&self, device_id: &str) -> Result<u64, sqlx::Error> {    
+        let mut transaction = self.pool.begin().await?;
+
+        for table in DETAIL_TABLES {
+            sqlx::query(&format!("DELETE FROM {table} WHERE device_id = $1"))
+                .bind(device_id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        sqlx::query("DELETE FROM rollups WHERE device_id = $1")
+            .bind(device_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        let samples = sqlx::query("DELETE FROM samples WHERE device_id = $1")
+            .bind(device_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        let registry = sqlx::query("DELETE FROM devices WHERE device_id = $1")
+            .bind(device_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
 
-pub async fn get_device_name_from_uid(
-    database: &Pool<Sqlite>,
-    device_id: &str,
-) -> String {
-    let row = sqlx::query("SELECT device_name FROM devices WHERE device_id = ?1")
+        Ok(DB::rows_affected(&samples) + DB::rows_affected(&registry))
+    }
+
+    async fn rename_device(&self, device_id: &str, device_name: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE devices
+            SET device_name = $1
+            WHERE device_id = $2
+            "#
+        )
+        .bind(device_name)
         .bind(device_id)
-        .fetch_one(&*database)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(DB::rows_affected(&result))
+    }
+
+    async fn roll_up(&self, device_id: &str, since: i64) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let mut finer: Option<i64> = None;
+
+        for resolution in constants::ROLLUP_RESOLUTIONS {
+            let start = since / resolution * resolution;
+
+            sqlx::query("DELETE FROM rollups WHERE device_id = $1 AND resolution = $2 AND time >= $3")
+                .bind(device_id)
+                .bind(resolution)
+                .bind(start)
+                .execute(&mut *transaction)
+                .await?;
+
+            let statements = match finer {
+                None => SAMPLE_COLUMNS
+                    .iter()
+                    .map(|(column, _)| roll_up_column("samples", None, None, column))
+                    .chain(ROLLUP_DETAILS.iter().flat_map(|detail| {
+                        detail.columns.iter().map(|column| roll_up_column(detail.table, Some(detail.series), detail.field, column))
+                    }))
+                    .collect(),
+                // Averages are weighted by how many samples each finer bucket holds
+                Some(_) => vec![
+                    r#"
+                    INSERT INTO rollups (device_id, resolution, time, source, series, field, min_value, max_value, avg_value, last_value, sample_count)
+                    SELECT grouped.device_id, $3, grouped.bucket, grouped.source, grouped.series, grouped.field,
+                        grouped.min_value, grouped.max_value, grouped.avg_value, latest.last_value, grouped.sample_count
+                    FROM (
+                        SELECT device_id, source, series, field, time / $3 * $3 AS bucket,
+                            MIN(min_value) AS min_value, MAX(max_value) AS max_value,
+                            SUM(CAST(avg_value AS DOUBLE PRECISION) * sample_count) / SUM(sample_count) AS avg_value,
+                            SUM(sample_count) AS sample_count, MAX(time) AS last_time
+                        FROM rollups
+                        WHERE device_id = $1 AND resolution = $4 AND time >= $2
+                        GROUP BY device_id, source, series, field, time / $3 * $3
+                    ) AS grouped
+                    JOIN rollups AS latest ON latest.device_id = grouped.device_id AND latest.resolution = $4 AND latest.time = grouped.last_time
+                        AND latest.source = grouped.source AND latest.series = grouped.series AND latest.field = grouped.field
+                    "#
+                    .to_string(),
+                ],
+            };
+
+            for statement in statements {
+                let query = sqlx::query(&statement).bind(device_id).bind(start).bind(resolution);
+
+                // Some backends reject parameters a statement doesn't use, only the coarser tiers read the finer one
+                let query = match finer {
+                    Some(finer) => query.bind(finer),
+                    None => query,
+                };
+
+                query.execute(&mut *transaction).await?;
+            }
+
+            finer = Some(resolution);
+        }
+
+        transaction.commit().await
+    }
+
+    async fn get_rollup_starts(&self) -> HashMap<String, i64> {
+        sqlx::query(
+            r#"
+            SELECT devices.device_id, COALESCE(MAX(rollups.time), devices.created_at) AS since
+            FROM devices
+            LEFT JOIN rollups ON rollups.device_id = devices.device_id AND rollups.resolution = $1
+            GROUP BY devices.device_id, devices.created_at
+            "#,
+        )
+        .bind(constants::ROLLUP_RESOLUTIONS[0])
+        .fetch_all(&self.pool)
         .await
-        .expect("Name not found");
+        .expect("Failed to find where to roll up from")
+        .iter()
+        .map(|row| (row.get("device_id"), row.get("since")))
+        .collect()
+    }
 
-    row.get("device_name")
-}
+    async fn count_expired(&self, device_id: &str, samples_before: Option<i64>, rollups_before: Option<i64>) -> Result<(u64, u64), sqlx::Error> {
+        let mut counts = [0; 2];
 
-pub async fn get_device_stats_after(
-    database: &Pool<Sqlite>,
-    device_id: &str,
-    since_timestamp: i64,
-) -> Vec<Device> {
-    let rows = sqlx::query_as::<_, Device>(
-        r#"
-        SELECT *
-        FROM devices
-        WHERE device_id = ?1
-        ORDER BY time ASC
-        "#,
-    )
-    .bind(device_id)
-    .bind(since_timestamp)
-    .fetch_all(database)
-    .await
-    .expect("Failed to fetch device stats");
+        for (count, (table, before)) in counts.iter_mut().zip([("samples", samples_before), ("rollups", rollups_before)]) {
+            let Some(before) = before else {
+                continue;
+            };
 
-    rows
-}
+            let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table} WHERE device_id = $1 AND time < $2"))
+                .bind(device_id)
+                .bind(before)
+                .fetch_one(&self.pool)
+                .await?;
 
-/// Removes all rows with the supplied device_id
-/// 
-/// # Arguments
-/// * `database: &Pool<Sqlite>` - Database to execute the query on
-/// * `device_id: &str` - Device id to search for
-pub async fn remove_device(database: &Pool<Sqlite>, device_id: &str) -> String {    
-    
-    match sqlx::query(
-        r#"
-        DELETE FROM devices WHERE device_id = ?1;
-    "#,
-    )
-    .bind(device_id)
-    .execute(&*database)
-    .await
-    .ok() {
-        Some(v) => {
-            if v.rows_affected() == 0 {
-                format!("No rows found matching id: {device_id}")
-            } else {
-                format!("Removed {} rows for device {device_id}", v.rows_affected())
+            *count = rows as u64;
+        }
+
+        Ok((counts[0], counts[1]))
+    }
+
+    async fn prune_device(&self, device_id: &str, samples_before: Option<i64>, rollups_before: Option<i64>) -> Result<(u64, u64), sqlx::Error> {
+        let mut removed = [0; 2];
+
+        // The first table of each set sets the batches and the rest follow it by time, only its rows are counted
+        let samples = pruned_tables();
+        let sets: [(&[&str], Option<i64>); 2] = [(&samples, samples_before), (&["rollups"], rollups_before)];
+
+        for (count, (tables, before)) in removed.iter_mut().zip(sets) {
+            let Some(before) = before else {
+                continue;
+            };
+
+            loop {
+                // Rows sharing the time at the end of a batch go with it so every batch removes something
+                let batch_end: Option<i64> = sqlx::query_scalar(&format!(
+                    "SELECT time FROM {} WHERE device_id = $1 AND time < $2 ORDER BY time ASC LIMIT 1 OFFSET $3",
+                    tables[0]
+                ))
+                .bind(device_id)
+                .bind(before)
+                .bind(constants::PRUNE_BATCH_SIZE)
+                .fetch_optional(&self.pool)
+                .await?;
+
+                let mut transaction = self.pool.begin().await?;
+
+                for (index, table) in tables.iter().enumerate() {
+                    let result = sqlx::query(&format!("DELETE FROM {table} WHERE device_id = $1 AND time <= $2"))
+                        .bind(device_id)
+                        .bind(batch_end.unwrap_or(before - 1))
+                        .execute(&mut *transaction)
+                        .await?;
+
+                    if index == 0 {
+                        *count += DB::rows_affected(&result);
+                    }
+                }
+
+                transaction.commit().await?;
+
+                if batch_end.is_none() {
+                    break;
+                }
             }
-        },
-        None => format!("Error removing device")
+        }
+
+        Ok((removed[0], removed[1]))
     }
-}
 
-/// Changes the device_name of all rows matching the supplied device_id
-/// 
-/// # Arguments
-/// * `database: &Pool<Sqlite>` - Database to execute the query on
-/// * `device_id: &str` - Device id to search for
-/// * `device_name: &str` - Name to change the all the values to
-pub async fn rename_device(database: &Pool<Sqlite>, device_id: &str, device_name: &str) -> String {
-    match sqlx::query(
-        r#"
-        UPDATE devices
-        SET device_name = ?1
-        WHERE device_id = ?2
-        "#
-    )
-    .bind(device_name)
-    .bind(device_id)
-    .execute(&*database)
-    .await
-    .ok() {
-        Some(v) => {    
-            if v.rows_affected() == 0 {
-                format!("No rows found matching id: {device_id}")
-            } else {
-                format!("Changed device name for {device_id} to {device_name}\n{} rows affected", v.rows_affected())
-            }
-        },
-        None => "Error renaming device".to_string()
-    }
-}
\ No newline at end of file
+    async fn reclaim_space(&self) -> Result<(), sqlx::Error> {
+        DB::reclaim_space(&self.pool).await
+    }
+}
@@ -1,29 +1,424 @@
+use std::collections::BTreeMap;
+
 use serde_json::{Value, json};
 use uuid::Uuid;
 
-use crate::{json_handler::{read_server_config_value, ToDevice, ToServerConfig}};
+use crate::{json_handler::{read_server_config_value, ToDevice, ToServerConfig}, stats_handling::conversions::{format_bytes, format_time, get_byte_unit, get_time_unit, Unit}};
 
 /// Holds all the information about a device each minute it is monitored
+///
+/// Values are `None` and details are empty when the collector producing them didn't run for the sample,
+/// so collectors with a longer interval than the sample interval leave gaps instead of repeating old values
 #[derive(sqlx::FromRow, Clone)]
 pub struct Device {
     /// Unique identifier for the device
     pub device_id: String,
     /// Friendly name for the device
     pub device_name: String,
-    /// Amount of RAM used (in bytes)
-    pub ram_used: i64,
+    /// Amount of RAM used (in bytes), page cache isn't counted
+    pub ram_used: Option<i64>,
     /// Amount of RAM available (in bytes)
-    pub ram_total: i64,
+    pub ram_total: Option<i64>,
     /// Current CPU usage as a percentage (0.0 to 1.0)
-    pub cpu_usage: f32,
-    /// Number of processes running on the device (this may never be used and left as NULL in the database)
-    pub processes: i32,
-    /// Amount of incoming network traffic (in bytes)
-    pub network_in: i64,
-    /// Amount of outgoing network traffic (in bytes)
-    pub network_out: i64,
+    pub cpu_usage: Option<f32>,
+    /// Number of processes running on the device
+    pub processes: Option<i32>,
+    /// Incoming network traffic over every interface (in bytes per second)
+    pub network_in: Option<i64>,
+    /// Outgoing network traffic over every interface (in bytes per second)
+    pub network_out: Option<i64>,
     /// Unix timestamp the data was taken
     pub time: i64,
+    /// 1 minute load average
+    pub load_1: Option<f32>,
+    /// 5 minute load average
+    pub load_5: Option<f32>,
+    /// 15 minute load average
+    pub load_15: Option<f32>,
+    /// Current CPU frequency averaged over the cores (in MHz)
+    pub cpu_freq: Option<i64>,
+    /// Maximum CPU frequency (in MHz), 0 if it's unknown
+    pub cpu_freq_max: Option<i64>,
+    /// RAM used by kernel buffers (in bytes)
+    pub ram_buffers: Option<i64>,
+    /// RAM used by the page cache (in bytes)
+    pub ram_cached: Option<i64>,
+    /// Size of swap (in bytes)
+    pub swap_total: Option<i64>,
+    /// Swap in use (in bytes)
+    pub swap_used: Option<i64>,
+    /// Size of the ZFS ARC (in bytes), it's included in `ram_used`
+    pub zfs_arc: Option<i64>,
+    /// Usage of each core as a percentage (0.0 to 1.0), stored in the `cpu_cores` table
+    #[sqlx(skip)]
+    pub cpu_cores: Vec<f32>,
+    /// Usage of each mounted filesystem, stored in the `filesystems` table
+    #[sqlx(skip)]
+    pub filesystems: Vec<FilesystemUsage>,
+    /// Throughput of each disk, stored in the `disk_io` table
+    #[sqlx(skip)]
+    pub disk_io: Vec<DiskIo>,
+    /// Traffic of each network interface, stored in the `network_interfaces` table
+    #[sqlx(skip)]
+    pub interfaces: Vec<NetworkInterface>,
+    /// Temperatures, battery charge and AC state, stored in the `sensors` table
+    #[sqlx(skip)]
+    pub sensors: Vec<Sensor>,
+    /// Processes using the most CPU and the most memory, stored in the `processes` table
+    #[sqlx(skip)]
+    pub top_processes: Vec<ProcessInfo>,
+    /// Metrics without a field of their own, by name
+    #[sqlx(skip)]
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// A device known to the server, stored once in the `devices` table, its samples are in the `samples` table
+#[derive(sqlx::FromRow, Clone)]
+pub struct DeviceRecord {
+    /// Unique identifier for the device
+    pub device_id: String,
+    /// Friendly name for the device
+    pub device_name: String,
+    /// Unix timestamp of the device's first sample
+    pub created_at: i64,
+    /// Unix timestamp of the device's latest sample
+    pub last_seen: i64,
+    /// JSON object of anything else known about the device, `{}` if there's nothing
+    pub metadata: String,
+}
+
+/// A process that was among the busiest when the sample was taken
+#[derive(sqlx::FromRow, Clone)]
+pub struct ProcessInfo {
+    /// Process ID
+    pub pid: i64,
+    /// Name of the executable
+    pub name: String,
+    /// Name of the user running the process, empty if it's unknown
+    pub user_name: String,
+    /// CPU usage since the previous sample as a percentage of one core (0.0 to 1.0 per core)
+    pub cpu_usage: f32,
+    /// Resident memory (in bytes)
+    pub rss: i64,
+    /// Command line the process was started with, only the executable if the client redacts them
+    pub cmdline: String,
+}
+
+impl ProcessInfo {
+    /// Converts the `ProcessInfo` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "pid"     : self.pid,
+            "name"    : self.name,
+            "user"    : self.user_name,
+            "cpuUsage": self.cpu_usage,
+            "rss"     : self.rss,
+            "cmdline" : self.cmdline
+        })
+    }
+
+    /// Converts a JSON `Value` to a `ProcessInfo` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Process sent by the client
+    pub fn from_json(value: &Value) -> ProcessInfo {
+        ProcessInfo {
+            pid: value["pid"].as_i64().unwrap_or(0),
+            name: value["name"].as_str().unwrap_or_default().to_string(),
+            user_name: value["user"].as_str().unwrap_or_default().to_string(),
+            cpu_usage: value["cpuUsage"].as_f64().unwrap_or(0.0) as f32,
+            rss: value["rss"].as_i64().unwrap_or(0),
+            cmdline: value["cmdline"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// Reading of a hardware sensor
+#[derive(sqlx::FromRow, Clone)]
+pub struct Sensor {
+    /// Name of the sensor, unique within a sample, such as `coretemp Package id 0`
+    pub label: String,
+    /// What the sensor measures, one of `constants::SENSOR_TEMPERATURE`, `SENSOR_BATTERY` or `SENSOR_AC`
+    pub kind: String,
+    /// Degrees Celsius for temperatures, percent charged for batteries and 1 or 0 for whether AC power is plugged in
+    pub value: f32,
+}
+
+impl Sensor {
+    /// Converts the `Sensor` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "label": self.label,
+            "kind" : self.kind,
+            "value": self.value
+        })
+    }
+
+    /// Converts a JSON `Value` to a `Sensor` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Sensor sent by the client
+    pub fn from_json(value: &Value) -> Sensor {
+        Sensor {
+            label: value["label"].as_str().unwrap_or_default().to_string(),
+            kind: value["kind"].as_str().unwrap_or_default().to_string(),
+            value: value["value"].as_f64().unwrap_or(0.0) as f32,
+        }
+    }
+}
+
+/// Traffic of a network interface, every value is per second since the previous sample
+#[derive(sqlx::FromRow, Clone, Default)]
+pub struct NetworkInterface {
+    /// Name of the interface, such as eth0
+    pub interface: String,
+    /// Bytes received per second
+    pub rx_bytes: i64,
+    /// Bytes sent per second
+    pub tx_bytes: i64,
+    /// Packets received per second
+    pub rx_packets: i64,
+    /// Packets sent per second
+    pub tx_packets: i64,
+    /// Receive errors per second
+    pub rx_errors: f32,
+    /// Send errors per second
+    pub tx_errors: f32,
+    /// Received packets dropped per second
+    pub rx_drops: f32,
+    /// Outgoing packets dropped per second
+    pub tx_drops: f32,
+}
+
+impl NetworkInterface {
+    /// Converts the `NetworkInterface` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "interface": self.interface,
+            "rxBytes"  : self.rx_bytes,
+            "txBytes"  : self.tx_bytes,
+            "rxPackets": self.rx_packets,
+            "txPackets": self.tx_packets,
+            "rxErrors" : self.rx_errors,
+            "txErrors" : self.tx_errors,
+            "rxDrops"  : self.rx_drops,
+            "txDrops"  : self.tx_drops
+        })
+    }
+
+    /// Converts a JSON `Value` to a `NetworkInterface` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Interface sent by the client
+    pub fn from_json(value: &Value) -> NetworkInterface {
+        NetworkInterface {
+            interface: value["interface"].as_str().unwrap_or_default().to_string(),
+            rx_bytes: value["rxBytes"].as_i64().unwrap_or(0),
+            tx_bytes: value["txBytes"].as_i64().unwrap_or(0),
+            rx_packets: value["rxPackets"].as_i64().unwrap_or(0),
+            tx_packets: value["txPackets"].as_i64().unwrap_or(0),
+            rx_errors: value["rxErrors"].as_f64().unwrap_or(0.0) as f32,
+            tx_errors: value["txErrors"].as_f64().unwrap_or(0.0) as f32,
+            rx_drops: value["rxDrops"].as_f64().unwrap_or(0.0) as f32,
+            tx_drops: value["txDrops"].as_f64().unwrap_or(0.0) as f32,
+        }
+    }
+}
+
+/// Throughput of a block device, every value is per second over the sampling window
+#[derive(sqlx::FromRow, Clone, Default)]
+pub struct DiskIo {
+    /// Name of the block device, such as sda
+    pub disk: String,
+    /// Bytes read per second
+    pub read_bytes: i64,
+    /// Bytes written per second
+    pub write_bytes: i64,
+    /// Reads completed per second
+    pub read_ops: i64,
+    /// Writes completed per second
+    pub write_ops: i64,
+}
+
+impl DiskIo {
+    /// Converts the `DiskIo` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "disk"      : self.disk,
+            "readBytes" : self.read_bytes,
+            "writeBytes": self.write_bytes,
+            "readOps"   : self.read_ops,
+            "writeOps"  : self.write_ops
+        })
+    }
+
+    /// Converts a JSON `Value` to a `DiskIo` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Disk sent by the client
+    pub fn from_json(value: &Value) -> DiskIo {
+        DiskIo {
+            disk: value["disk"].as_str().unwrap_or_default().to_string(),
+            read_bytes: value["readBytes"].as_i64().unwrap_or(0),
+            write_bytes: value["writeBytes"].as_i64().unwrap_or(0),
+            read_ops: value["readOps"].as_i64().unwrap_or(0),
+            write_ops: value["writeOps"].as_i64().unwrap_or(0),
+        }
+    }
+}
+
+/// Space and inode usage of a mounted filesystem
+#[derive(sqlx::FromRow, Clone)]
+pub struct FilesystemUsage {
+    /// Where the filesystem is mounted
+    pub mount_point: String,
+    /// Type of the filesystem, such as ext4
+    pub fs_type: String,
+    /// Size of the filesystem (in bytes)
+    pub total: i64,
+    /// Space used (in bytes)
+    pub used: i64,
+    /// Space available to users (in bytes), can be less than `total - used` as some is reserved for root
+    pub free: i64,
+    /// Number of inodes
+    pub inodes_total: i64,
+    /// Number of inodes used
+    pub inodes_used: i64,
+}
+
+impl FilesystemUsage {
+    /// Converts the `FilesystemUsage` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "mountPoint" : self.mount_point,
+            "fsType"     : self.fs_type,
+            "total"      : self.total,
+            "used"       : self.used,
+            "free"       : self.free,
+            "inodesTotal": self.inodes_total,
+            "inodesUsed" : self.inodes_used
+        })
+    }
+
+    /// Converts a JSON `Value` to a `FilesystemUsage` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Filesystem sent by the client
+    pub fn from_json(value: &Value) -> FilesystemUsage {
+        FilesystemUsage {
+            mount_point: value["mountPoint"].as_str().unwrap_or_default().to_string(),
+            fs_type: value["fsType"].as_str().unwrap_or_default().to_string(),
+            total: value["total"].as_i64().unwrap_or(0),
+            used: value["used"].as_i64().unwrap_or(0),
+            free: value["free"].as_i64().unwrap_or(0),
+            inodes_total: value["inodesTotal"].as_i64().unwrap_or(0),
+            inodes_used: value["inodesUsed"].as_i64().unwrap_or(0),
+        }
+    }
+}
+
+/// What a device is, sent by the client when it starts and whenever it changes, stored in the `device_inventory` table
+#[derive(sqlx::FromRow, Clone, PartialEq, Default)]
+pub struct Inventory {
+    /// Hostname of the device
+    pub hostname: String,
+    /// Name and version of the OS or distribution, such as `Ubuntu 24.04.1 LTS`
+    pub os: String,
+    /// Kernel version
+    pub kernel: String,
+    /// CPU architecture, such as `x86_64`
+    pub arch: String,
+    /// Model of the CPU
+    pub cpu_model: String,
+    /// Number of physical cores
+    pub cpu_cores: i64,
+    /// Number of logical cores
+    pub cpu_threads: i64,
+    /// Amount of RAM (in bytes)
+    pub ram_total: i64,
+    /// Unix timestamp the device booted at
+    pub boot_time: i64,
+    /// Version of rlsd the client is running
+    pub version: String,
+    /// Unix timestamp the server received the inventory at, 0 until it's stored
+    pub time: i64,
+}
+
+impl Inventory {
+    /// Converts the `Inventory` to a JSON `Value`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "hostname"  : self.hostname,
+            "os"        : self.os,
+            "kernel"    : self.kernel,
+            "arch"      : self.arch,
+            "cpuModel"  : self.cpu_model,
+            "cpuCores"  : self.cpu_cores,
+            "cpuThreads": self.cpu_threads,
+            "ramTotal"  : self.ram_total,
+            "bootTime"  : self.boot_time,
+            "version"   : self.version,
+            "time"      : self.time
+        })
+    }
+
+    /// Converts a JSON `Value` to an `Inventory` instance
+    ///
+    /// # Arguments
+    /// * `value: &Value` - Inventory sent by the client
+    pub fn from_json(value: &Value) -> Inventory {
+        Inventory {
+            hostname: value["hostname"].as_str().unwrap_or_default().to_string(),
+            os: value["os"].as_str().unwrap_or_default().to_string(),
+            kernel: value["kernel"].as_str().unwrap_or_default().to_string(),
+            arch: value["arch"].as_str().unwrap_or_default().to_string(),
+            cpu_model: value["cpuModel"].as_str().unwrap_or_default().to_string(),
+            cpu_cores: value["cpuCores"].as_i64().unwrap_or(0),
+            cpu_threads: value["cpuThreads"].as_i64().unwrap_or(0),
+            ram_total: value["ramTotal"].as_i64().unwrap_or(0),
+            boot_time: value["bootTime"].as_i64().unwrap_or(0),
+            version: value["version"].as_str().unwrap_or_default().to_string(),
+            time: value["time"].as_i64().unwrap_or(0),
+        }
+    }
+
+    /// Checks if anything but the time it was received at differs from another inventory
+    ///
+    /// # Arguments
+    /// * `other: &Inventory` - Inventory to compare with
+    pub fn changed_from(&self, other: &Inventory) -> bool {
+        Inventory { time: 0, ..self.clone() } != Inventory { time: 0, ..other.clone() }
+    }
+
+    /// Returns one line per field, such as `Kernel: 6.8.0`, with the uptime worked out from the boot time
+    ///
+    /// # Arguments
+    /// * `now: i64` - Current Unix timestamp
+    pub fn lines(&self, now: i64) -> Vec<String> {
+        let uptime = (now - self.boot_time).max(0) as u128;
+
+        vec![
+            format!("Hostname: {}", self.hostname),
+            format!("OS: {}", self.os),
+            format!("Kernel: {}", self.kernel),
+            format!("Architecture: {}", self.arch),
+            format!("CPU: {} ({} cores, {} threads)", self.cpu_model, self.cpu_cores, self.cpu_threads),
+            format!(
+                "RAM: {:.1}{}",
+                format_bytes(self.ram_total as f64, Unit::BYTE),
+                get_byte_unit(self.ram_total.max(0) as usize, Unit::BYTE)
+            ),
+            format!(
+                "Uptime: {:.1} {} (booted {})",
+                format_time(uptime, Unit::SECOND),
+                get_time_unit(uptime, Unit::SECOND),
+                chrono::DateTime::from_timestamp(self.boot_time, 0)
+                    .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default()
+            ),
+            format!("rlsd: {}", self.version),
+        ]
+    }
 }
 
 impl Device {
@@ -37,13 +432,13 @@ impl Device {
     /// * `ram_total` - Total amount of RAM available (in bytes)
     /// * `cpu_usage` - Current CPU usage as a percentage (0.0 to 1.0)
     /// * `processes` - Number of running processes on the device
-    /// * `network_in` - Amount of incoming network traffic (in bytes)
-    /// * `network_out` - Amount of outgoing network traffic (in bytes)
+    /// * `network_in` - Incoming network traffic (in bytes per second)
+    /// * `network_out` - Outgoing network traffic (in bytes per second)
     /// * `time` - Unix timestamp the data was taken
     ///
     /// # Returns
     ///
-    /// A new `Device` instance initialized with the specified values.
+    /// A new `Device` instance initialized with the specified values, every other field is left empty
     pub fn new(
         device_id: &str,
         device_name: &str,
@@ -55,16 +450,52 @@ impl Device {
         network_out: i64,
         time: i64,
     ) -> Device {
+        Device {
+            ram_used: Some(ram_used),
+            ram_total: Some(ram_total),
+            cpu_usage: Some(cpu_usage),
+            processes: Some(processes),
+            network_in: Some(network_in),
+            network_out: Some(network_out),
+            ..Device::empty(device_id, device_name, time)
+        }
+    }
+
+    /// Creates a `Device` without any values, filled in by the collectors that run for the sample
+    ///
+    /// # Arguments
+    ///
+    /// * `device_id` - Unique identifier for the device
+    /// * `device_name` - Friendly name for the device
+    /// * `time` - Unix timestamp the data was taken
+    pub fn empty(device_id: &str, device_name: &str, time: i64) -> Device {
         Device {
             device_id: device_id.to_string(),
             device_name: device_name.to_string(),
-            ram_used: ram_used,
-            ram_total: ram_total,
-            cpu_usage: cpu_usage,
-            processes: processes,
-            network_in: network_in,
-            network_out: network_out,
-            time: time,
+            ram_used: None,
+            ram_total: None,
+            cpu_usage: None,
+            processes: None,
+            network_in: None,
+            network_out: None,
+            time,
+            load_1: None,
+            load_5: None,
+            load_15: None,
+            cpu_freq: None,
+            cpu_freq_max: None,
+            ram_buffers: None,
+            ram_cached: None,
+            swap_total: None,
+            swap_used: None,
+            zfs_arc: None,
+            cpu_cores: Vec::new(),
+            filesystems: Vec::new(),
+            disk_io: Vec::new(),
+            interfaces: Vec::new(),
+            sensors: Vec::new(),
+            top_processes: Vec::new(),
+            metrics: BTreeMap::new(),
         }
     }
 
@@ -79,23 +510,59 @@ impl Device {
             "processes" : self.processes,
             "networkIn" : self.network_in,
             "networkOut": self.network_out,
-            "time": self.time
+            "time": self.time,
+            "load1"     : self.load_1,
+            "load5"     : self.load_5,
+            "load15"    : self.load_15,
+            "cpuFreq"   : self.cpu_freq,
+            "cpuFreqMax": self.cpu_freq_max,
+            "ramBuffers": self.ram_buffers,
+            "ramCached" : self.ram_cached,
+            "swapTotal" : self.swap_total,
+            "swapUsed"  : self.swap_used,
+            "zfsArc"    : self.zfs_arc,
+            "cpuCores"  : self.cpu_cores,
+            "filesystems": self.filesystems.iter().map(FilesystemUsage::to_json).collect::<Vec<Value>>(),
+            "diskIO"    : self.disk_io.iter().map(DiskIo::to_json).collect::<Vec<Value>>(),
+            "interfaces": self.interfaces.iter().map(NetworkInterface::to_json).collect::<Vec<Value>>(),
+            "sensors"   : self.sensors.iter().map(Sensor::to_json).collect::<Vec<Value>>(),
+            "topProcesses": self.top_processes.iter().map(ProcessInfo::to_json).collect::<Vec<Value>>(),
+            "metrics"   : self.metrics
         })
     }
 
-    /// Converts the `Device` to a `String`
+    /// Converts the `Device` to a `String`, values that weren't collected are shown as `N/A`
     pub fn to_string(&self) -> String {
+        let value = |value: Option<String>| value.unwrap_or_else(|| "N/A".to_string());
+
         format!(
-            "Device ID: {}\nDevice Name: {}\nRAM Used: {}\nRAM Total: {}\nCPU Usage: {}%\nProcesses: {}\nNetwork In: {} bytes\nNetwork Out: {} bytes\nTime: {}",
+            "Device ID: {}\nDevice Name: {}\nRAM Used: {}\nRAM Total: {}\nRAM Buffers: {}\nRAM Cached: {}\nSwap Used: {}\nSwap Total: {}\nZFS ARC: {}\nCPU Usage: {}%\nProcesses: {}\nNetwork In: {} bytes/s\nNetwork Out: {} bytes/s\nTime: {}\nLoad Average: {} {} {}\nCPU Frequency: {}/{} MHz\nCores: {}\nFilesystems: {}\nDisks: {}\nInterfaces: {}\nSensors: {}\nTop Processes: {}\nMetrics: {}",
             self.device_id,
             self.device_name,
-            self.ram_used,
-            self.ram_total,
-            self.cpu_usage,
-            self.processes,
-            self.network_in,
-            self.network_out,
-            self.time
+            value(self.ram_used.map(|v| v.to_string())),
+            value(self.ram_total.map(|v| v.to_string())),
+            value(self.ram_buffers.map(|v| v.to_string())),
+            value(self.ram_cached.map(|v| v.to_string())),
+            value(self.swap_used.map(|v| v.to_string())),
+            value(self.swap_total.map(|v| v.to_string())),
+            value(self.zfs_arc.map(|v| v.to_string())),
+            value(self.cpu_usage.map(|v| v.to_string())),
+            value(self.processes.map(|v| v.to_string())),
+            value(self.network_in.map(|v| v.to_string())),
+            value(self.network_out.map(|v| v.to_string())),
+            self.time,
+            value(self.load_1.map(|v| v.to_string())),
+            value(self.load_5.map(|v| v.to_string())),
+            value(self.load_15.map(|v| v.to_string())),
+            value(self.cpu_freq.map(|v| v.to_string())),
+            value(self.cpu_freq_max.map(|v| v.to_string())),
+            self.cpu_cores.len(),
+            self.filesystems.len(),
+            self.disk_io.len(),
+            self.interfaces.len(),
+            self.sensors.len(),
+            self.top_processes.len(),
+            self.metrics.len()
         )
     }
 }
@@ -104,22 +571,85 @@ impl ToDevice for serde_json::Value {
     /// Converts a JSON value to a `Device` instance.
     ///
     /// # Returns
-    /// A `Device` instance created from the JSON `Value`.
+    /// A `Device` instance created from the JSON `Value`, values that are missing or `null` weren't collected
     fn to_device(&self) -> Device {
         Device {
             device_id: self["deviceID"].as_str().unwrap_or_default().to_string(),
             device_name: self["deviceName"].as_str().unwrap_or_default().to_string(),
-            ram_used: self["ramUsed"].as_i64().unwrap_or(0),
-            ram_total: self["ramTotal"].as_i64().unwrap_or(0),
-            cpu_usage: self["cpuUsage"].as_f64().unwrap_or(0.0) as f32,
-            processes: self["processes"].as_i64().unwrap_or(0) as i32,
-            network_in: self["networkIn"].as_i64().unwrap_or(0),
-            network_out: self["networkOut"].as_i64().unwrap_or(0),
+            ram_used: self["ramUsed"].as_i64(),
+            ram_total: self["ramTotal"].as_i64(),
+            cpu_usage: self["cpuUsage"].as_f64().map(|value| value as f32),
+            processes: self["processes"].as_i64().map(|value| value as i32),
+            network_in: self["networkIn"].as_i64(),
+            network_out: self["networkOut"].as_i64(),
             time: self["time"].as_i64().unwrap_or(0),
+            load_1: self["load1"].as_f64().map(|value| value as f32),
+            load_5: self["load5"].as_f64().map(|value| value as f32),
+            load_15: self["load15"].as_f64().map(|value| value as f32),
+            cpu_freq: self["cpuFreq"].as_i64(),
+            cpu_freq_max: self["cpuFreqMax"].as_i64(),
+            ram_buffers: self["ramBuffers"].as_i64(),
+            ram_cached: self["ramCached"].as_i64(),
+            swap_total: self["swapTotal"].as_i64(),
+            swap_used: self["swapUsed"].as_i64(),
+            zfs_arc: self["zfsArc"].as_i64(),
+            cpu_cores: self["cpuCores"]
+                .as_array()
+                .map(|cores| cores.iter().map(|core| core.as_f64().unwrap_or(0.0) as f32).collect())
+                .unwrap_or_default(),
+            filesystems: self["filesystems"]
+                .as_array()
+                .map(|filesystems| filesystems.iter().map(FilesystemUsage::from_json).collect())
+                .unwrap_or_default(),
+            disk_io: self["diskIO"]
+                .as_array()
+                .map(|disks| disks.iter().map(DiskIo::from_json).collect())
+                .unwrap_or_default(),
+            interfaces: self["interfaces"]
+                .as_array()
+                .map(|interfaces| interfaces.iter().map(NetworkInterface::from_json).collect())
+                .unwrap_or_default(),
+            sensors: self["sensors"]
+                .as_array()
+                .map(|sensors| sensors.iter().map(Sensor::from_json).collect())
+                .unwrap_or_default(),
+            top_processes: self["topProcesses"]
+                .as_array()
+                .map(|processes| processes.iter().map(ProcessInfo::from_json).collect())
+                .unwrap_or_default(),
+            metrics: self["metrics"]
+                .as_object()
+                .map(|metrics| {
+                    metrics.iter().filter_map(|(name, value)| Some((name.clone(), value.as_f64()?))).collect()
+                })
+                .unwrap_or_default(),
         }
     }
 }
 
+/// Aggregate of one value of a device over a bucket of a rollup tier, stored in the `rollups` table
+#[derive(sqlx::FromRow, Clone)]
+pub struct Rollup {
+    /// Unix timestamp the bucket starts at
+    pub time: i64,
+    /// Table the value comes from, such as `samples` or `metrics`
+    pub source: String,
+    /// Row of the table the value comes from, such as the core, disk or metric name, empty for `samples`
+    pub series: String,
+    /// Column the value comes from, the kind for sensors
+    pub field: String,
+    /// Lowest value in the bucket
+    pub min_value: f64,
+    /// Highest value in the bucket
+    pub max_value: f64,
+    /// Average of the bucket's samples
+    pub avg_value: f64,
+    /// Value of the bucket's latest sample
+    pub last_value: f64,
+    /// Number of samples in the bucket
+    pub sample_count: i64,
+}
+
 /// Generates a new device id
 /// 
 /// # Returns
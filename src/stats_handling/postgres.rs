@@ -0,0 +1,37 @@
+//! PostgreSQL backend for the SQL storage, built with the `postgres` feature and used when `databaseUrl` points at it
+use async_trait::async_trait;
+use sqlx::{migrate::MigrateError, postgres::{PgPoolOptions, PgQueryResult}, Pool, Postgres};
+
+use crate::stats_handling::database::Backend;
+
+#[async_trait]
+impl Backend for Postgres {
+    async fn migrate(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+        sqlx::migrate!("./migrations").run(pool).await
+    }
+
+    async fn reclaim_space(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query("VACUUM").execute(pool).await?;
+
+        Ok(())
+    }
+
+    fn rows_affected(result: &PgQueryResult) -> u64 {
+        result.rows_affected()
+    }
+}
+
+/// Connects to a PostgreSQL database
+///
+/// # Arguments
+/// * `url: &str` - URL of the database, such as `postgres://rlsd@localhost/rlsd`
+///
+/// # Returns
+/// `Pool<Postgres>` - Interact with the database
+pub async fn connect(url: &str) -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect(url)
+        .await
+        .expect("Couldn't connect to database")
+}
@@ -0,0 +1,61 @@
+//! Removes samples and rollups once they're older than the retention set in the server config
+//!
+//! The server prunes every `PRUNE_INTERVAL_SECONDS` and `rlsd --prune` does the same on demand,
+//! with `--dry-run` only counting what would be removed
+use crate::{config::server::ServerConfig, stats_handling::storage::Storage};
+
+/// What pruning removed, or would remove, from a device
+pub struct Pruned {
+    /// ID of the device
+    pub device_id: String,
+    /// Name of the device in the registry
+    pub device_name: String,
+    /// Unix timestamp samples older than are removed, `None` if they're kept forever
+    pub samples_before: Option<i64>,
+    /// Unix timestamp rollups older than are removed, `None` if they're kept forever
+    pub rollups_before: Option<i64>,
+    /// Number of samples removed
+    pub samples: u64,
+    /// Number of rollup rows removed
+    pub rollups: u64,
+}
+
+/// Prunes every device in the registry by its retention, the freed space is handed back if anything was removed
+///
+/// # Arguments
+/// * `database: &dyn Storage` - Database to prune
+/// * `config: &ServerConfig` - Config holding the retention of each device
+/// * `now: i64` - Current unix timestamp
+/// * `dry_run: bool` - Only count what would be removed
+///
+/// # Returns
+/// * `Ok(Vec<Pruned>)` - What was removed from each device
+/// * `Err(sqlx::Error)` - A query failed, devices pruned before it stay pruned
+pub async fn prune(database: &dyn Storage, config: &ServerConfig, now: i64, dry_run: bool) -> Result<Vec<Pruned>, sqlx::Error> {
+    let mut pruned = Vec::new();
+
+    for device in database.get_devices().await {
+        let (samples_before, rollups_before) = config.retention_for(&device.device_id).cutoffs(now);
+
+        let (samples, rollups) = if dry_run {
+            database.count_expired(&device.device_id, samples_before, rollups_before).await?
+        } else {
+            database.prune_device(&device.device_id, samples_before, rollups_before).await?
+        };
+
+        pruned.push(Pruned {
+            device_id: device.device_id,
+            device_name: device.device_name,
+            samples_before,
+            rollups_before,
+            samples,
+            rollups,
+        });
+    }
+
+    if !dry_run && pruned.iter().any(|device| device.samples + device.rollups > 0) {
+        database.reclaim_space().await?;
+    }
+
+    Ok(pruned)
+}
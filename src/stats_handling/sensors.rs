@@ -0,0 +1,138 @@
+//! Hardware sensors
+//!
+//! Temperatures are read from hwmon and the thermal zones in sysfs so they're only reported on Linux,
+//! the battery charge and AC state come from systemstat
+use std::{fs, path::Path};
+
+use systemstat::Platform;
+
+use crate::{
+    constants::{SENSOR_AC, SENSOR_BATTERY, SENSOR_TEMPERATURE},
+    stats_handling::device_info::Sensor,
+};
+
+/// Returns every temperature sensor, and the battery charge and AC state if the device has a battery
+///
+/// # Arguments
+/// * `sys: &impl Platform` - Reads the battery
+/// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+pub fn get_sensors(sys: &impl Platform, root: &Path) -> Vec<Sensor> {
+    let mut sensors = read_temperatures(&root.join("sys"));
+
+    // Desktops and servers don't have a battery so whether they're on AC doesn't tell anything
+    if let Ok(battery) = sys.battery_life() {
+        sensors.push(Sensor {
+            label: "Battery".to_string(),
+            kind: SENSOR_BATTERY.to_string(),
+            value: battery.remaining_capacity * 100.0,
+        });
+
+        if let Ok(on_ac) = sys.on_ac_power() {
+            sensors.push(Sensor {
+                label: "AC power".to_string(),
+                kind: SENSOR_AC.to_string(),
+                value: if on_ac { 1.0 } else { 0.0 },
+            });
+        }
+    }
+
+    sensors
+}
+
+/// Reads the hwmon and thermal zone temperatures
+///
+/// Sensors that can't be read are left out, labels are made unique by numbering the repeats
+///
+/// # Arguments
+/// * `root: &Path` - Where sysfs is mounted, `/sys` unless it's a copy of the tree
+///
+/// # Returns
+/// `Vec<Sensor>` - hwmon sensors labelled `<chip> <label>` followed by thermal zones labelled `thermal <type>`
+pub fn read_temperatures(root: &Path) -> Vec<Sensor> {
+    let mut sensors = Vec::new();
+
+    for chip in sorted_entries(&root.join("class/hwmon"), "hwmon") {
+        let chip_name = read_trimmed(&chip.join("name")).unwrap_or_else(|| file_name(&chip));
+
+        let mut inputs: Vec<u32> = sorted_entries(&chip, "temp")
+            .iter()
+            .filter_map(|path| file_name(path).strip_prefix("temp")?.strip_suffix("_input")?.parse().ok())
+            .collect();
+
+        inputs.sort();
+
+        for input in inputs {
+            let Some(millidegrees) = read_trimmed(&chip.join(format!("temp{input}_input"))).and_then(|v| v.parse::<f32>().ok())
+            else {
+                continue;
+            };
+
+            let label = read_trimmed(&chip.join(format!("temp{input}_label"))).unwrap_or(format!("temp{input}"));
+
+            push_unique(&mut sensors, format!("{chip_name} {label}"), millidegrees / 1000.0);
+        }
+    }
+
+    for zone in sorted_entries(&root.join("class/thermal"), "thermal_zone") {
+        let Some(millidegrees) = read_trimmed(&zone.join("temp")).and_then(|v| v.parse::<f32>().ok()) else {
+            continue;
+        };
+
+        let zone_type = read_trimmed(&zone.join("type")).unwrap_or_else(|| file_name(&zone));
+
+        push_unique(&mut sensors, format!("thermal {zone_type}"), millidegrees / 1000.0);
+    }
+
+    sensors
+}
+
+/// Adds a temperature, numbering the label if another sensor already uses it
+fn push_unique(sensors: &mut Vec<Sensor>, label: String, celsius: f32) {
+    let mut unique = label.clone();
+    let mut repeat = 1;
+
+    while sensors.iter().any(|sensor| sensor.label == unique) {
+        repeat += 1;
+        unique = format!("{label} {repeat}");
+    }
+
+    sensors.push(Sensor {
+        label: unique,
+        kind: SENSOR_TEMPERATURE.to_string(),
+        value: celsius,
+    });
+}
+
+/// Lists the entries of a directory starting with `prefix`, sorted by name so sensors keep their order between samples
+fn sorted_entries(dir: &Path, prefix: &str) -> Vec<std::path::PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| file_name(path).starts_with(prefix))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Natural order so hwmon10 comes after hwmon9
+    entries.sort_by_key(|path| {
+        let name = file_name(path);
+        let digits = name.trim_start_matches(|c: char| !c.is_ascii_digit()).len();
+        (name.len() - digits, name.len(), name)
+    });
+
+    entries
+}
+
+/// Reads a sysfs attribute without its trailing newline, `None` if it's missing, unreadable or empty
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?.trim().to_string();
+
+    (!value.is_empty()).then_some(value)
+}
+
+/// Returns the last part of a path as a `String`
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
@@ -0,0 +1,75 @@
+//! On-disk buffer for samples the client couldn't send
+//!
+//! Samples are stored one JSON object per line in the data directory, oldest first,
+//! and are replayed with their original timestamps once the server is reachable again
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+};
+
+use serde_json::Value;
+
+use crate::{
+    constants::{get_spool_path, SPOOL_MAX_AGE_SECONDS, SPOOL_MAX_BYTES},
+    json_handler::ToDevice,
+    stats_handling::{device_info::Device, stats_getter::get_unix_timestamp},
+};
+
+/// Reads every sample in the spool that isn't too old to keep
+///
+/// # Returns
+/// `Vec<Device>` - Samples, oldest first
+pub fn load() -> Vec<Device> {
+    let oldest = get_unix_timestamp() - SPOOL_MAX_AGE_SECONDS;
+
+    fs::read_to_string(get_spool_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .map(|value| value.to_device())
+        .filter(|device| device.time >= oldest)
+        .collect()
+}
+
+/// Checks if there are samples waiting to be sent
+pub fn is_empty() -> bool {
+    fs::metadata(get_spool_path()).map(|metadata| metadata.len() == 0).unwrap_or(true)
+}
+
+/// Adds a sample to the end of the spool, the oldest samples are dropped once it's over `SPOOL_MAX_BYTES`
+///
+/// # Arguments
+/// * `device: &Device` - Sample to keep
+pub fn push(device: &Device) {
+    let line = format!("{}\n", device.clone().to_json());
+
+    match OpenOptions::new().create(true).append(true).open(get_spool_path()) {
+        Ok(mut file) => {
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                eprintln!("Failed to write to the spool: {e}");
+            }
+        }
+        Err(e) => eprintln!("Failed to open the spool: {e}"),
+    }
+
+    if fs::metadata(get_spool_path()).map(|metadata| metadata.len()).unwrap_or(0) > SPOOL_MAX_BYTES {
+        let mut devices = load();
+
+        // Drop the oldest quarter so the file isn't rewritten on every push
+        devices.drain(..devices.len() / 4);
+
+        replace(&devices);
+    }
+}
+
+/// Replaces the spool with the supplied samples
+///
+/// # Arguments
+/// * `devices: &[Device]` - Samples still waiting to be sent, oldest first
+pub fn replace(devices: &[Device]) {
+    let lines: String = devices.iter().map(|device| format!("{}\n", device.clone().to_json())).collect();
+
+    if let Err(e) = fs::write(get_spool_path(), lines) {
+        eprintln!("Failed to write to the spool: {e}");
+    }
+}
@@ -1,101 +1,527 @@
 use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
     thread::{self},
-    time::{self, Duration, SystemTime},
+    time::{self, Duration, Instant, SystemTime},
 };
 
-use systemstat::Platform;
+use sysinfo::{CpuRefreshKind, ProcessRefreshKind, ProcessesToUpdate, ThreadKind, UpdateKind, Users};
+use systemstat::{BlockDeviceStats, IpAddr, Platform};
 
-/// Returns the usage of the CPU
+use crate::{
+    constants::{IGNORED_BLOCK_DEVICES, IGNORED_FILESYSTEMS},
+    stats_handling::device_info::{DiskIo, FilesystemUsage, Inventory, NetworkInterface, ProcessInfo},
+};
+
+/// Returns the usage of the CPU and of each of its cores
+///
+/// Both are measured over the same second, the usage will be 0 and the cores empty if it fails
 ///
-/// Will return 0 if it fails
-pub fn get_cpu_usage(sys: &impl Platform) -> f32 {
+/// # Returns
+/// `(f32, Vec<f32>)` - Usage of the whole CPU and of each core, from 0.0 to 1.0
+pub fn get_cpu_usage(sys: &impl Platform) -> (f32, Vec<f32>) {
     // Starts measuring CPU load
-    let cpu_avg = sys.cpu_load_aggregate().unwrap();
+    let cpu_avg = sys.cpu_load_aggregate();
+    let cpu_cores = sys.cpu_load();
     // Measure for one second
     thread::sleep(Duration::from_secs(1));
 
     // Return the CPU usage if Ok
     // Returns 0 if it is Err
-    match cpu_avg.done() {
+    let usage = match cpu_avg.and_then(|measurement| measurement.done()) {
         Ok(v) => 1.0 - v.idle,
         Err(_) => 0.0,
+    };
+
+    let cores = match cpu_cores.and_then(|measurement| measurement.done()) {
+        Ok(v) => v.iter().map(|core| 1.0 - core.idle).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    (usage, cores)
+}
+
+/// Returns the 1, 5 and 15 minute load averages
+///
+/// Will return 0 if it fails, Windows doesn't have load averages
+pub fn get_load_average(sys: &impl Platform) -> (f32, f32, f32) {
+    match sys.load_average() {
+        Ok(v) => (v.one, v.five, v.fifteen),
+        Err(_) => (0.0, 0.0, 0.0),
     }
 }
 
-/// Returns the system RAM usage
+/// Returns the current frequency of the CPU, averaged over its cores, and its maximum frequency in MHz
+///
+/// The maximum is read from cpufreq so it will be 0 when that isn't available, such as outside of Linux
 ///
-/// Will return 0 if it fails
-pub fn get_ram_usage(sys: &impl Platform) -> i64 {
-    // Gets wrapped memory
-    let ram_usage = sys.memory();
-
-    // If ram_usage is Ok then it takes the total RAM subtracted by the free RAM to find the used RAM
-    // Otherwise it returns 0
-    match ram_usage {
-        Ok(v) => (v.total.0 - v.free.0) as i64,
-        Err(_) => 0,
+/// # Arguments
+/// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+pub fn get_cpu_frequency(root: &Path) -> (i64, i64) {
+    let mut sys = sysinfo::System::new();
+    sys.refresh_cpu_frequency();
+
+    let cpus = sys.cpus();
+
+    let current = match cpus.len() {
+        0 => 0,
+        count => cpus.iter().map(|cpu| cpu.frequency()).sum::<u64>() / count as u64,
+    };
+
+    // cpufreq reports kHz, every core is checked as big.LITTLE cores don't share a maximum
+    let max = fs::read_dir(root.join("sys/devices/system/cpu"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| fs::read_to_string(entry.path().join("cpufreq/cpuinfo_max_freq")).ok())
+                .filter_map(|khz| khz.trim().parse::<u64>().ok())
+                .max()
+                .unwrap_or(0)
+                / 1000
+        })
+        .unwrap_or(0);
+
+    (current as i64, max as i64)
+}
+
+/// Memory usage of the system, every value is in bytes
+#[derive(Default)]
+pub struct MemoryInfo {
+    /// Installed RAM
+    pub total: i64,
+    /// RAM that can't be given to programs without swapping, page cache isn't counted
+    pub used: i64,
+    /// RAM used by kernel buffers
+    pub buffers: i64,
+    /// RAM used by the page cache and reclaimable kernel caches
+    pub cached: i64,
+    /// Size of swap
+    pub swap_total: i64,
+    /// Swap in use
+    pub swap_used: i64,
+    /// Size of the ZFS ARC, 0 without ZFS. The kernel counts it as used
+    pub zfs_arc: i64,
+}
+
+/// Returns the memory usage of the system
+///
+/// /proc/meminfo is used on Linux, elsewhere only the totals are known and used memory is everything that isn't free.
+/// Will return 0 for anything that can't be read
+///
+/// # Arguments
+/// * `root: &Path` - Root of the filesystem procfs is read from, `/` outside of tests
+pub fn get_memory(sys: &impl Platform, root: &Path) -> MemoryInfo {
+    if let Some(mut memory) = fs::read_to_string(root.join("proc/meminfo")).ok().and_then(|meminfo| parse_meminfo(&meminfo)) {
+        memory.zfs_arc = fs::read_to_string(root.join("proc/spl/kstat/zfs/arcstats"))
+            .ok()
+            .and_then(|arcstats| parse_zfs_arc(&arcstats))
+            .unwrap_or(0);
+
+        return memory;
+    }
+
+    let mut memory = MemoryInfo::default();
+
+    if let Ok(v) = sys.memory() {
+        memory.total = v.total.0 as i64;
+        memory.used = v.total.0.saturating_sub(v.free.0) as i64;
     }
+
+    if let Ok(v) = sys.swap() {
+        memory.swap_total = v.total.0 as i64;
+        memory.swap_used = v.total.0.saturating_sub(v.free.0) as i64;
+    }
+
+    memory
+}
+
+/// Parses the contents of /proc/meminfo
+///
+/// Used memory is `MemTotal - MemAvailable`, kernels older than 3.14 don't have `MemAvailable`
+/// so free memory, buffers and cache are counted as available instead
+///
+/// # Arguments
+/// * `meminfo: &str` - Contents of /proc/meminfo
+///
+/// # Returns
+/// `Option<MemoryInfo>` - Memory usage without the ZFS ARC, `None` if `MemTotal` is missing
+pub fn parse_meminfo(meminfo: &str) -> Option<MemoryInfo> {
+    // Every line looks like `MemTotal:       16314464 kB`
+    let fields: HashMap<&str, i64> = meminfo
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let kibibytes = value.split_whitespace().next()?.parse::<i64>().ok()?;
+
+            Some((name.trim(), kibibytes * 1024))
+        })
+        .collect();
+
+    let field = |name: &str| fields.get(name).copied().unwrap_or(0);
+
+    let total = *fields.get("MemTotal")?;
+    let buffers = field("Buffers");
+    let cached = field("Cached") + field("SReclaimable");
+    let available = fields.get("MemAvailable").copied().unwrap_or(field("MemFree") + buffers + cached);
+
+    Some(MemoryInfo {
+        total,
+        used: (total - available).max(0),
+        buffers,
+        cached,
+        swap_total: field("SwapTotal"),
+        swap_used: (field("SwapTotal") - field("SwapFree")).max(0),
+        zfs_arc: 0,
+    })
+}
+
+/// Parses the size of the ZFS ARC from /proc/spl/kstat/zfs/arcstats
+///
+/// # Arguments
+/// * `arcstats: &str` - Contents of arcstats, each line has a name, a type and a value in bytes
+pub fn parse_zfs_arc(arcstats: &str) -> Option<i64> {
+    arcstats.lines().find_map(|line| {
+        let mut columns = line.split_whitespace();
+
+        (columns.next()? == "size").then_some(())?;
+
+        columns.nth(1)?.parse().ok()
+    })
 }
 
-/// Returns the available RAM in bytes
+/// Returns the usage of every mounted filesystem that's stored on a disk
 ///
-/// will return 0 if it fails
-pub fn get_ram_total(sys: &impl Platform) -> i64 {
-    match sys.memory() {
-        Ok(v) => v.total.0 as i64,
-        Err(_) => 0,
+/// Filesystems mounted more than once, such as with bind mounts, are only reported at their first mount point.
+/// Will return an empty `Vec` if it fails
+pub fn get_filesystems(sys: &impl Platform) -> Vec<FilesystemUsage> {
+    let mut sources = Vec::new();
+    let mut filesystems = Vec::new();
+
+    for mount in sys.mounts().unwrap_or_default() {
+        if IGNORED_FILESYSTEMS.contains(&mount.fs_type.as_str())
+            || mount.total.0 == 0
+            || sources.contains(&mount.fs_mounted_from)
+        {
+            continue;
+        }
+
+        sources.push(mount.fs_mounted_from.clone());
+
+        filesystems.push(FilesystemUsage {
+            mount_point: mount.fs_mounted_on,
+            fs_type: mount.fs_type,
+            total: mount.total.0 as i64,
+            used: mount.total.0.saturating_sub(mount.free.0) as i64,
+            free: mount.avail.0 as i64,
+            inodes_total: mount.files_total as i64,
+            inodes_used: mount.files as i64,
+        });
     }
+
+    filesystems
 }
 
-/// Returns the number of processes running on the system
-pub fn get_processes() -> i32 {
-    let sys = sysinfo::System::new_all();
+/// Keeps the counters of every disk between samples so their throughput can be worked out without waiting
+#[derive(Default)]
+pub struct DiskIoTracker {
+    /// Counters of each disk at the previous sample
+    previous: BTreeMap<String, BlockDeviceStats>,
+    /// When the previous sample was taken
+    last_sample: Option<Instant>,
+}
+
+impl DiskIoTracker {
+    /// Returns the throughput of every disk since the previous call
+    ///
+    /// Partitions aren't reported as their I/O is already counted in their disk.
+    /// The first call has nothing to compare against so it only keeps the counters and returns an empty `Vec`,
+    /// it will also return an empty `Vec` if it fails, such as outside of Linux
+    ///
+    /// # Arguments
+    /// * `sys: &impl Platform` - Reads the block device counters
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn sample(&mut self, sys: &impl Platform, root: &Path) -> Vec<DiskIo> {
+        let now = Instant::now();
+        let elapsed = self.last_sample.map(|then| now.duration_since(then).as_secs_f64()).unwrap_or(0.0);
+
+        let block = root.join("sys/block");
+        // Only whole disks are listed in /sys/block, every device is kept if it can't be read
+        let whole_disks = block.is_dir();
+
+        let current: BTreeMap<String, BlockDeviceStats> = match sys.block_device_statistics() {
+            Ok(stats) => stats
+                .into_iter()
+                .filter(|(name, _)| !IGNORED_BLOCK_DEVICES.iter().any(|prefix| name.starts_with(prefix)))
+                .filter(|(name, _)| !whole_disks || block.join(name).exists())
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+
+        // Sectors in /proc/diskstats are always 512 bytes, whatever the disk uses
+        let rate = |now: usize, then: usize| (now.saturating_sub(then) as f64 / elapsed).round() as i64;
+
+        let disks = current
+            .iter()
+            .filter(|_| elapsed > 0.0)
+            .filter_map(|(name, now)| {
+                let then = self.previous.get(name)?;
+
+                Some(DiskIo {
+                    disk: name.to_string(),
+                    read_bytes: rate(now.read_sectors * 512, then.read_sectors * 512),
+                    write_bytes: rate(now.write_sectors * 512, then.write_sectors * 512),
+                    read_ops: rate(now.read_ios, then.read_ios),
+                    write_ops: rate(now.write_ios, then.write_ios),
+                })
+            })
+            .collect();
+
+        self.previous = current;
+        self.last_sample = Some(now);
+
+        disks
+    }
+}
 
-    sys.processes().len().try_into().unwrap()
+/// Keeps the process list between samples so the CPU usage of each process can be worked out without waiting
+pub struct ProcessTracker {
+    /// Processes seen at the previous sample
+    system: sysinfo::System,
+    /// Users the processes can belong to
+    users: Users,
 }
 
-pub fn get_network_in(sys: &impl Platform) -> i64 {
-    // Amount of bytes received
-    let mut bytes_in: i64 = 0;
+impl Default for ProcessTracker {
+    /// Makes a new `ProcessTracker`, processes report no CPU usage until the second sample
+    fn default() -> ProcessTracker {
+        ProcessTracker {
+            system: sysinfo::System::new(),
+            users: Users::new_with_refreshed_list(),
+        }
+    }
+}
 
-    for i in 0..2 {
-        // For every interface, counter-pattern bytes_in by the rx bytes
-        if let Ok(int) = sys.networks() {
-            let interface = int.iter().next().unwrap().0.to_string();
+impl ProcessTracker {
+    /// Returns the number of processes running and the busiest of them
+    ///
+    /// # Arguments
+    /// * `count: usize` - Number of processes to report by CPU usage and by memory, a process can be in both
+    /// * `redact_cmdline: bool` - Whether only the executable is reported instead of the whole command line
+    ///
+    /// # Returns
+    /// `(i32, Vec<ProcessInfo>)` - Number of processes and the busiest ones, by CPU usage first
+    pub fn sample(&mut self, count: usize, redact_cmdline: bool) -> (i32, Vec<ProcessInfo>) {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_cpu()
+                .with_memory()
+                .with_user(UpdateKind::OnlyIfNotSet)
+                .with_cmd(UpdateKind::OnlyIfNotSet)
+                .with_exe(UpdateKind::OnlyIfNotSet),
+        );
+
+        let processes = self.system.processes();
+        let total = i32::try_from(processes.len()).unwrap_or(i32::MAX);
+
+        if count == 0 {
+            return (total, Vec::new());
+        }
 
-            bytes_in += match sys.network_stats(&interface) {
-                // If it's the first loop, add the bytes, if it's the second, subtract them
-                Ok(v) => v.tx_bytes.0 as i64 * (if i == 0 { -1 } else { 1 }),
-                Err(_) => 0,
+        // Threads share the memory and CPU time of their process so only processes and kernel threads are ranked
+        let mut ranked: Vec<&sysinfo::Process> =
+            processes.values().filter(|process| process.thread_kind() != Some(ThreadKind::Userland)).collect();
+
+        // Idle processes aren't reported by CPU usage, so nothing is on the first sample
+        ranked.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage()));
+        let mut top: Vec<&sysinfo::Process> =
+            ranked.iter().filter(|process| process.cpu_usage() > 0.0).take(count).copied().collect();
+
+        ranked.sort_by_key(|process| std::cmp::Reverse(process.memory()));
+        for process in ranked.into_iter().take(count) {
+            if !top.iter().any(|seen| seen.pid() == process.pid()) {
+                top.push(process);
             }
         }
 
-        thread::sleep(Duration::from_secs(1));
+        // Users are only looked up again when a process belongs to one that wasn't there before
+        if top.iter().any(|process| process.user_id().is_some_and(|uid| self.users.get_user_by_id(uid).is_none())) {
+            self.users.refresh();
+        }
+
+        let top = top
+            .into_iter()
+            .map(|process| {
+                let name = process.name().to_string_lossy().to_string();
+
+                // Kernel threads have no command line, ps shows them with their name in brackets
+                let cmdline = match (process.cmd().is_empty(), redact_cmdline) {
+                    (true, _) => format!("[{name}]"),
+                    (false, true) => process
+                        .exe()
+                        .map(|exe| exe.to_string_lossy().to_string())
+                        .unwrap_or_else(|| process.cmd()[0].to_string_lossy().to_string()),
+                    (false, false) => process.cmd().iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" "),
+                };
+
+                ProcessInfo {
+                    pid: i64::from(process.pid().as_u32()),
+                    name,
+                    user_name: process
+                        .user_id()
+                        .and_then(|uid| self.users.get_user_by_id(uid))
+                        .map(|user| user.name().to_string())
+                        .unwrap_or_default(),
+                    cpu_usage: process.cpu_usage() / 100.0,
+                    rss: process.memory() as i64,
+                    cmdline,
+                }
+            })
+            .collect();
+
+        (total, top)
     }
+}
 
-    bytes_in
+/// Counters of a network interface since it came up
+#[derive(Clone, Copy, Default)]
+struct NetworkCounters {
+    rx_bytes: u64,
+    tx_bytes: u64,
+    rx_packets: u64,
+    tx_packets: u64,
+    rx_errors: u64,
+    tx_errors: u64,
+    rx_drops: u64,
+    tx_drops: u64,
 }
 
-pub fn get_network_out(sys: &impl Platform) -> i64 {
-    // Amount of bytes transmitted
-    let mut bytes_out: i64 = 0;
+/// Keeps the counters of every network interface between samples so their traffic can be worked out without waiting
+#[derive(Default)]
+pub struct NetworkTracker {
+    /// Counters of each interface at the previous sample
+    previous: HashMap<String, NetworkCounters>,
+    /// When the previous sample was taken
+    last_sample: Option<Instant>,
+}
+
+impl NetworkTracker {
+    /// Returns the traffic of every interface except loopback since the previous call
+    ///
+    /// The first call has nothing to compare against so it only keeps the counters and returns an empty `Vec`,
+    /// interfaces that just appeared are left out for the same reason
+    ///
+    /// # Arguments
+    /// * `sys: &impl Platform` - Lists the interfaces
+    /// * `root: &Path` - Root of the filesystem sysfs is read from, `/` outside of tests
+    pub fn sample(&mut self, sys: &impl Platform, root: &Path) -> Vec<NetworkInterface> {
+        let now = Instant::now();
+        let elapsed = self.last_sample.map(|then| now.duration_since(then).as_secs_f64()).unwrap_or(0.0);
+
+        let mut current = HashMap::new();
 
-    for i in 0..2 {
-        // For every interface, counter-pattern bytes_in by the tx bytes
-        if let Ok(int) = sys.networks() {
-            let interface = int.iter().next().unwrap().0.to_string();
+        for (name, network) in sys.networks().unwrap_or_default() {
+            let loopback = name == "lo" || network.addrs.iter().any(|addrs| match addrs.addr {
+                IpAddr::V4(ip) => ip.is_loopback(),
+                IpAddr::V6(ip) => ip.is_loopback(),
+                _ => false,
+            });
 
-            bytes_out += match sys.network_stats(&interface) {
-                // If it's the first loop, add the bytes, if it's the second, subtract them
-                Ok(v) => v.tx_bytes.0 as i64 * (if i == 0 { -1 } else { 1 }),
-                Err(_) => 0,
+            if !loopback {
+                if let Some(counters) = read_network_counters(sys, root, &name) {
+                    current.insert(name, counters);
+                }
             }
         }
 
-        thread::sleep(Duration::from_secs(1));
+        let mut interfaces: Vec<NetworkInterface> = current
+            .iter()
+            .filter(|_| elapsed > 0.0)
+            .filter_map(|(name, now)| {
+                let then = self.previous.get(name)?;
+
+                // Counters go back to 0 when an interface is reset so they're never allowed to go negative
+                let per_second = |now: u64, then: u64| now.saturating_sub(then) as f64 / elapsed;
+                let rate = |now: u64, then: u64| per_second(now, then).round() as i64;
+
+                Some(NetworkInterface {
+                    interface: name.to_string(),
+                    rx_bytes: rate(now.rx_bytes, then.rx_bytes),
+                    tx_bytes: rate(now.tx_bytes, then.tx_bytes),
+                    rx_packets: rate(now.rx_packets, then.rx_packets),
+                    tx_packets: rate(now.tx_packets, then.tx_packets),
+                    rx_errors: per_second(now.rx_errors, then.rx_errors) as f32,
+                    tx_errors: per_second(now.tx_errors, then.tx_errors) as f32,
+                    rx_drops: per_second(now.rx_drops, then.rx_drops) as f32,
+                    tx_drops: per_second(now.tx_drops, then.tx_drops) as f32,
+                })
+            })
+            .collect();
+
+        interfaces.sort_by(|a, b| a.interface.cmp(&b.interface));
+
+        self.previous = current;
+        self.last_sample = Some(now);
+
+        interfaces
+    }
+}
+
+/// Reads the counters of a network interface
+///
+/// The statistics in sysfs are used on Linux as they include dropped packets, other systems don't report drops
+fn read_network_counters(sys: &impl Platform, root: &Path, interface: &str) -> Option<NetworkCounters> {
+    let statistics = root.join("sys/class/net").join(interface).join("statistics");
+
+    if statistics.is_dir() {
+        let read = |name: &str| -> Option<u64> { fs::read_to_string(statistics.join(name)).ok()?.trim().parse().ok() };
+
+        return Some(NetworkCounters {
+            rx_bytes: read("rx_bytes")?,
+            tx_bytes: read("tx_bytes")?,
+            rx_packets: read("rx_packets")?,
+            tx_packets: read("tx_packets")?,
+            rx_errors: read("rx_errors")?,
+            tx_errors: read("tx_errors")?,
+            rx_drops: read("rx_dropped").unwrap_or(0),
+            tx_drops: read("tx_dropped").unwrap_or(0),
+        });
     }
 
-    bytes_out
+    sys.network_stats(interface).ok().map(|stats| NetworkCounters {
+        rx_bytes: stats.rx_bytes.0,
+        tx_bytes: stats.tx_bytes.0,
+        rx_packets: stats.rx_packets,
+        tx_packets: stats.tx_packets,
+        rx_errors: stats.rx_errors,
+        tx_errors: stats.tx_errors,
+        ..NetworkCounters::default()
+    })
+}
+
+/// Returns what the device is, fields that can't be read are left empty or 0
+pub fn get_inventory() -> Inventory {
+    let mut sys = sysinfo::System::new();
+    sys.refresh_cpu_list(CpuRefreshKind::nothing());
+    sys.refresh_memory();
+
+    let threads = sys.cpus().len() as i64;
+
+    Inventory {
+        hostname: whoami::fallible::hostname().unwrap_or_else(|_| sysinfo::System::host_name().unwrap_or_default()),
+        os: whoami::distro(),
+        kernel: sysinfo::System::kernel_version().unwrap_or_default(),
+        arch: sysinfo::System::cpu_arch(),
+        cpu_model: sys.cpus().first().map(|cpu| cpu.brand().trim().to_string()).unwrap_or_default(),
+        cpu_cores: sysinfo::System::physical_core_count().map(|cores| cores as i64).unwrap_or(threads),
+        cpu_threads: threads,
+        ram_total: sys.total_memory() as i64,
+        boot_time: sysinfo::System::boot_time() as i64,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        time: 0,
+    }
 }
 
 /// Returns the amount of seconds since the UNIX EPOCH
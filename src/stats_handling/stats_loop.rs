@@ -1,39 +1,66 @@
-use std::{thread, time::Duration};
+use std::{path::Path, thread, time::{Duration, Instant}};
+use serde_json::{json, Value};
 use systemstat::{Platform, System};
 
 use crate::{
-    constants::LOOP_TIME_SECONDS, json_handler::read_client_config_string, socket_handling::{command_type::Commands, client}, stats_handling::{
-        device_info::Device,
-        stats_getter::{
-            get_cpu_usage, get_network_in, get_network_out, get_processes, get_ram_total,
-            get_ram_usage, get_unix_timestamp,
-        },
+    constants::{get_client_config_path, MIN_SAMPLE_INTERVAL_SECONDS, SPOOL_BATCH_SIZE}, json_handler::{read_json_as_value, ToClientConfig}, socket_handling::{command_type::Commands, client, response::RlsdError}, stats_handling::{
+        collector::Registry,
+        device_info::{Device, Inventory},
+        spool,
+        stats_getter::{get_inventory, get_unix_timestamp},
     }
 };
 
 pub async fn start_stats_loop() {
     let result = thread::spawn(|| {
-        let device_id = read_client_config_string("deviceID");
+        let config = read_json_as_value(&get_client_config_path()).to_client();
 
-        let device_name = read_client_config_string("deviceName");
+        // Collectors keep what they need between samples, such as the network counters to work out traffic from
+        let mut registry = Registry::from_config(&config, Path::new("/"));
+
+        println!("Collecting {}", registry.names().join(", "));
+
+        let device_id = config.device_id;
+
+        let device_name = config.device_name;
+
+        // Reports can't be more frequent than samples, and the server drops samples taken closer together than its minimum
+        let sample_interval = config.sample_interval.min(config.interval).max(MIN_SAMPLE_INTERVAL_SECONDS);
+
+        // The server can ask for a longer interval than the configured one
+        let mut interval = config.interval.max(1);
+
+        let mut samples = Vec::new();
+        let mut last_sent: Option<Instant> = None;
+        let mut sent_inventory: Option<Inventory> = None;
 
         loop {
             let sys = &System::new();
 
-            let device = Device::new(
-                &device_id,
-                &device_name,
-                get_ram_usage(sys),
-                get_ram_total(sys),
-                get_cpu_usage(sys),
-                get_processes(),
-                get_network_in(sys),
-                get_network_out(sys),
-                get_unix_timestamp(),
-            );
-
-            client::send(Commands::INPUT, device.to_json());
-            thread::sleep(Duration::from_secs(LOOP_TIME_SECONDS));
+            // Every field is filled by the collectors that are due, the rest are left empty
+            let mut device = Device::empty(&device_id, &device_name, get_unix_timestamp());
+
+            registry.sample(sys, &mut device);
+
+            samples.push(device);
+
+            // Samples are taken every sample_interval but only sent every interval
+            if last_sent.is_none_or(|sent| sent.elapsed() >= Duration::from_secs(interval)) {
+                send_inventory(&device_id, &mut sent_inventory);
+
+                if let Some(allowed) = send_or_spool(&device_id, std::mem::take(&mut samples)) {
+                    let new_interval = config.interval.max(allowed).max(1);
+
+                    if new_interval != interval {
+                        println!("The server allows reports every {allowed} seconds, reporting every {new_interval} seconds");
+                        interval = new_interval;
+                    }
+                }
+
+                last_sent = Some(Instant::now());
+            }
+
+            thread::sleep(Duration::from_secs(sample_interval));
         }
     })
     .join();
@@ -44,3 +71,123 @@ pub async fn start_stats_loop() {
     }
     
 }
+
+/// Sends the inventory if it wasn't sent yet or changed since it was, such as after a kernel update
+///
+/// # Arguments
+/// * `device_id: &str` - ID of this device
+/// * `sent: &mut Option<Inventory>` - Inventory the server last accepted, replaced once a new one is accepted
+fn send_inventory(device_id: &str, sent: &mut Option<Inventory>) {
+    let inventory = get_inventory();
+
+    if sent.as_ref().is_some_and(|sent| !inventory.changed_from(sent)) {
+        return;
+    }
+
+    match client::send(Commands::INVENTORY, json!({"deviceID": device_id, "inventory": inventory.to_json()})) {
+        Ok(_) => *sent = Some(inventory),
+        Err(e) => eprintln!("Couldn't send the inventory, trying again at the next report: {e}"),
+    }
+}
+
+/// Sends the samples taken since the last report in one batch, keeping them in the spool if the server can't be reached,
+/// doesn't confirm them, fails to store them or they were sent too soon
+///
+/// Samples already in the spool are sent first so the server gets them in order
+///
+/// # Arguments
+/// * `device_id: &str` - ID of this device
+/// * `samples: Vec<Device>` - Samples to send, oldest first
+///
+/// # Returns
+/// `Option<u64>` - Interval the server allows this device to report at, if it replied with one
+fn send_or_spool(device_id: &str, samples: Vec<Device>) -> Option<u64> {
+    if spool::is_empty() {
+        return match send_batch(device_id, &samples) {
+            Ok(reply) => advertised_interval(&reply["data"]),
+            Err(RlsdError::Server { code: 429, data, .. }) => {
+                samples.iter().for_each(spool::push);
+                advertised_interval(&data)
+            }
+            Err(RlsdError::Connection(e)) => {
+                eprintln!("Couldn't connect to the server, keeping the samples to send later: {e}");
+                samples.iter().for_each(spool::push);
+                None
+            }
+            // The server may have stored them before the reply was lost, sending them again is safe as it skips samples it has
+            Err(RlsdError::Protocol(e)) => {
+                eprintln!("The server didn't confirm the samples, keeping them to send later: {e}");
+                samples.iter().for_each(spool::push);
+                None
+            }
+            // The server couldn't store them this time, they'll be sent again
+            Err(RlsdError::Server { code: 500..=599, message, .. }) => {
+                eprintln!("The server couldn't store the samples, keeping them to send later: {message}");
+                samples.iter().for_each(spool::push);
+                None
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                None
+            }
+        };
+    }
+
+    samples.iter().for_each(spool::push);
+
+    flush_spool(device_id)
+}
+
+/// Sends the spool to the server in batches, oldest first, stopping at the first batch that isn't accepted
+///
+/// # Arguments
+/// * `device_id: &str` - ID of this device
+///
+/// # Returns
+/// `Option<u64>` - Interval the server allows this device to report at, if it replied with one
+fn flush_spool(device_id: &str) -> Option<u64> {
+    let mut devices = spool::load();
+
+    let mut interval = None;
+
+    while !devices.is_empty() {
+        let count = devices.len().min(SPOOL_BATCH_SIZE);
+
+        match send_batch(device_id, &devices[..count]) {
+            Ok(reply) => interval = advertised_interval(&reply["data"]),
+            // The batch will never be accepted, drop it so it doesn't hold up the rest
+            Err(RlsdError::Server { code: 400, message, .. }) => eprintln!("Dropping {count} spooled samples: {message}"),
+            // The rest is sent at the next report
+            Err(RlsdError::Server { code: 429, data, .. }) => return advertised_interval(&data),
+            Err(e) => {
+                eprintln!("Couldn't send spooled samples, trying again later: {e}");
+                break;
+            }
+        }
+
+        devices.drain(..count);
+
+        spool::replace(&devices);
+    }
+
+    interval
+}
+
+/// Sends samples with `BATCH_INPUT`, stamped with the current time so the server can check this device's clock
+///
+/// # Arguments
+/// * `device_id: &str` - ID of this device
+/// * `samples: &[Device]` - Samples to send
+fn send_batch(device_id: &str, samples: &[Device]) -> Result<Value, RlsdError> {
+    let samples: Vec<Value> = samples.iter().map(|device| device.clone().to_json()).collect();
+
+    client::send(Commands::BatchInput, json!({"deviceID": device_id, "sentAt": get_unix_timestamp(), "samples": samples}))
+}
+
+/// Reads the interval the server advertised in a reply's data
+///
+/// # Arguments
+/// * `data: &Value` - Data sent with the reply
+fn advertised_interval(data: &Value) -> Option<u64> {
+    data["interval"].as_u64()
+}
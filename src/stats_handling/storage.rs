@@ -0,0 +1,204 @@
+//! Where the server keeps devices, their samples and rollups
+//!
+//! The server and TUI only talk to a `Storage`, SQLite is used unless the server config sets a `databaseUrl`
+//! for another backend such as PostgreSQL (built with the `postgres` feature). Every backend runs the same migrations
+//! from `migrations`, so they're written in SQL both databases understand
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+
+use crate::stats_handling::{
+    database::SampleQuery,
+    device_info::{Device, DeviceRecord, FilesystemUsage, Inventory, ProcessInfo, Rollup},
+};
+
+/// A database holding everything the server stores, shared between the server's tasks and the TUI
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Runs the migrations that haven't been applied yet
+    ///
+    /// # Returns
+    /// * `Ok()` - The schema is up to date
+    /// * `Err(sqlx::migrate::MigrateError)` - A migration failed
+    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError>;
+
+    /// Inserts data into the database, every sample is inserted in one transaction so either all or none are stored
+    ///
+    /// Devices that aren't in the `devices` registry yet are added with the name of their sample,
+    /// the registry's name is only changed by `rename_device`. Samples a device already has a sample at the time of are skipped
+    /// with their details, so a batch that was stored but never confirmed can be sent again
+    ///
+    /// # Arguments
+    /// * `devices: &[Device]` - Samples to insert
+    ///
+    /// # Returns
+    /// * `Ok()` - Insertion succeeds
+    /// * `Err(sqlx::Error)` - Insertion fails
+    async fn input_data(&self, devices: &[Device]) -> Result<(), sqlx::Error>;
+
+    /// Checks if a device is in the `devices` registry
+    ///
+    /// # Arguments
+    /// * `id: &str` - Device id to look for
+    async fn check_device_id_exists(&self, id: &str) -> bool;
+
+    /// Get all the different device uids
+    ///
+    /// # Returns
+    /// `HashSet<String>` - Contains all the different device uids
+    async fn get_all_device_uids(&self) -> HashSet<String>;
+
+    /// Gets every device in the registry
+    ///
+    /// # Returns
+    /// `Vec<DeviceRecord>` - Devices sorted by name
+    async fn get_devices(&self) -> Vec<DeviceRecord>;
+
+    /// Gets the name of a device in the registry
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to get the name of
+    async fn get_device_name_from_uid(&self, device_id: &str) -> String;
+
+    /// Loads the samples of a device picked by a query, oldest first
+    ///
+    /// # Arguments
+    /// * `query: &SampleQuery` - Which samples to load and what to load of them
+    ///
+    /// # Returns
+    /// `Vec<Device>` - Samples with the details that were asked for
+    async fn get_samples(&self, query: &SampleQuery) -> Vec<Device>;
+
+    /// Loads every sample of a device taken at or after a time, with all of their details
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to load the samples of
+    /// * `since_timestamp: i64` - Unix timestamp of the oldest sample to load
+    async fn get_device_stats_after(&self, device_id: &str, since_timestamp: i64) -> Vec<Device> {
+        self.get_samples(&SampleQuery::new(device_id, since_timestamp, i64::MAX)).await
+    }
+
+    /// Loads the buckets of a rollup tier between two times, oldest first
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to load the rollups of
+    /// * `resolution: i64` - Bucket size of the tier to load
+    /// * `from: i64` - Unix timestamp, the bucket holding it is the first one loaded
+    /// * `to: i64` - Unix timestamp of the start of the last bucket to load
+    ///
+    /// # Returns
+    /// `Vec<Rollup>` - A row per value per bucket, empty if the range hasn't been rolled up
+    async fn get_rollups(&self, device_id: &str, resolution: i64, from: i64, to: i64) -> Vec<Rollup>;
+
+    /// Gets the filesystems reported in the latest sample of a device
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to get the filesystems of
+    ///
+    /// # Returns
+    /// `Vec<FilesystemUsage>` - Filesystems sorted by mount point, empty if the device never reported any
+    async fn get_latest_filesystems(&self, device_id: &str) -> Vec<FilesystemUsage>;
+
+    /// Stores the inventory of a device, unless it's the same as the latest one stored
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device the inventory is from
+    /// * `inventory: &Inventory` - Inventory sent by the device, its `time` is when it was received
+    ///
+    /// # Returns
+    /// `Result<bool, sqlx::Error>` - Whether a row was added
+    async fn input_inventory(&self, device_id: &str, inventory: &Inventory) -> Result<bool, sqlx::Error>;
+
+    /// Gets the latest inventory of a device
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to get the inventory of
+    ///
+    /// # Returns
+    /// `Option<Inventory>` - The inventory, `None` if the device never sent one
+    async fn get_inventory(&self, device_id: &str) -> Option<Inventory>;
+
+    /// Gets the busiest processes of a device at a point in time
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to get the processes of
+    /// * `time: i64` - Unix timestamp, the latest sample taken at or before it is used
+    ///
+    /// # Returns
+    /// `Vec<ProcessInfo>` - Processes sorted by CPU usage, empty if the device didn't report any by then
+    async fn get_processes_at(&self, device_id: &str, time: i64) -> Vec<ProcessInfo>;
+
+    /// Removes all rows with the supplied device_id, along with the device itself from the registry
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device id to search for
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Number of samples removed, plus one if the device was in the registry
+    /// * `Err(sqlx::Error)` - The query failed
+    async fn remove_device(&self, device_id: &str) -> Result<u64, sqlx::Error>;
+
+    /// Changes the name of a device in the registry, its samples aren't touched
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device id to search for
+    /// * `device_name: &str` - New name of the device
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Number of devices renamed, 0 if the device isn't in the registry
+    /// * `Err(sqlx::Error)` - The query failed
+    async fn rename_device(&self, device_id: &str, device_name: &str) -> Result<u64, sqlx::Error>;
+
+    /// Rebuilds a device's rollups from a time onwards, every tier's bucket holding that time is rebuilt along with the ones after it
+    ///
+    /// The 15 minute tier is made from the samples and the coarser tiers from the tier before them,
+    /// all in one transaction so readers never see a tier half built
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to roll up
+    /// * `since: i64` - Unix timestamp of the oldest sample that changed
+    ///
+    /// # Returns
+    /// * `Ok()` - Every tier was rebuilt
+    /// * `Err(sqlx::Error)` - A query failed, nothing was changed
+    async fn roll_up(&self, device_id: &str, since: i64) -> Result<(), sqlx::Error>;
+
+    /// Finds where each device's rollups have to be rebuilt from when the server starts,
+    /// the start of its latest 15 minute bucket or when it was first seen if it hasn't been rolled up yet
+    ///
+    /// # Returns
+    /// `HashMap<String, i64>` - Unix timestamp to roll up from for each device id
+    async fn get_rollup_starts(&self) -> HashMap<String, i64>;
+
+    /// Counts what pruning a device would remove without removing anything
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to count the data of
+    /// * `samples_before: Option<i64>` - Unix timestamp samples older than would be removed, `None` keeps them
+    /// * `rollups_before: Option<i64>` - Unix timestamp rollups older than would be removed, `None` keeps them
+    ///
+    /// # Returns
+    /// * `Ok((u64, u64))` - Number of samples and rollup rows that would be removed
+    /// * `Err(sqlx::Error)` - A query failed
+    async fn count_expired(&self, device_id: &str, samples_before: Option<i64>, rollups_before: Option<i64>) -> Result<(u64, u64), sqlx::Error>;
+
+    /// Removes a device's samples with their details and its rollups once they're older than its retention,
+    /// oldest first in batches of `PRUNE_BATCH_SIZE` samples or rollup rows
+    ///
+    /// # Arguments
+    /// * `device_id: &str` - Device to prune
+    /// * `samples_before: Option<i64>` - Unix timestamp samples older than are removed, `None` keeps them
+    /// * `rollups_before: Option<i64>` - Unix timestamp rollups older than are removed, `None` keeps them
+    ///
+    /// # Returns
+    /// * `Ok((u64, u64))` - Number of samples and rollup rows removed
+    /// * `Err(sqlx::Error)` - A query failed, batches removed before it stay removed
+    async fn prune_device(&self, device_id: &str, samples_before: Option<i64>, rollups_before: Option<i64>) -> Result<(u64, u64), sqlx::Error>;
+
+    /// Hands the space freed by pruning back to the filesystem
+    ///
+    /// # Returns
+    /// * `Ok()` - The free space was released
+    /// * `Err(sqlx::Error)` - Vacuuming failed
+    async fn reclaim_space(&self) -> Result<(), sqlx::Error>;
+}
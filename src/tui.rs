@@ -5,14 +5,13 @@ use crossterm::{
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     symbols,
-    text::Span,
-    widgets::{Axis, Block, Borders, Chart, Dataset, LegendPosition, Paragraph, Tabs},
-    Terminal,
+    text::{Line, Span},
+    widgets::{Axis, Block, Borders, Cell, Chart, Dataset, GraphType, LegendPosition, Paragraph, Row, Table, Tabs},
+    Frame, Terminal,
 };
-use sqlx::{Pool, Sqlite};
 use std::{
     cmp::Ordering,
     collections::HashMap,
@@ -22,12 +21,14 @@ use std::{
 
 use crate::{
     constants::{
-        DOWN_SAMPLE_POINTS, DO_INTERPOLATION, INTERPOLATION_STEPS
+        DOWN_SAMPLE_POINTS, DO_INTERPOLATION, INTERPOLATION_STEPS, SENSOR_AC, SENSOR_BATTERY, SENSOR_TEMPERATURE, TUI_MAX_SAMPLES,
     },
+    json_handler::{read_server_config_value, ToServerConfig},
     stats_handling::{
         conversions::{format_bytes, format_time, get_byte_unit, get_time_unit, Unit},
-        database::{self, get_device_stats_after},
-        device_info::Device,
+        database::SampleQuery,
+        device_info::{Device, FilesystemUsage, Inventory, NetworkInterface, ProcessInfo},
+        storage::Storage,
     },
 };
 
@@ -67,12 +68,93 @@ impl TimeRange {
     }
 }
 
+/// Name, points and color of a line on a chart
+type Series<'a> = (&'a str, &'a [(f64, f64)], Color);
+
+/// Colors given to charts with a line per sensor, red and yellow are left for the thresholds
+const SERIES_COLORS: [Color; 8] = [
+    Color::Green,
+    Color::Cyan,
+    Color::Magenta,
+    Color::Blue,
+    Color::LightGreen,
+    Color::LightCyan,
+    Color::LightMagenta,
+    Color::LightBlue,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Overview,
+    Cpu,
+    Disk,
+    Network,
+    Sensors,
+    Processes,
+    Metrics,
+    Details,
+}
+
+impl View {
+    fn all() -> Vec<View> {
+        use View::*;
+        vec![Overview, Cpu, Disk, Network, Sensors, Processes, Metrics, Details]
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            View::Overview => "Overview",
+            View::Cpu => "CPU",
+            View::Disk => "Disk",
+            View::Network => "Network",
+            View::Sensors => "Sensors",
+            View::Processes => "Processes",
+            View::Metrics => "Metrics",
+            View::Details => "Details",
+        }
+    }
+
+    /// Returns the columns and details of each sample the view charts, the rest aren't loaded
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            View::Overview => &[
+                "cpu_usage", "ram_used", "ram_total", "ram_buffers", "ram_cached", "swap_total", "swap_used", "zfs_arc",
+                "network_in", "network_out", "disk_io",
+            ],
+            View::Cpu => &["cpu_cores", "cpu_freq", "cpu_freq_max", "load_1", "load_5", "load_15"],
+            View::Network => &["network_interfaces"],
+            View::Sensors => &["sensors"],
+            View::Processes => &["cpu_usage", "ram_used", "ram_total"],
+            View::Metrics => &["metrics"],
+            // Filesystems, processes and the inventory are loaded on their own
+            View::Disk | View::Details => &[],
+        }
+    }
+}
+
 struct App {
     device_names: Vec<String>,
     device_ids: Vec<String>,
     selected_device: usize,
     time_range_index: usize,
+    view_index: usize,
+    /// Interface shown on the network view, 0 is every interface added together
+    interface_index: usize,
+    /// Custom metric charted on the metrics view
+    metric_index: usize,
     metrics_cache: HashMap<String, Vec<Device>>,
+    /// Filesystems of the selected device from its latest sample
+    filesystems: Vec<FilesystemUsage>,
+    /// Temperature in degrees Celsius marked as a warning on the sensors view
+    temp_warning: f64,
+    /// Temperature in degrees Celsius marked as critical on the sensors view
+    temp_critical: f64,
+    /// Sample shown on the processes view, `None` follows the latest one
+    process_time: Option<i64>,
+    /// Busiest processes of the selected device at the shown sample
+    processes: Vec<ProcessInfo>,
+    /// Latest inventory of the selected device
+    inventory: Option<Inventory>,
     last_updated: Instant,
 }
 
@@ -88,16 +170,103 @@ impl App {
         TimeRange::all()[self.time_range_index]
     }
 
-    async fn refresh_data(&mut self, database: &Pool<Sqlite>) {
-        if let Some(device_id) = self.selected_device_id() {
-            let since = chrono::Utc::now().timestamp() - self.selected_time_range().duration_secs();
-            let data = get_device_stats_after(&database, device_id, since).await;
+    fn selected_view(&self) -> View {
+        View::all()[self.view_index]
+    }
+
+    /// Returns the names of every interface the selected device reported in the loaded samples
+    fn interface_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .selected_device_id()
+            .and_then(|device_id| self.metrics_cache.get(device_id))
+            .map(|data| data.iter().flat_map(|d| d.interfaces.iter().map(|i| i.interface.clone())).collect())
+            .unwrap_or_default();
+
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
+    /// Returns the names of every custom metric the selected device reported in the loaded samples
+    fn metric_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .selected_device_id()
+            .and_then(|device_id| self.metrics_cache.get(device_id))
+            .map(|data| data.iter().flat_map(|d| d.metrics.keys().cloned()).collect())
+            .unwrap_or_default();
+
+        names.sort();
+        names.dedup();
 
-            self.metrics_cache.insert(device_id.to_string(), data);
+        names
+    }
+
+    /// Returns the interface selected on the network view, `None` if every interface is shown
+    fn selected_interface(&self) -> Option<String> {
+        match self.interface_index {
+            0 => None,
+            index => self.interface_names().get(index - 1).cloned(),
         }
+    }
+
+    /// Returns the times of the selected device's samples in the time range, oldest first
+    fn sample_times(&self) -> Vec<i64> {
+        let time_min = chrono::Utc::now().timestamp() - self.selected_time_range().duration_secs();
+
+        self.selected_device_id()
+            .and_then(|device_id| self.metrics_cache.get(device_id))
+            .map(|data| data.iter().map(|d| d.time).filter(|time| *time >= time_min).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the time of the sample shown on the processes view
+    fn selected_process_time(&self) -> Option<i64> {
+        self.process_time.or_else(|| self.sample_times().last().copied())
+    }
+
+    /// Moves the processes view to an older or newer sample, moving past the newest follows the latest sample again
+    ///
+    /// # Arguments
+    /// * `older: bool` - Whether to move to the previous sample rather than the next one
+    fn step_process_time(&mut self, older: bool) {
+        let times = self.sample_times();
+
+        let Some(current) = self.selected_process_time() else {
+            return;
+        };
+
+        self.process_time = if older {
+            times.iter().rev().find(|time| **time < current).or(times.first()).copied()
+        } else {
+            times.iter().find(|time| **time > current).copied().filter(|time| Some(time) != times.last())
+        };
+    }
+
+    async fn refresh_data(&mut self, database: &dyn Storage) {
+        if let Some(device_id) = self.selected_device_id().map(str::to_string) {
+            let now = chrono::Utc::now().timestamp();
+            let query = SampleQuery::new(&device_id, now - self.selected_time_range().duration_secs(), now)
+                .with_columns(self.selected_view().columns())
+                .with_limit(TUI_MAX_SAMPLES);
+            let data = database.get_samples(&query).await;
+
+            self.filesystems = database.get_latest_filesystems(&device_id).await;
+            self.inventory = database.get_inventory(&device_id).await;
+            self.metrics_cache.insert(device_id, data);
+        }
+        self.load_processes(database).await;
         self.last_updated = Instant::now();
     }
 
+    /// Loads the processes of the sample shown on the processes view
+    async fn load_processes(&mut self, database: &dyn Storage) {
+        self.processes = match (self.selected_device_id(), self.selected_process_time()) {
+            (Some(device_id), Some(time)) => database.get_processes_at(device_id, time).await,
+            _ => Vec::new(),
+        };
+    }
+
     fn make_chart<'a>(
         &self,
         data: &'a [(f64, f64)],
@@ -126,6 +295,34 @@ impl App {
         chart
     }
 
+    fn make_multi_chart<'a>(
+        &self,
+        series: &[Series<'a>],
+        unit: Unit,
+        time: i64,
+        min: f64,
+        max: f64,
+        title: &'a str,
+    ) -> Chart<'a> {
+        let datasets = series
+            .iter()
+            .map(|(name, data, color)| {
+                Dataset::default()
+                    .name(*name)
+                    .marker(symbols::Marker::Dot)
+                    .style(Style::default().fg(*color))
+                    .data(data)
+            })
+            .collect();
+
+        let chart = Chart::new(datasets)
+            .legend_position(Some(LegendPosition::TopLeft))
+            .hidden_legend_constraints((Constraint::Percentage(50), Constraint::Percentage(60)))
+            .block(Block::default().title(title).borders(Borders::ALL));
+
+        self.detail_chart(chart, unit, time, min, max)
+    }
+
     fn detail_chart<'a>(&self, chart: Chart<'a>, unit: Unit, time: i64, min: f64, max: f64) -> Chart<'a> {
         let time = time as u128;
 
@@ -168,7 +365,7 @@ impl App {
     }
 }
 
-pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn start_tui(database: &dyn Storage) -> Result<(), Box<dyn std::error::Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -179,20 +376,31 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
     let mut device_ids: Vec<String> = Vec::new();
 
     // Load device IDs and names from DB
-    for device_id in database::get_all_device_uids(&database).await.iter() {
-        let device_name = database::get_device_name_from_uid(&database, device_id).await;
+    for device_id in database.get_all_device_uids().await.iter() {
+        let device_name = database.get_device_name_from_uid(device_id).await;
         device_names.push(device_name);
         device_ids.push(device_id.clone());
     }
 
     device_bubble_sort(&mut device_names, &mut device_ids);
 
+    let config = read_server_config_value().to_server();
+
     let mut app = App {
         device_names,
         device_ids,
         selected_device: 0,
         time_range_index: 0,
+        view_index: 0,
+        interface_index: 0,
+        metric_index: 0,
         metrics_cache: HashMap::new(),
+        filesystems: Vec::new(),
+        temp_warning: config.temp_warning,
+        temp_critical: config.temp_critical,
+        process_time: None,
+        processes: Vec::new(),
+        inventory: None,
         last_updated: Instant::now() - Duration::from_secs(999),
     };
 
@@ -200,13 +408,13 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
 
     loop {
         if app.last_updated.elapsed().as_secs() > 10 {
-            app.refresh_data(&database).await;
+            app.refresh_data(database).await;
 
             let mut device_names: Vec<String> = Vec::new();
             let mut device_ids: Vec<String> = Vec::new();
 
-            for device_id in database::get_all_device_uids(&database).await.iter() {
-                let device_name = database::get_device_name_from_uid(&database, device_id).await;
+            for device_id in database.get_all_device_uids().await.iter() {
+                let device_name = database.get_device_name_from_uid(device_id).await;
                 device_names.push(device_name);
                 device_ids.push(device_id.clone());
             }
@@ -244,147 +452,41 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
             // Gets the time range as a str
             let time_range = TimeRange::all()[app.time_range_index].as_str();
 
-            // Allows the user to cycle through how much data should be shown
-            let time_range_selector = Paragraph::new(Span::styled(
-                format!("[ {} ▲ ▼ ]", time_range),
-                Style::default().fg(Color::Green),
-            ));
-            f.render_widget(time_range_selector, chunks[1]);
+            // Allows the user to cycle through how much data should be shown and which charts show it
+            let mut selector = vec![
+                Span::styled(format!("[ {} ▲ ▼ ]", time_range), Style::default().fg(Color::Green)),
+                Span::raw("  [ "),
+            ];
+
+            for view in View::all() {
+                let style = if view == app.selected_view() {
+                    Style::default().fg(Color::Green)
+                } else {
+                    Style::default().fg(Color::Gray)
+                };
+
+                selector.push(Span::styled(format!("{} ", view.as_str()), style));
+            }
+
+            selector.push(Span::raw("v ]"));
+
+            f.render_widget(Paragraph::new(Line::from(selector)), chunks[1]);
 
             if let Some(device_id) = app.selected_device_id() {
                 if let Some(data) = app.metrics_cache.get(device_id) {
                     let now = chrono::Utc::now().timestamp();
                     let time_min = now - app.selected_time_range().duration_secs();
-                    let unfiltered_data: Vec<_> = data.iter().filter(|d| d.time >= time_min).collect();
-
-                    let graph_chunks = Layout::default()
-                        .direction(Direction::Vertical)
-                        .constraints([
-                            Constraint::Percentage(33),
-                            Constraint::Percentage(33),
-                            Constraint::Percentage(34),
-                        ])
-                        .split(chunks[2]);
-
-                    // Makes all the datasets
-                    let data = make_dataset(time_min, data);
-
-                    // Assigns the different vectors to their respective values
-                    let cpu_data = data.get(0).unwrap();
-                    let ram_data = data.get(1).unwrap();
-                    let network_in_data = data.get(2).unwrap();
-                    let network_out_data = data.get(3).unwrap();
-
-                    let duration = app.selected_time_range().duration_secs();
-
-                    let (cpu_min, cpu_max) = find_min_max(&cpu_data);
-
-                    // let cpu_data = down_sample(&interpolate(&cpu_data, 4), 10);
-                    let cpu_data = down_sample(&cpu_data, 40);
-
-                    let cpu_chart = app.make_chart(
-                        &cpu_data,
-                        Unit::Percentage,
-                        duration,
-                        cpu_min,
-                        cpu_max,
-                        "CPU",
-                        Color::Green,
-                    );
-
-                    f.render_widget(cpu_chart, graph_chunks[0]);
-
-                    // RAM Chart
-
-                    // Gets the largest value from the vector
-                    let (ram_min, ram_max) = find_min_max(&ram_data); 
-                    // Makes RAM the chart
-                    let ram_unit = get_byte_unit(
-                        unfiltered_data
-                            .iter()
-                            .map(|d| d.ram_used)
-                            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                            .unwrap_or(0) as usize,
-                        Unit::BYTE,
-                    );
-
-                    let ram_chart = app.make_chart(
-                        &ram_data,
-                        ram_unit.clone(),
-                        duration,
-                        ram_min,
-                        ram_max,
-                        "RAM",
-                        Color::Red,
-                    );
-
-                    f.render_widget(ram_chart, graph_chunks[1]);
-
-                    // Network Chart
-                    let (network_in_min, network_in_max) = find_min_max(&network_in_data);
-
-                    // Get the network in max from the unfiltered data
-                    let network_in_unit = get_byte_unit(
-                        unfiltered_data
-                            .iter()
-                            .map(|d| d.network_in)
-                            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                            .unwrap_or(0) as usize,
-                        Unit::BYTE);
-
-                    let (network_out_min, network_out_max) = find_min_max(&network_out_data);
-
-                    // Get the network out max from the unfiltered data
-                    let network_out_unit = get_byte_unit(
-                        unfiltered_data
-                            .iter()
-                            .map(|d| d.network_out)
-                            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                            .unwrap_or(0) as usize,
-                        Unit::BYTE);
-
-                    let (network_min, network_max, network_unit): (f64, f64, Unit) = {
-                        let min = network_in_min.min(network_out_min);
-
-                        if network_in_max > network_out_max {
-                            (min, network_in_max, network_in_unit)
-                        } else {
-                            (min, network_out_max, network_out_unit)
-                        }
-                    };
-
-                    let mut network_chart = Chart::new(vec![
-                        Dataset::default()
-                            .name("Network In")
-                            .marker(symbols::Marker::Dot)
-                            .style(Style::default().fg(Color::Magenta))
-                            .data(&network_in_data),
-                        Dataset::default()
-                            .name("Network Out")
-                            .marker(symbols::Marker::Dot)
-                            .style(Style::default().fg(Color::Cyan))
-                            .data(&network_out_data),
-                    ])
-                    .legend_position(Some(LegendPosition::TopLeft))
-                    .hidden_legend_constraints((
-                        Constraint::Percentage(50),
-                        Constraint::Percentage(60),
-                    ))
-                    .block(
-                        Block::default()
-                            .title("Network Usage")
-                            .borders(Borders::ALL),
-                    );
-
-                    network_chart = app.detail_chart(
-                        network_chart,
-                        network_unit.clone(),
-                        duration,
-                        network_min,
-                        network_max,
-                    );
-
-                    f.render_widget(network_chart, graph_chunks[2]);
+
+                    match app.selected_view() {
+                        View::Overview => draw_overview(f, &app, chunks[2], data, time_min),
+                        View::Cpu => draw_cpu(f, &app, chunks[2], data, time_min),
+                        View::Disk => draw_disk(f, &app, chunks[2]),
+                        View::Network => draw_network(f, &app, chunks[2], data, time_min),
+                        View::Sensors => draw_sensors(f, &app, chunks[2], data, time_min),
+                        View::Processes => draw_processes(f, &app, chunks[2], data, time_min),
+                        View::Metrics => draw_metrics(f, &app, chunks[2], data, time_min),
+                        View::Details => draw_details(f, &app, chunks[2], data),
+                    }
                 }
             }
         })?;
@@ -393,9 +495,23 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
             if let Event::Key(key) = event::read()? {
                 match key.code {
                     KeyCode::Char('q') => break,
+                    KeyCode::Char('v') => {
+                        app.view_index = (app.view_index + 1) % View::all().len();
+                        app.refresh_data(database).await;
+                    }
+                    KeyCode::Char('i') => app.interface_index = (app.interface_index + 1) % (app.interface_names().len() + 1),
+                    KeyCode::Char('m') => app.metric_index = (app.metric_index + 1) % app.metric_names().len().max(1),
+                    KeyCode::Char('[') => {
+                        app.step_process_time(true);
+                        app.load_processes(database).await;
+                    }
+                    KeyCode::Char(']') => {
+                        app.step_process_time(false);
+                        app.load_processes(database).await;
+                    }
                     KeyCode::Tab => {
                         app.selected_device = (app.selected_device + 1) % app.device_names.len();
-                        app.refresh_data(&database).await;
+                        app.refresh_data(database).await;
                     }
                     KeyCode::BackTab => {
                         if app.selected_device == 0 {
@@ -403,7 +519,7 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
                         } else {
                             app.selected_device -= 1;
                         }
-                        app.refresh_data(&database).await;
+                        app.refresh_data(database).await;
                     }
                     KeyCode::Left => {
                         if app.selected_device == 0 {
@@ -411,7 +527,7 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
                         } else {
                             app.selected_device -= 1;
                         }
-                        app.refresh_data(&database).await
+                        app.refresh_data(database).await
                     }
                     KeyCode::Right => {
                         if app.selected_device == app.device_names.len() - 1 {
@@ -419,11 +535,11 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
                         } else {
                             app.selected_device += 1;
                         }
-                        app.refresh_data(&database).await
+                        app.refresh_data(database).await
                     }
                     KeyCode::Up => {
                         app.time_range_index = (app.time_range_index + 1) % TimeRange::all().len();
-                        app.refresh_data(&database).await;
+                        app.refresh_data(database).await;
                     }
                     KeyCode::Down => {
                         if app.time_range_index == 0 {
@@ -431,7 +547,7 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
                         } else {
                             app.time_range_index -= 1;
                         }
-                        app.refresh_data(&database).await;
+                        app.refresh_data(database).await;
                     }
                     _ => {}
                 }
@@ -449,69 +565,878 @@ pub async fn start_tui(database: &Pool<Sqlite>) -> Result<(), Box<dyn std::error
     Ok(())
 }
 
-fn device_bubble_sort(device_names: &mut Vec<String>, device_ids: &mut Vec<String>) {
-    let n = device_names.len();
-    for i in 0..n {
-        for j in 0..n - 1 - i {
-            if device_names[j] > device_names[j + 1] {
-                device_names.swap(j, j + 1);
-                device_ids.swap(j, j + 1);
-            }
+/// Draws the CPU, RAM and network charts
+fn draw_overview(f: &mut Frame, app: &App, area: Rect, data: &Vec<Device>, time_min: i64) {
+    let unfiltered_data: Vec<_> = data.iter().filter(|d| d.time >= time_min).collect();
+
+    let graph_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+        ])
+        .split(area);
+
+    // Memory is stacked, each line is the one below it plus its own share, all in the unit of the largest total
+    let ram_unit = get_byte_unit(
+        unfiltered_data
+            .iter()
+            .flat_map(|d| [d.ram_total, stacked_memory(d)])
+            .flatten()
+            .max()
+            .unwrap_or(0) as usize,
+        Unit::BYTE,
+    );
+    let ram_scale = ram_unit.to_f64();
+    let ram_total = unfiltered_data.iter().filter_map(|d| d.ram_total).max().unwrap_or(0) as f64 / ram_scale;
+
+    let ram_used_data = make_series(time_min, data, |d| {
+        Some((d.ram_used? - d.zfs_arc.unwrap_or(0)).max(0) as f64 / ram_scale)
+    });
+    let ram_arc_data = make_series(time_min, data, |d| Some(d.ram_used? as f64 / ram_scale));
+    let ram_cache_data = make_series(time_min, data, |d| {
+        Some((d.ram_used? + d.ram_buffers? + d.ram_cached?) as f64 / ram_scale)
+    });
+    let ram_swap_data = make_series(time_min, data, |d| Some(stacked_memory(d)? as f64 / ram_scale));
+
+    // Disk I/O of every disk added together, all in the unit of the busiest sample
+    let disk_unit = get_byte_unit(
+        unfiltered_data
+            .iter()
+            .flat_map(|d| d.disk_io.iter().map(|disk| disk.read_bytes.max(disk.write_bytes)))
+            .max()
+            .unwrap_or(0) as usize,
+        Unit::BYTE,
+    );
+    let disk_scale = disk_unit.to_f64();
+
+    // Samples without disks are left out, the disk collector didn't run for them
+    let disk_read_data = make_series(time_min, data, |d| {
+        (!d.disk_io.is_empty()).then(|| d.disk_io.iter().map(|disk| disk.read_bytes).sum::<i64>() as f64 / disk_scale)
+    });
+    let disk_write_data = make_series(time_min, data, |d| {
+        (!d.disk_io.is_empty()).then(|| d.disk_io.iter().map(|disk| disk.write_bytes).sum::<i64>() as f64 / disk_scale)
+    });
+
+    // Operations per second of the latest sample with disks
+    let (read_ops, write_ops) = unfiltered_data
+        .iter()
+        .rev()
+        .find(|d| !d.disk_io.is_empty())
+        .map(|d| d.disk_io.iter().fold((0, 0), |(read, write), disk| (read + disk.read_ops, write + disk.write_ops)))
+        .unwrap_or((0, 0));
+    let disk_title = format!("Disk I/O per second ({read_ops} reads/s, {write_ops} writes/s)");
+
+    // Makes all the datasets
+    let data = make_dataset(time_min, data);
+
+    // Assigns the different vectors to their respective values
+    let cpu_data = data.get(0).unwrap();
+    let network_in_data = data.get(2).unwrap();
+    let network_out_data = data.get(3).unwrap();
+
+    let duration = app.selected_time_range().duration_secs();
+
+    let (cpu_min, cpu_max) = find_min_max(&cpu_data);
+
+    // let cpu_data = down_sample(&interpolate(&cpu_data, 4), 10);
+    let cpu_data = down_sample(&cpu_data, 40);
+
+    let cpu_chart = app.make_chart(
+        &cpu_data,
+        Unit::Percentage,
+        duration,
+        cpu_min,
+        cpu_max,
+        "CPU",
+        Color::Green,
+    );
+
+    f.render_widget(cpu_chart, graph_chunks[0]);
+
+    // RAM Chart, ZFS ARC and swap are only shown on devices that have them
+    let mut memory_series: Vec<Series> = vec![("Used", &ram_used_data, Color::Red)];
+
+    if unfiltered_data.iter().any(|d| d.zfs_arc.is_some_and(|arc| arc > 0)) {
+        memory_series.push(("ZFS ARC", &ram_arc_data, Color::LightMagenta));
+    }
+
+    memory_series.push(("Buffers/Cache", &ram_cache_data, Color::Blue));
+
+    if unfiltered_data.iter().any(|d| d.swap_total.is_some_and(|swap| swap > 0)) {
+        memory_series.push(("Swap", &ram_swap_data, Color::Yellow));
+    }
+
+    let ram_max = memory_series.iter().map(|(_, series, _)| find_min_max(series).1).fold(ram_total, f64::max);
+
+    let ram_chart = app.make_multi_chart(
+        &memory_series,
+        ram_unit,
+        duration,
+        0.0,
+        ram_max,
+        "Memory (stacked)",
+    );
+
+    f.render_widget(ram_chart, graph_chunks[1]);
+
+    // Network Chart
+    let (network_in_min, network_in_max) = find_min_max(&network_in_data);
+
+    // Get the network in max from the unfiltered data
+    let network_in_unit = get_byte_unit(
+        unfiltered_data
+            .iter()
+            .filter_map(|d| d.network_in)
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .unwrap_or(0) as usize,
+        Unit::BYTE);
+
+    let (network_out_min, network_out_max) = find_min_max(&network_out_data);
+
+    // Get the network out max from the unfiltered data
+    let network_out_unit = get_byte_unit(
+        unfiltered_data
+            .iter()
+            .filter_map(|d| d.network_out)
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .unwrap_or(0) as usize,
+        Unit::BYTE);
+
+    let (network_min, network_max, network_unit): (f64, f64, Unit) = {
+        let min = network_in_min.min(network_out_min);
+
+        if network_in_max > network_out_max {
+            (min, network_in_max, network_in_unit)
+        } else {
+            (min, network_out_max, network_out_unit)
         }
+    };
+
+    let mut network_chart = Chart::new(vec![
+        Dataset::default()
+            .name("Network In")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::Magenta))
+            .data(&network_in_data),
+        Dataset::default()
+            .name("Network Out")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&network_out_data),
+    ])
+    .legend_position(Some(LegendPosition::TopLeft))
+    .hidden_legend_constraints((
+        Constraint::Percentage(50),
+        Constraint::Percentage(60),
+    ))
+    .block(
+        Block::default()
+            .title("Network Usage")
+            .borders(Borders::ALL),
+    );
+
+    network_chart = app.detail_chart(
+        network_chart,
+        network_unit.clone(),
+        duration,
+        network_min,
+        network_max,
+    );
+
+    f.render_widget(network_chart, graph_chunks[2]);
+
+    // Disk I/O Chart
+    let disk_max = find_min_max(&disk_read_data).1.max(find_min_max(&disk_write_data).1);
+
+    let disk_chart = app.make_multi_chart(
+        &[("Read", &disk_read_data, Color::Yellow), ("Write", &disk_write_data, Color::Blue)],
+        disk_unit,
+        duration,
+        0.0,
+        disk_max,
+        &disk_title,
+    );
+
+    f.render_widget(disk_chart, graph_chunks[3]);
+}
+
+/// Draws the per-core heatmap, the load averages and the CPU frequency
+fn draw_cpu(f: &mut Frame, app: &App, area: Rect, data: &[Device], time_min: i64) {
+    let filtered: Vec<&Device> = data.iter().filter(|d| d.time >= time_min).collect();
+    let cores = filtered.iter().map(|d| d.cpu_cores.len()).max().unwrap_or(0);
+    let duration = app.selected_time_range().duration_secs();
+
+    let graph_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length((cores as u16 + 2).min(area.height / 2)),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ])
+        .split(area);
+
+    // Per-core heatmap, one row per core and one column per slice of the time range
+    let label_width = format!("cpu{} ", cores.saturating_sub(1)).len();
+    let columns = (graph_chunks[0].width as usize).saturating_sub(2 + label_width);
+
+    let rows: Vec<Line> = (0..cores)
+        .map(|core| {
+            let mut cells = vec![Span::raw(format!("{:<label_width$}", format!("cpu{core}")))];
+
+            cells.extend(core_heatmap(&filtered, core, time_min, duration, columns).into_iter().map(|usage| match usage {
+                Some(usage) => heat_cell(usage),
+                None => Span::raw(" "),
+            }));
+
+            Line::from(cells)
+        })
+        .collect();
+
+    let heatmap = Paragraph::new(rows).block(
+        Block::default()
+            .title("Per-core Usage (░ <25% ▒ <50% ▓ <75% █ ≥75%)")
+            .borders(Borders::ALL),
+    );
+
+    f.render_widget(heatmap, graph_chunks[0]);
+
+    // Load average chart
+    let load_1 = make_series(time_min, data, |d| d.load_1.map(f64::from));
+    let load_5 = make_series(time_min, data, |d| d.load_5.map(f64::from));
+    let load_15 = make_series(time_min, data, |d| d.load_15.map(f64::from));
+
+    let load_max = [&load_1, &load_5, &load_15]
+        .iter()
+        .map(|series| find_min_max(series).1)
+        .fold(0.0, f64::max);
+
+    let load_chart = app.make_multi_chart(
+        &[
+            ("1 min", &load_1, Color::Green),
+            ("5 min", &load_5, Color::Yellow),
+            ("15 min", &load_15, Color::Red),
+        ],
+        Unit::Number,
+        duration,
+        0.0,
+        load_max,
+        "Load Average",
+    );
+
+    f.render_widget(load_chart, graph_chunks[1]);
+
+    // Frequency chart
+    let freq = make_series(time_min, data, |d| d.cpu_freq.map(|freq| freq as f64));
+    let freq_max = make_series(time_min, data, |d| d.cpu_freq_max.map(|freq| freq as f64));
+
+    let freq_top = find_min_max(&freq).1.max(find_min_max(&freq_max).1);
+
+    let freq_chart = app.make_multi_chart(
+        &[("Current", &freq, Color::Cyan), ("Max", &freq_max, Color::Magenta)],
+        Unit::MEGAHERTZ,
+        duration,
+        0.0,
+        freq_top,
+        "CPU Frequency",
+    );
+
+    f.render_widget(freq_chart, graph_chunks[2]);
+}
+
+/// Draws a usage bar for every filesystem of the device
+fn draw_disk(f: &mut Frame, app: &App, area: Rect) {
+    let mount_width = app.filesystems.iter().map(|fs| fs.mount_point.len()).max().unwrap_or(0).min(30);
+    let type_width = app.filesystems.iter().map(|fs| fs.fs_type.len()).max().unwrap_or(0);
+    // Space left for the bar after the borders, names, percentage and sizes
+    let bar_width = (area.width as usize).saturating_sub(mount_width + type_width + 2 + 45).max(10);
+
+    let rows: Vec<Line> = app
+        .filesystems
+        .iter()
+        .map(|fs| {
+            let usage = share(fs.used, fs.used + fs.free);
+            let inode_usage = share(fs.inodes_used, fs.inodes_total);
+            let filled = ((usage * bar_width as f64).round() as usize).min(bar_width);
+
+            let color = match usage {
+                usage if usage < 0.7 => Color::Green,
+                usage if usage < 0.9 => Color::Yellow,
+                _ => Color::Red,
+            };
+
+            Line::from(vec![
+                Span::raw(format!("{:<mount_width$.mount_width$} {:<type_width$} ", fs.mount_point, fs.fs_type)),
+                Span::styled("█".repeat(filled), Style::default().fg(color)),
+                Span::styled("░".repeat(bar_width - filled), Style::default().fg(Color::DarkGray)),
+                Span::raw(format!(
+                    " {:>5.1}% {:>10} / {:<10} inodes {:>5.1}%",
+                    usage * 100.0,
+                    bytes_label(fs.used),
+                    bytes_label(fs.total),
+                    inode_usage * 100.0
+                )),
+            ])
+        })
+        .collect();
+
+    let disks = if rows.is_empty() {
+        Paragraph::new("No filesystems reported by this device")
+    } else {
+        Paragraph::new(rows)
+    };
+
+    f.render_widget(disks.block(Block::default().title("Disk Usage").borders(Borders::ALL)), area);
+}
+
+/// Draws the traffic, packets and errors of the selected interface
+fn draw_network(f: &mut Frame, app: &App, area: Rect, data: &[Device], time_min: i64) {
+    let selected = app.selected_interface();
+    let duration = app.selected_time_range().duration_secs();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ])
+        .split(area);
+
+    // Allows the user to cycle through the interfaces
+    let mut selector = vec![Span::raw("[ ")];
+
+    for (index, name) in std::iter::once("All".to_string()).chain(app.interface_names()).enumerate() {
+        // The index can be past the end after switching to a device with fewer interfaces, every interface is shown then
+        let style = if index == app.interface_index || (index == 0 && selected.is_none()) {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default().fg(Color::Gray)
+        };
+
+        selector.push(Span::styled(format!("{name} "), style));
     }
+
+    selector.push(Span::raw("i ]"));
+
+    f.render_widget(Paragraph::new(Line::from(selector)), chunks[0]);
+
+    // Adds up a value over the selected interface, or every interface if none is selected
+    // Samples without interfaces give nothing, the network collector didn't run for them
+    let total = |d: &Device, value: fn(&NetworkInterface) -> f64| -> Option<f64> {
+        (!d.interfaces.is_empty()).then(|| {
+            d.interfaces
+                .iter()
+                .filter(|interface| selected.as_ref().is_none_or(|name| &interface.interface == name))
+                .map(value)
+                .sum::<f64>()
+        })
+    };
+
+    // Traffic chart, all in the unit of the busiest sample
+    let busiest = data
+        .iter()
+        .filter(|d| d.time >= time_min)
+        .filter_map(|d| Some(total(d, |i| i.rx_bytes as f64)?.max(total(d, |i| i.tx_bytes as f64)?)))
+        .fold(0.0, f64::max);
+    let traffic_unit = get_byte_unit(busiest as usize, Unit::BYTE);
+    let traffic_scale = traffic_unit.to_f64();
+
+    let rx_bytes = make_series(time_min, data, |d| Some(total(d, |i| i.rx_bytes as f64)? / traffic_scale));
+    let tx_bytes = make_series(time_min, data, |d| Some(total(d, |i| i.tx_bytes as f64)? / traffic_scale));
+    let traffic_max = find_min_max(&rx_bytes).1.max(find_min_max(&tx_bytes).1);
+
+    let traffic_chart = app.make_multi_chart(
+        &[("Received", &rx_bytes, Color::Magenta), ("Sent", &tx_bytes, Color::Cyan)],
+        traffic_unit,
+        duration,
+        0.0,
+        traffic_max,
+        "Traffic per second",
+    );
+
+    f.render_widget(traffic_chart, chunks[1]);
+
+    // Packets chart
+    let rx_packets = make_series(time_min, data, |d| total(d, |i| i.rx_packets as f64));
+    let tx_packets = make_series(time_min, data, |d| total(d, |i| i.tx_packets as f64));
+    let packets_max = find_min_max(&rx_packets).1.max(find_min_max(&tx_packets).1);
+
+    let packets_chart = app.make_multi_chart(
+        &[("Received", &rx_packets, Color::Magenta), ("Sent", &tx_packets, Color::Cyan)],
+        Unit::Number,
+        duration,
+        0.0,
+        packets_max,
+        "Packets per second",
+    );
+
+    f.render_widget(packets_chart, chunks[2]);
+
+    // Errors and drops chart
+    let rx_errors = make_series(time_min, data, |d| total(d, |i| i.rx_errors.into()));
+    let tx_errors = make_series(time_min, data, |d| total(d, |i| i.tx_errors.into()));
+    let rx_drops = make_series(time_min, data, |d| total(d, |i| i.rx_drops.into()));
+    let tx_drops = make_series(time_min, data, |d| total(d, |i| i.tx_drops.into()));
+
+    let problems_max = [&rx_errors, &tx_errors, &rx_drops, &tx_drops]
+        .iter()
+        .map(|series| find_min_max(series).1)
+        .fold(0.0, f64::max);
+
+    let problems_chart = app.make_multi_chart(
+        &[
+            ("Receive errors", &rx_errors, Color::Red),
+            ("Send errors", &tx_errors, Color::LightRed),
+            ("Received dropped", &rx_drops, Color::Yellow),
+            ("Sent dropped", &tx_drops, Color::LightYellow),
+        ],
+        Unit::Number,
+        duration,
+        0.0,
+        problems_max,
+        "Errors and drops per second",
+    );
+
+    f.render_widget(problems_chart, chunks[3]);
 }
 
-pub fn make_dataset(time_min: i64, data: &Vec<Device>) -> Vec<Vec<(f64, f64)>> {
-    let filtered: Vec<_> = data.iter().filter(|d| d.time >= time_min).collect();
+/// Draws every temperature sensor against the warning and critical thresholds, and the battery state
+fn draw_sensors(f: &mut Frame, app: &App, area: Rect, data: &[Device], time_min: i64) {
+    let filtered: Vec<&Device> = data.iter().filter(|d| d.time >= time_min).collect();
+    let duration = app.selected_time_range().duration_secs();
 
-    let mut datasets: Vec<Vec<(f64, f64)>> = vec![Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Length(3)])
+        .split(area);
 
-    // Take the data and make it usable on the charts
-    // CPU usage
-    let cpu_data: Vec<(f64, f64)> = filtered
+    let mut labels: Vec<&str> = filtered
         .iter()
-        .map(|d| ((d.time - time_min) as f64, (d.cpu_usage * 100.0) as f64))
+        .flat_map(|d| d.sensors.iter().filter(|s| s.kind == SENSOR_TEMPERATURE).map(|s| s.label.as_str()))
         .collect();
 
-    // Ram Usage
-    let ram_data: Vec<(f64, f64)> = filtered
+    labels.sort();
+    labels.dedup();
+
+    // Samples without the sensor are skipped rather than charted as 0
+    let temperatures: Vec<Vec<(f64, f64)>> = labels
         .iter()
-        .map(|d| {
-            (
-                (d.time - time_min) as f64,
-                format_bytes(d.ram_used as f64, Unit::BYTE),
-            )
+        .map(|label| {
+            let points: Vec<(f64, f64)> = filtered
+                .iter()
+                .filter_map(|d| {
+                    let sensor = d.sensors.iter().find(|s| s.label == *label)?;
+
+                    Some(((d.time - time_min) as f64, sensor.value as f64))
+                })
+                .collect();
+
+            filter(&points, DO_INTERPOLATION)
         })
         .collect();
 
-    // Network In
-    let network_in_data: Vec<(f64, f64)> = filtered
+    let warning = [(0.0, app.temp_warning), (duration as f64, app.temp_warning)];
+    let critical = [(0.0, app.temp_critical), (duration as f64, app.temp_critical)];
+
+    let mut datasets: Vec<Dataset> = labels
         .iter()
-        .map(|d| {
-            (
-                (d.time - time_min) as f64,
-                format_bytes(d.network_in as f64, Unit::BYTE),
-            )
+        .zip(&temperatures)
+        .enumerate()
+        .map(|(index, (label, points))| {
+            Dataset::default()
+                .name(*label)
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(SERIES_COLORS[index % SERIES_COLORS.len()]))
+                .data(points)
         })
         .collect();
 
-    // Network Out
-    let network_out_data: Vec<(f64, f64)> = filtered
+    datasets.push(
+        Dataset::default()
+            .name("Warning")
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&warning),
+    );
+    datasets.push(
+        Dataset::default()
+            .name("Critical")
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Red))
+            .data(&critical),
+    );
+
+    // Leaves some room above the hottest reading or the critical line
+    let hottest = temperatures.iter().map(|points| find_min_max(points).1).fold(0.0, f64::max);
+    let top = (hottest.max(app.temp_critical) / 10.0).ceil() * 10.0 + 5.0;
+
+    let chart = Chart::new(datasets)
+        .legend_position(Some(LegendPosition::TopLeft))
+        .hidden_legend_constraints((Constraint::Percentage(50), Constraint::Percentage(60)))
+        .block(Block::default().title("Temperatures").borders(Borders::ALL));
+
+    f.render_widget(app.detail_chart(chart, Unit::CELSIUS, duration, 0.0, top), chunks[0]);
+
+    // Current state from the latest sample with sensors
+    let mut status = Vec::new();
+
+    if let Some(latest) = filtered.iter().rev().find(|d| !d.sensors.is_empty()) {
+        if let Some(hottest) = latest
+            .sensors
+            .iter()
+            .filter(|s| s.kind == SENSOR_TEMPERATURE)
+            .max_by(|a, b| a.value.partial_cmp(&b.value).unwrap_or(Ordering::Equal))
+        {
+            let color = match hottest.value as f64 {
+                value if value >= app.temp_critical => Color::Red,
+                value if value >= app.temp_warning => Color::Yellow,
+                _ => Color::Green,
+            };
+
+            status.push(Span::styled(format!("Hottest: {} {:.1}°C  ", hottest.label, hottest.value), Style::default().fg(color)));
+        }
+
+        match latest.sensors.iter().find(|s| s.kind == SENSOR_BATTERY) {
+            Some(battery) => {
+                let power = match latest.sensors.iter().find(|s| s.kind == SENSOR_AC) {
+                    Some(ac) if ac.value > 0.0 => " (on AC)",
+                    Some(_) => " (on battery)",
+                    None => "",
+                };
+
+                status.push(Span::raw(format!("Battery: {:.0}%{power}", battery.value)));
+            }
+            None => status.push(Span::raw("No battery")),
+        }
+    }
+
+    let status = Paragraph::new(Line::from(status)).block(Block::default().title("Now").borders(Borders::ALL));
+
+    f.render_widget(status, chunks[1]);
+}
+
+/// Draws the CPU and memory usage with the selected sample marked, and the busiest processes at that sample
+fn draw_processes(f: &mut Frame, app: &App, area: Rect, data: &[Device], time_min: i64) {
+    let duration = app.selected_time_range().duration_secs();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(35), Constraint::Fill(1)])
+        .split(area);
+
+    let cpu = make_series(time_min, data, |d| Some(d.cpu_usage? as f64 * 100.0));
+    let memory = make_series(time_min, data, |d| Some(share(d.ram_used?, d.ram_total?) * 100.0));
+
+    let selected_time = app.selected_process_time();
+    let marker: Vec<(f64, f64)> = selected_time
+        .map(|time| vec![((time - time_min) as f64, 0.0), ((time - time_min) as f64, 100.0)])
+        .unwrap_or_default();
+
+    let chart = Chart::new(vec![
+        Dataset::default()
+            .name("CPU")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::Green))
+            .data(&cpu),
+        Dataset::default()
+            .name("Memory")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&memory),
+        Dataset::default()
+            .name("Selected")
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&marker),
+    ])
+    .legend_position(Some(LegendPosition::TopLeft))
+    .hidden_legend_constraints((Constraint::Percentage(50), Constraint::Percentage(60)))
+    .block(Block::default().title("Usage ([ older, ] newer)").borders(Borders::ALL));
+
+    f.render_widget(app.detail_chart(chart, Unit::Percentage, duration, 0.0, 100.0), chunks[0]);
+
+    let title = match selected_time.and_then(|time| chrono::DateTime::from_timestamp(time, 0)) {
+        Some(time) => format!(
+            "Processes at {}{}",
+            time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+            if app.process_time.is_none() { " (latest)" } else { "" }
+        ),
+        None => "Processes".to_string(),
+    };
+
+    let header = Row::new(["PID", "User", "CPU", "Memory", "Name", "Command"]).style(Style::default().fg(Color::Green));
+
+    let rows: Vec<Row> = app
+        .processes
         .iter()
-        .map(|d| {
-            (
-                (d.time - time_min) as f64,
-                format_bytes(d.network_out as f64, Unit::BYTE),
-            )
+        .map(|process| {
+            Row::new([
+                Cell::from(process.pid.to_string()),
+                Cell::from(process.user_name.clone()),
+                Cell::from(format!("{:.1}%", process.cpu_usage * 100.0)),
+                Cell::from(bytes_label(process.rss)),
+                Cell::from(process.name.clone()),
+                Cell::from(process.cmdline.clone()),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(16),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header)
+    .block(Block::default().title(title).borders(Borders::ALL));
+
+    f.render_widget(table, chunks[1]);
+}
+
+/// Draws the selected custom metric and a summary of every custom metric in the time range
+fn draw_metrics(f: &mut Frame, app: &App, area: Rect, data: &[Device], time_min: i64) {
+    let filtered: Vec<&Device> = data.iter().filter(|d| d.time >= time_min).collect();
+    let duration = app.selected_time_range().duration_secs();
+    let names = app.metric_names();
+
+    if names.is_empty() {
+        let empty = Paragraph::new("No custom metrics, add commands to the client config or push them to its socket")
+            .block(Block::default().title("Metrics").borders(Borders::ALL));
+
+        f.render_widget(empty, area);
+        return;
+    }
+
+    // The index can be past the end after switching to a device with fewer metrics
+    let selected = &names[app.metric_index.min(names.len() - 1)];
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Fill(1)])
+        .split(area);
+
+    // Samples without the metric are skipped rather than charted as 0
+    let points: Vec<(f64, f64)> = filtered
+        .iter()
+        .filter_map(|d| Some(((d.time - time_min) as f64, *d.metrics.get(selected)?)))
+        .collect();
+    let points = filter(&points, DO_INTERPOLATION);
+
+    // Custom metrics can be negative, so the range isn't taken from `find_min_max`
+    let low = points.iter().map(|(_, value)| *value).fold(f64::MAX, f64::min).min(0.0);
+    let high = points.iter().map(|(_, value)| *value).fold(f64::MIN, f64::max).max(low + 1.0);
+
+    let chart = Chart::new(vec![Dataset::default()
+        .name(selected.as_str())
+        .marker(symbols::Marker::Dot)
+        .style(Style::default().fg(Color::Green))
+        .data(&points)])
+    .legend_position(Some(LegendPosition::TopLeft))
+    .hidden_legend_constraints((Constraint::Percentage(50), Constraint::Percentage(60)))
+    .block(Block::default().title(format!("{selected} (m next)")).borders(Borders::ALL));
+
+    f.render_widget(app.detail_chart(chart, Unit::Number, duration, low, high), chunks[0]);
+
+    let header = Row::new(["Metric", "Latest", "Min", "Max", "Average"]).style(Style::default().fg(Color::Green));
+
+    let rows: Vec<Row> = names
+        .iter()
+        .map(|name| {
+            let values: Vec<f64> = filtered.iter().filter_map(|d| d.metrics.get(name).copied()).collect();
+
+            let style = if name == selected {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default()
+            };
+
+            let summary = |value: Option<f64>| value.map(|value| format!("{value:.2}")).unwrap_or_else(|| "-".to_string());
+
+            Row::new([
+                name.clone(),
+                summary(values.last().copied()),
+                summary(values.iter().copied().reduce(f64::min)),
+                summary(values.iter().copied().reduce(f64::max)),
+                summary((!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)),
+            ])
+            .style(style)
         })
         .collect();
 
-    datasets[0] = filter(&cpu_data, DO_INTERPOLATION);
-    datasets[1] = filter(&ram_data, DO_INTERPOLATION);
-    datasets[2] = filter(&network_in_data, DO_INTERPOLATION);
-    datasets[3] = filter(&network_out_data, DO_INTERPOLATION);
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(1),
+            Constraint::Length(14),
+            Constraint::Length(14),
+            Constraint::Length(14),
+            Constraint::Length(14),
+        ],
+    )
+    .header(header)
+    .block(Block::default().title("Custom metrics").borders(Borders::ALL));
+
+    f.render_widget(table, chunks[1]);
+}
+
+/// Draws what the selected device is and when it last reported
+fn draw_details(f: &mut Frame, app: &App, area: Rect, data: &[Device]) {
+    let timestamp = |time: i64| {
+        chrono::DateTime::from_timestamp(time, 0)
+            .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    };
+
+    let last_sample = data.last().map(|d| d.time);
+
+    let mut lines: Vec<Line> = match &app.inventory {
+        // The uptime is as of the latest sample so it doesn't keep going up once the device stops reporting
+        Some(inventory) => inventory
+            .lines(last_sample.unwrap_or_else(|| chrono::Utc::now().timestamp()))
+            .into_iter()
+            .map(Line::from)
+            .collect(),
+        None => vec![Line::from("This device hasn't reported its inventory")],
+    };
+
+    lines.push(Line::from(""));
+
+    if let Some(inventory) = &app.inventory {
+        lines.push(Line::from(format!("Inventory reported: {}", timestamp(inventory.time))));
+    }
+
+    lines.push(Line::from(match last_sample {
+        Some(time) => format!("Latest sample: {}", timestamp(time)),
+        None => "No samples in this time range".to_string(),
+    }));
+
+    let details = Paragraph::new(lines).block(Block::default().title("Device").borders(Borders::ALL));
+
+    f.render_widget(details, area);
+}
+
+/// Returns the top of the stacked memory chart, RAM used with buffers, cache and swap on top
+///
+/// # Returns
+/// `Option<i64>` - `None` if the memory collector didn't run for the sample
+fn stacked_memory(d: &Device) -> Option<i64> {
+    Some(d.ram_used? + d.ram_buffers? + d.ram_cached? + d.swap_used?)
+}
+
+/// Returns `part / whole`, 0 if `whole` is 0
+fn share(part: i64, whole: i64) -> f64 {
+    if whole > 0 {
+        part as f64 / whole as f64
+    } else {
+        0.0
+    }
+}
+
+/// Formats bytes in the largest unit that keeps them above 1, such as `1.5GiB`
+fn bytes_label(bytes: i64) -> String {
+    format!(
+        "{:.1}{}",
+        format_bytes(bytes as f64, Unit::BYTE),
+        get_byte_unit(bytes.max(0) as usize, Unit::BYTE)
+    )
+}
+
+/// Averages the usage of a core over each column of the heatmap
+///
+/// # Arguments
+/// * `data: &[&Device]` - Samples in the time range
+/// * `core: usize` - Core to average
+/// * `time_min: i64` - Start of the time range
+/// * `duration: i64` - Length of the time range in seconds
+/// * `columns: usize` - Number of columns to split the range into
+///
+/// # Returns
+/// `Vec<Option<f32>>` - Average usage of each column, `None` if there's no sample in it
+fn core_heatmap(data: &[&Device], core: usize, time_min: i64, duration: i64, columns: usize) -> Vec<Option<f32>> {
+    if columns == 0 || duration <= 0 {
+        return Vec::new();
+    }
+
+    let mut sums = vec![(0.0, 0); columns];
+
+    for device in data {
+        if let Some(usage) = device.cpu_cores.get(core) {
+            let column = ((device.time - time_min) * columns as i64 / duration).clamp(0, columns as i64 - 1) as usize;
+
+            sums[column].0 += usage;
+            sums[column].1 += 1;
+        }
+    }
+
+    sums.iter()
+        .map(|(sum, count)| if *count > 0 { Some(sum / *count as f32) } else { None })
+        .collect()
+}
+
+/// Makes a heatmap cell, denser and redder as the usage goes up
+fn heat_cell(usage: f32) -> Span<'static> {
+    let (symbol, color) = match usage {
+        usage if usage < 0.25 => ("░", Color::Green),
+        usage if usage < 0.5 => ("▒", Color::Yellow),
+        usage if usage < 0.75 => ("▓", Color::LightRed),
+        _ => ("█", Color::Red),
+    };
+
+    Span::styled(symbol, Style::default().fg(color))
+}
+
+fn device_bubble_sort(device_names: &mut Vec<String>, device_ids: &mut Vec<String>) {
+    let n = device_names.len();
+    for i in 0..n {
+        for j in 0..n - 1 - i {
+            if device_names[j] > device_names[j + 1] {
+                device_names.swap(j, j + 1);
+                device_ids.swap(j, j + 1);
+            }
+        }
+    }
+}
+
+pub fn make_dataset(time_min: i64, data: &Vec<Device>) -> Vec<Vec<(f64, f64)>> {
+    // Take the data and make it usable on the charts
+    vec![
+        // CPU usage
+        make_series(time_min, data, |d| Some(d.cpu_usage? as f64 * 100.0)),
+        // Ram Usage
+        make_series(time_min, data, |d| Some(format_bytes(d.ram_used? as f64, Unit::BYTE))),
+        // Network In
+        make_series(time_min, data, |d| Some(format_bytes(d.network_in? as f64, Unit::BYTE))),
+        // Network Out
+        make_series(time_min, data, |d| Some(format_bytes(d.network_out? as f64, Unit::BYTE))),
+    ]
+}
+
+/// Makes a filtered chart series from one value of every sample in the time range
+///
+/// # Arguments
+/// * `time_min: i64` - Start of the time range
+/// * `data: &[Device]` - Samples to take the value from
+/// * `value: impl Fn(&Device) -> Option<f64>` - Gets the value to chart from a sample, `None` if it wasn't collected
+///
+/// # Returns
+/// `Vec<(f64, f64)>` - Seconds since the start of the range and the value, samples without the value are left out
+fn make_series(time_min: i64, data: &[Device], value: impl Fn(&Device) -> Option<f64>) -> Vec<(f64, f64)> {
+    let series: Vec<(f64, f64)> = data
+        .iter()
+        .filter(|d| d.time >= time_min)
+        .filter_map(|d| Some(((d.time - time_min) as f64, value(d)?)))
+        .collect();
 
-    datasets
+    filter(&series, DO_INTERPOLATION)
 }
 
 fn down_sample(data: &[(f64, f64)], target_points: u16) -> Vec<(f64, f64)> {
@@ -0,0 +1,415 @@
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rlsd::{
+    config::client::{ClientConfig, CollectorConfig, CustomCommand},
+    constants::{
+        METRIC_CPU_CORES, METRIC_CPU_FREQ_MAX, METRIC_CPU_USAGE, METRIC_DISK_IO, METRIC_FILESYSTEMS, METRIC_INTERFACES,
+        METRIC_LOAD_1, METRIC_LOAD_15, METRIC_PROCESS_COUNT, METRIC_RAM_BUFFERS, METRIC_RAM_CACHED, METRIC_RAM_TOTAL,
+        METRIC_RAM_USED, METRIC_SENSORS, METRIC_SWAP_TOTAL, METRIC_SWAP_USED, METRIC_TOP_PROCESSES, SENSOR_AC,
+        SENSOR_BATTERY, SENSOR_TEMPERATURE,
+    },
+    stats_handling::{
+        collector::{
+            apply, Collector, CpuCollector, DiskIoCollector, FilesystemCollector, MemoryCollector, Metric, MetricValue,
+            NetworkCollector, ProcessCollector, Registry, SensorCollector,
+        },
+        device_info::{Device, NetworkInterface},
+    },
+};
+use systemstat::{
+    BatteryLife, BlockDeviceStats, ByteSize, CPULoad, DelayedMeasurement, Filesystem, IpAddr, LoadAverage, Memory, Network,
+    NetworkAddrs, NetworkStats, Platform, PlatformCpuLoad, PlatformMemory, SocketStats, Swap,
+};
+
+/// Platform with fixed readings, the disk and network counters go up every time they're read
+#[derive(Default)]
+struct MockPlatform {
+    reads: Cell<usize>,
+}
+
+impl MockPlatform {
+    /// Counts a read of the counters and returns how many came before it
+    fn read(&self) -> usize {
+        let reads = self.reads.get();
+        self.reads.set(reads + 1);
+
+        reads
+    }
+}
+
+fn not_mocked<T>() -> io::Result<T> {
+    Err(io::Error::other("not mocked"))
+}
+
+fn cpu(idle: f32) -> CPULoad {
+    CPULoad { user: 1.0 - idle, nice: 0.0, system: 0.0, interrupt: 0.0, idle, platform: PlatformCpuLoad::zero() }
+}
+
+fn mount(fs_type: &str, from: &str, on: &str, total: u64, free: u64) -> Filesystem {
+    Filesystem {
+        files: 100,
+        files_total: 1000,
+        files_avail: 900,
+        free: ByteSize::b(free),
+        avail: ByteSize::b(free),
+        total: ByteSize::b(total),
+        name_max: 255,
+        fs_type: fs_type.to_string(),
+        fs_mounted_from: from.to_string(),
+        fs_mounted_on: on.to_string(),
+    }
+}
+
+fn block(name: &str, reads: usize) -> BlockDeviceStats {
+    BlockDeviceStats {
+        name: name.to_string(),
+        read_ios: 10 * reads,
+        read_merges: 0,
+        read_sectors: 100 * reads,
+        read_ticks: 0,
+        write_ios: 20 * reads,
+        write_merges: 0,
+        write_sectors: 200 * reads,
+        write_ticks: 0,
+        in_flight: 0,
+        io_ticks: 0,
+        time_in_queue: 0,
+    }
+}
+
+fn network(name: &str, addr: IpAddr) -> Network {
+    Network { name: name.to_string(), addrs: vec![NetworkAddrs { addr, netmask: IpAddr::Empty }] }
+}
+
+impl Platform for MockPlatform {
+    fn new() -> Self {
+        MockPlatform::default()
+    }
+
+    fn cpu_load(&self) -> io::Result<DelayedMeasurement<Vec<CPULoad>>> {
+        Ok(DelayedMeasurement::new(Box::new(|| Ok(vec![cpu(0.75), cpu(0.25)]))))
+    }
+
+    fn load_average(&self) -> io::Result<LoadAverage> {
+        Ok(LoadAverage { one: 1.5, five: 1.0, fifteen: 0.5 })
+    }
+
+    fn memory(&self) -> io::Result<Memory> {
+        Ok(Memory { total: ByteSize::gib(8), free: ByteSize::gib(6), platform_memory: PlatformMemory { meminfo: BTreeMap::new() } })
+    }
+
+    fn swap(&self) -> io::Result<Swap> {
+        Ok(Swap { total: ByteSize::gib(2), free: ByteSize::gib(1), platform_swap: PlatformMemory { meminfo: BTreeMap::new() } })
+    }
+
+    fn uptime(&self) -> io::Result<Duration> {
+        not_mocked()
+    }
+
+    fn battery_life(&self) -> io::Result<BatteryLife> {
+        Ok(BatteryLife { remaining_capacity: 0.5, remaining_time: Duration::from_secs(3600) })
+    }
+
+    fn on_ac_power(&self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn mounts(&self) -> io::Result<Vec<Filesystem>> {
+        Ok(vec![
+            mount("ext4", "/dev/sda1", "/", 1000, 250),
+            mount("ext4", "/dev/sda1", "/srv/bind", 1000, 250),
+            mount("tmpfs", "tmpfs", "/tmp", 1000, 1000),
+            mount("ext4", "/dev/sdb1", "/empty", 0, 0),
+        ])
+    }
+
+    fn block_device_statistics(&self) -> io::Result<BTreeMap<String, BlockDeviceStats>> {
+        let reads = self.read();
+
+        Ok(["sda", "sda1", "loop0"].into_iter().map(|name| (name.to_string(), block(name, reads))).collect())
+    }
+
+    fn networks(&self) -> io::Result<BTreeMap<String, Network>> {
+        Ok(BTreeMap::from([
+            ("mock0".to_string(), network("mock0", IpAddr::V4("192.168.1.2".parse().unwrap()))),
+            ("lo".to_string(), network("lo", IpAddr::V4("127.0.0.1".parse().unwrap()))),
+        ]))
+    }
+
+    fn network_stats(&self, _interface: &str) -> io::Result<NetworkStats> {
+        let reads = self.read() as u64;
+
+        Ok(NetworkStats {
+            rx_bytes: ByteSize::b(1000 * reads),
+            tx_bytes: ByteSize::b(500 * reads),
+            rx_packets: 10 * reads,
+            tx_packets: 5 * reads,
+            rx_errors: reads,
+            tx_errors: 0,
+        })
+    }
+
+    fn cpu_temp(&self) -> io::Result<f32> {
+        not_mocked()
+    }
+
+    fn socket_stats(&self) -> io::Result<SocketStats> {
+        not_mocked()
+    }
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// Directory that doesn't exist so only the platform is read
+fn empty_root() -> PathBuf {
+    fixtures().join("empty")
+}
+
+fn find<'a>(metrics: &'a [Metric], name: &str) -> &'a MetricValue {
+    &metrics.iter().find(|metric| metric.name == name).unwrap_or_else(|| panic!("{name} is missing")).value
+}
+
+fn float(metrics: &[Metric], name: &str) -> f64 {
+    match find(metrics, name) {
+        MetricValue::Float(value) => *value,
+        _ => panic!("{name} isn't a float"),
+    }
+}
+
+fn integer(metrics: &[Metric], name: &str) -> i64 {
+    match find(metrics, name) {
+        MetricValue::Integer(value) => *value,
+        _ => panic!("{name} isn't an integer"),
+    }
+}
+
+/// Collector that counts its runs
+struct CountingCollector {
+    runs: i64,
+}
+
+impl Collector<MockPlatform> for CountingCollector {
+    fn name(&self) -> &str {
+        "counting"
+    }
+
+    fn collect(&mut self, _sys: &MockPlatform) -> Vec<Metric> {
+        self.runs += 1;
+
+        vec![Metric::new("test.runs", MetricValue::Integer(self.runs))]
+    }
+}
+
+#[test]
+fn cpu_collector_reads_usage_load_and_max_frequency() {
+    let metrics = CpuCollector::new(&fixtures()).collect(&MockPlatform::new());
+
+    assert_eq!(float(&metrics, METRIC_CPU_USAGE), 0.5);
+    assert_eq!(float(&metrics, METRIC_LOAD_1), 1.5);
+    assert_eq!(float(&metrics, METRIC_LOAD_15), 0.5);
+    assert_eq!(integer(&metrics, METRIC_CPU_FREQ_MAX), 3600);
+
+    match find(&metrics, METRIC_CPU_CORES) {
+        MetricValue::Cores(cores) => assert_eq!(cores, &[0.25, 0.75]),
+        _ => panic!("cores aren't cores"),
+    }
+}
+
+#[test]
+fn memory_collector_prefers_meminfo() {
+    let metrics = MemoryCollector::new(&fixtures()).collect(&MockPlatform::new());
+
+    assert_eq!(integer(&metrics, METRIC_RAM_TOTAL), 16_000_000 * 1024);
+    assert_eq!(integer(&metrics, METRIC_RAM_USED), 6_000_000 * 1024);
+    assert_eq!(integer(&metrics, METRIC_RAM_BUFFERS), 500_000 * 1024);
+    assert_eq!(integer(&metrics, METRIC_RAM_CACHED), 6_400_000 * 1024);
+    assert_eq!(integer(&metrics, METRIC_SWAP_USED), 1_000_000 * 1024);
+}
+
+#[test]
+fn memory_collector_falls_back_to_the_platform() {
+    let metrics = MemoryCollector::new(&empty_root()).collect(&MockPlatform::new());
+
+    assert_eq!(integer(&metrics, METRIC_RAM_TOTAL), 8 << 30);
+    assert_eq!(integer(&metrics, METRIC_RAM_USED), 2 << 30);
+    assert_eq!(integer(&metrics, METRIC_SWAP_TOTAL), 2 << 30);
+    assert_eq!(integer(&metrics, METRIC_SWAP_USED), 1 << 30);
+}
+
+#[test]
+fn filesystem_collector_skips_virtual_empty_and_repeated_mounts() {
+    let metrics = FilesystemCollector.collect(&MockPlatform::new());
+
+    match find(&metrics, METRIC_FILESYSTEMS) {
+        MetricValue::Filesystems(filesystems) => {
+            assert_eq!(filesystems.len(), 1);
+            assert_eq!(filesystems[0].mount_point, "/");
+            assert_eq!(filesystems[0].used, 750);
+            assert_eq!(filesystems[0].inodes_used, 100);
+        }
+        _ => panic!("filesystems aren't filesystems"),
+    }
+}
+
+#[test]
+fn disk_io_collector_needs_two_runs_and_only_reports_whole_disks() {
+    let sys = MockPlatform::new();
+    let mut collector = DiskIoCollector::new(&fixtures());
+
+    match find(&collector.collect(&sys), METRIC_DISK_IO) {
+        MetricValue::DiskIo(disks) => assert!(disks.is_empty()),
+        _ => panic!("disks aren't disks"),
+    }
+
+    std::thread::sleep(Duration::from_millis(100));
+
+    match find(&collector.collect(&sys), METRIC_DISK_IO) {
+        MetricValue::DiskIo(disks) => {
+            assert_eq!(disks.len(), 1);
+            assert_eq!(disks[0].disk, "sda");
+            assert!(disks[0].read_bytes > 0 && disks[0].write_bytes > disks[0].read_bytes);
+            assert!(disks[0].read_ops > 0 && disks[0].write_ops >= disks[0].read_ops);
+        }
+        _ => panic!("disks aren't disks"),
+    }
+}
+
+#[test]
+fn network_collector_needs_two_runs_and_skips_loopback() {
+    let sys = MockPlatform::new();
+    let mut collector = NetworkCollector::new(&empty_root());
+
+    match find(&collector.collect(&sys), METRIC_INTERFACES) {
+        MetricValue::Interfaces(interfaces) => assert!(interfaces.is_empty()),
+        _ => panic!("interfaces aren't interfaces"),
+    }
+
+    std::thread::sleep(Duration::from_millis(100));
+
+    match find(&collector.collect(&sys), METRIC_INTERFACES) {
+        MetricValue::Interfaces(interfaces) => {
+            assert_eq!(interfaces.len(), 1);
+            assert_eq!(interfaces[0].interface, "mock0");
+            assert!(interfaces[0].rx_bytes > interfaces[0].tx_bytes);
+            assert!(interfaces[0].rx_errors > 0.0 && interfaces[0].tx_errors == 0.0);
+        }
+        _ => panic!("interfaces aren't interfaces"),
+    }
+}
+
+#[test]
+fn sensor_collector_adds_the_battery_to_the_temperatures() {
+    let metrics = SensorCollector::new(&fixtures()).collect(&MockPlatform::new());
+
+    match find(&metrics, METRIC_SENSORS) {
+        MetricValue::Sensors(sensors) => {
+            assert_eq!(sensors.iter().filter(|sensor| sensor.kind == SENSOR_TEMPERATURE).count(), 7);
+
+            let battery = sensors.iter().find(|sensor| sensor.kind == SENSOR_BATTERY).unwrap();
+            assert_eq!(battery.value, 50.0);
+
+            let ac = sensors.iter().find(|sensor| sensor.kind == SENSOR_AC).unwrap();
+            assert_eq!(ac.value, 1.0);
+        }
+        _ => panic!("sensors aren't sensors"),
+    }
+}
+
+#[test]
+fn process_collector_counts_and_redacts_processes() {
+    let metrics = ProcessCollector::new(3, true).collect(&MockPlatform::new());
+
+    assert!(integer(&metrics, METRIC_PROCESS_COUNT) > 0);
+
+    match find(&metrics, METRIC_TOP_PROCESSES) {
+        MetricValue::Processes(processes) => {
+            assert!(!processes.is_empty() && processes.len() <= 6);
+            assert!(processes.iter().all(|process| !process.cmdline.contains(" --")));
+        }
+        _ => panic!("processes aren't processes"),
+    }
+}
+
+#[test]
+fn registry_skips_disabled_collectors() {
+    let mut config = ClientConfig::new(String::new(), String::new(), String::new(), String::new(), String::new());
+    config.collectors.insert("processes".to_string(), CollectorConfig { enabled: false, interval: 0 });
+    config.collectors.insert("sensors".to_string(), CollectorConfig { enabled: false, interval: 60 });
+    config.collectors.insert("push".to_string(), CollectorConfig { enabled: false, interval: 0 });
+    config.commands.push(CustomCommand { name: "queue".to_string(), command: "echo depth=1".to_string(), interval: 30 });
+
+    let registry = Registry::<MockPlatform>::from_config(&config, &empty_root());
+
+    assert_eq!(registry.names(), ["cpu", "memory", "filesystems", "diskIO", "network", "queue"]);
+}
+
+#[test]
+fn registry_leaves_out_collectors_that_are_not_due() {
+    let sys = MockPlatform::new();
+    let mut registry = Registry::default();
+
+    registry.register(Box::new(CountingCollector { runs: 0 }), CollectorConfig { enabled: true, interval: 3600 });
+
+    assert_eq!(integer(&registry.collect(&sys), "test.runs"), 1);
+    assert!(registry.collect(&sys).is_empty());
+
+    let mut every_sample = Registry::default();
+
+    every_sample.register(Box::new(CountingCollector { runs: 0 }), CollectorConfig::default());
+
+    every_sample.collect(&sys);
+    assert_eq!(integer(&every_sample.collect(&sys), "test.runs"), 2);
+}
+
+#[test]
+fn metrics_fill_their_field_or_are_kept_by_name() {
+    let mut device = Device::new("id", "name", 0, 0, 0.0, 0, 0, 0, 0);
+
+    apply(&mut device, Metric::new(METRIC_RAM_USED, MetricValue::Integer(42)));
+    apply(&mut device, Metric::new("queue.depth", MetricValue::Integer(7)));
+
+    assert_eq!(device.ram_used, Some(42));
+    assert_eq!(device.metrics.get("queue.depth"), Some(&7.0));
+    assert!(!device.metrics.contains_key(METRIC_RAM_USED));
+}
+
+#[test]
+fn samples_without_interfaces_have_no_network_totals() {
+    let mut device = Device::empty("id", "name", 0);
+
+    apply(&mut device, Metric::new(METRIC_INTERFACES, MetricValue::Interfaces(Vec::new())));
+
+    assert!(device.network_in.is_none() && device.network_out.is_none());
+
+    let interface = NetworkInterface { interface: "eth0".to_string(), rx_bytes: 100, tx_bytes: 50, ..Default::default() };
+
+    apply(&mut device, Metric::new(METRIC_INTERFACES, MetricValue::Interfaces(vec![interface])));
+
+    assert_eq!((device.network_in, device.network_out), (Some(100), Some(50)));
+}
+
+#[test]
+fn samples_only_hold_the_values_of_collectors_that_ran() {
+    let sys = MockPlatform::new();
+    let mut registry = Registry::default();
+
+    registry.register(Box::new(CountingCollector { runs: 0 }), CollectorConfig { enabled: true, interval: 3600 });
+
+    let mut first = Device::empty("id", "name", 0);
+    registry.sample(&sys, &mut first);
+
+    let mut second = Device::empty("id", "name", 1);
+    registry.sample(&sys, &mut second);
+
+    assert_eq!(first.metrics.get("test.runs"), Some(&1.0));
+    assert!(second.metrics.is_empty());
+    assert!(second.ram_used.is_none() && second.cpu_usage.is_none());
+}
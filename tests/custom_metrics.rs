@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use rlsd::{
+    config::client::CustomCommand,
+    stats_handling::{
+        collector::{apply, Collector, MetricValue},
+        custom_metrics::{is_valid_metric_name, parse_command_output, parse_statsd_line, CommandCollector, PushKind, PushedMetrics},
+        device_info::Device,
+    },
+};
+use systemstat::{Platform, System};
+
+fn as_map(metrics: Vec<(String, f64)>) -> BTreeMap<String, f64> {
+    metrics.into_iter().collect()
+}
+
+#[test]
+fn metric_names_are_limited_to_safe_characters() {
+    assert!(is_valid_metric_name("queue.depth_1-a"));
+    assert!(!is_valid_metric_name(""));
+    assert!(!is_valid_metric_name("queue depth"));
+    assert!(!is_valid_metric_name("queue;drop"));
+    assert!(!is_valid_metric_name(&"a".repeat(101)));
+}
+
+#[test]
+fn command_output_is_read_as_lines() {
+    let metrics = as_map(parse_command_output("# comment\nqueue.depth=12\n  jobs = 3.5 \nbad name=1\nnan=NaN\nempty=\n"));
+
+    assert_eq!(metrics, BTreeMap::from([("jobs".to_string(), 3.5), ("queue.depth".to_string(), 12.0)]));
+}
+
+#[test]
+fn command_output_is_read_as_nested_json() {
+    let metrics = as_map(parse_command_output(r#"{"queue": {"depth": 12, "open": true}, "label": "text", "rate": 0.5}"#));
+
+    assert_eq!(
+        metrics,
+        BTreeMap::from([
+            ("queue.depth".to_string(), 12.0),
+            ("queue.open".to_string(), 1.0),
+            ("rate".to_string(), 0.5),
+        ])
+    );
+}
+
+#[test]
+fn statsd_lines_are_parsed() {
+    let gauge = parse_statsd_line("queue.depth:12|g").unwrap();
+    assert_eq!((gauge.value, gauge.kind, gauge.relative), (12.0, PushKind::Gauge, false));
+
+    let change = parse_statsd_line("queue.depth:-2|g").unwrap();
+    assert!(change.relative);
+
+    let sampled = parse_statsd_line("jobs.done:1|c|@0.5").unwrap();
+    assert_eq!((sampled.value, sampled.kind), (2.0, PushKind::Counter));
+
+    assert_eq!(parse_statsd_line("backup.time:320|ms").unwrap().kind, PushKind::Timer);
+
+    assert!(parse_statsd_line("queue.depth:12").is_none());
+    assert!(parse_statsd_line("queue.depth:abc|g").is_none());
+    assert!(parse_statsd_line("bad name:1|g").is_none());
+    assert!(parse_statsd_line("queue.depth:1|x").is_none());
+}
+
+#[test]
+fn pushed_metrics_keep_gauges_and_reset_counters_and_timers() {
+    let mut pushed = PushedMetrics::default();
+
+    for line in ["queue.depth:10|g", "queue.depth:+5|g", "jobs:1|c", "jobs:2|c", "backup:100|ms", "backup:300|ms"] {
+        pushed.add(parse_statsd_line(line).unwrap());
+    }
+
+    assert_eq!(
+        as_map(pushed.take()),
+        BTreeMap::from([("backup".to_string(), 200.0), ("jobs".to_string(), 3.0), ("queue.depth".to_string(), 15.0)])
+    );
+
+    assert_eq!(as_map(pushed.take()), BTreeMap::from([("jobs".to_string(), 0.0), ("queue.depth".to_string(), 15.0)]));
+}
+
+#[cfg(unix)]
+#[test]
+fn metrics_pushed_to_the_socket_are_collected() {
+    use std::{os::unix::net::UnixDatagram, thread, time::Duration};
+
+    use rlsd::stats_handling::custom_metrics::PushCollector;
+
+    let path = std::env::temp_dir().join(format!("rlsd-push-{}.sock", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let mut collector = PushCollector::listen(path).unwrap();
+
+    UnixDatagram::unbound().unwrap().send_to(b"queue.depth:7|g\njobs:2|c\nnot a metric\n", path).unwrap();
+
+    // The socket is read on another thread
+    let mut metrics = Vec::new();
+
+    for _ in 0..50 {
+        metrics = Collector::<System>::collect(&mut collector, &System::new());
+
+        if !metrics.is_empty() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let _ = std::fs::remove_file(path);
+
+    let values: BTreeMap<String, f64> = metrics
+        .into_iter()
+        .map(|metric| match metric.value {
+            MetricValue::Custom(value) => (metric.name, value),
+            _ => panic!("pushed metrics are custom"),
+        })
+        .collect();
+
+    assert_eq!(values, BTreeMap::from([("jobs".to_string(), 2.0), ("queue.depth".to_string(), 7.0)]));
+}
+
+#[cfg(unix)]
+#[test]
+fn command_collector_runs_the_command_and_keeps_built_in_names() {
+    let mut collector = CommandCollector::new(CustomCommand {
+        name: "queue".to_string(),
+        command: "echo queue.depth=4; echo cpu.usage=9".to_string(),
+        interval: 0,
+    });
+
+    let mut device = Device::new("id", "name", 0, 0, 0.5, 0, 0, 0, 0);
+
+    for metric in Collector::<System>::collect(&mut collector, &System::new()) {
+        apply(&mut device, metric);
+    }
+
+    // A custom metric named like a built-in one doesn't replace it
+    assert_eq!(device.cpu_usage, Some(0.5));
+    assert_eq!(device.metrics, BTreeMap::from([("cpu.usage".to_string(), 9.0), ("queue.depth".to_string(), 4.0)]));
+}
+
+#[cfg(unix)]
+#[test]
+fn failing_commands_report_nothing() {
+    let mut collector = CommandCollector::new(CustomCommand {
+        name: "broken".to_string(),
+        command: "echo queue.depth=4; exit 1".to_string(),
+        interval: 0,
+    });
+
+    assert!(Collector::<System>::collect(&mut collector, &System::new()).is_empty());
+}
@@ -0,0 +1,110 @@
+use std::fs;
+
+use rlsd::stats_handling::{database::SqlStorage, storage::Storage};
+use sqlx::{sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
+
+/// Makes an empty in-memory database, one connection so every query sees the same database
+async fn memory_database() -> Pool<Sqlite> {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+/// Makes an in-memory database with every migration applied
+async fn migrated_database() -> Pool<Sqlite> {
+    let storage = SqlStorage::new(memory_database().await);
+
+    storage.migrate().await.unwrap();
+
+    storage.pool().clone()
+}
+
+/// Runs the migration files whose names start before `before`, in order
+async fn run_migrations_before(database: &Pool<Sqlite>, before: &str) {
+    let mut files: Vec<_> = fs::read_dir("migrations").unwrap().flatten().map(|entry| entry.path()).collect();
+    files.sort();
+
+    for file in files.iter().filter(|file| file.file_name().unwrap().to_str().unwrap() < before) {
+        sqlx::raw_sql(&fs::read_to_string(file).unwrap()).execute(database).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn legacy_rows_are_split_into_devices_and_samples() {
+    let database = memory_database().await;
+
+    run_migrations_before(&database, "0011").await;
+
+    for (device_id, device_name, time) in [("a", "old", 100), ("a", "new", 200), ("a", "new", 300), ("b", "other", 150)] {
+        sqlx::query(
+            "INSERT INTO devices (device_id, device_name, ram_used, ram_total, cpu_usage, processes, network_in, network_out, time)
+            VALUES (?1, ?2, 1, 2, 0.5, 3, 4, 5, ?3)",
+        )
+        .bind(device_id)
+        .bind(device_name)
+        .bind(time)
+        .execute(&database)
+        .await
+        .unwrap();
+    }
+
+    sqlx::raw_sql(&fs::read_to_string("migrations/0011_devices_and_samples.sql").unwrap())
+        .execute(&database)
+        .await
+        .unwrap();
+
+    let devices: Vec<(String, String, i64, i64)> = SqlStorage::new(database.clone())
+        .get_devices()
+        .await
+        .into_iter()
+        .map(|device| (device.device_id, device.device_name, device.created_at, device.last_seen))
+        .collect();
+
+    assert_eq!(
+        devices,
+        [("a".to_string(), "new".to_string(), 100, 300), ("b".to_string(), "other".to_string(), 150, 150)]
+    );
+
+    let times: Vec<i64> = sqlx::query("SELECT time FROM samples ORDER BY time")
+        .fetch_all(&database)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get("time"))
+        .collect();
+
+    assert_eq!(times, [100, 150, 200, 300]);
+}
+
+#[tokio::test]
+async fn samples_need_a_registered_device() {
+    let database = migrated_database().await;
+
+    let orphan = sqlx::query("INSERT INTO samples (device_id, time, ram_used, ram_total, cpu_usage, processes, network_in, network_out) VALUES ('x', 1, 0, 0, 0, 0, 0, 0)")
+        .execute(&database)
+        .await;
+
+    assert!(orphan.is_err());
+}
+
+#[tokio::test]
+async fn range_queries_use_the_indexes() {
+    let database = migrated_database().await;
+
+    for table in ["samples", "cpu_cores", "disk_io", "network_interfaces", "sensors", "metrics"] {
+        let plan: Vec<String> = sqlx::query(&format!("EXPLAIN QUERY PLAN SELECT * FROM {table} WHERE device_id = ?1 AND time >= ?2 AND time <= ?3"))
+            .bind("a")
+            .bind(0)
+            .bind(10)
+            .fetch_all(&database)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("detail"))
+            .collect();
+
+        assert!(plan.iter().any(|step| step.contains("INDEX") && step.contains("device_id=? AND time>? AND time<?")), "{table}: {plan:?}");
+    }
+}
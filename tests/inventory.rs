@@ -0,0 +1,51 @@
+use rlsd::stats_handling::{device_info::Inventory, stats_getter::get_inventory};
+
+fn inventory() -> Inventory {
+    Inventory {
+        hostname: "host".to_string(),
+        os: "Debian GNU/Linux 12 (bookworm)".to_string(),
+        kernel: "6.1.0".to_string(),
+        arch: "x86_64".to_string(),
+        cpu_model: "Test CPU".to_string(),
+        cpu_cores: 4,
+        cpu_threads: 8,
+        ram_total: 16 * 1024 * 1024 * 1024,
+        boot_time: 1_000_000,
+        version: "0.1.0".to_string(),
+        time: 0,
+    }
+}
+
+#[test]
+fn inventory_survives_a_json_round_trip() {
+    let sent = inventory();
+
+    assert!(Inventory::from_json(&sent.to_json()) == sent);
+}
+
+#[test]
+fn only_the_received_time_is_ignored_when_comparing() {
+    let sent = inventory();
+
+    assert!(!Inventory { time: 2_000_000, ..sent.clone() }.changed_from(&sent));
+    assert!(Inventory { kernel: "6.1.1".to_string(), ..sent.clone() }.changed_from(&sent));
+    assert!(Inventory { boot_time: 1_500_000, ..sent.clone() }.changed_from(&sent));
+}
+
+#[test]
+fn uptime_is_worked_out_from_the_boot_time() {
+    let lines = inventory().lines(1_000_000 + 2 * 3600);
+
+    assert!(lines.iter().any(|line| line.starts_with("Uptime: 2.0 hours")), "{lines:?}");
+    assert!(lines.contains(&"RAM: 16.0GiB".to_string()), "{lines:?}");
+}
+
+#[test]
+fn the_running_device_reports_its_inventory() {
+    let inventory = get_inventory();
+
+    assert!(inventory.cpu_threads > 0);
+    assert!(inventory.ram_total > 0);
+    assert_eq!(inventory.version, env!("CARGO_PKG_VERSION"));
+    assert!(!inventory.changed_from(&get_inventory()));
+}
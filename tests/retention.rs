@@ -0,0 +1,33 @@
+use rlsd::{config::server::Retention, constants::MAX_SAMPLE_AGE_SECONDS, json_handler::ToServerConfig};
+use serde_json::json;
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[test]
+fn configs_without_retention_keep_everything() {
+    let config = json!({"registeredDeviceIDs": [], "adminIDs": []}).to_server();
+
+    assert!(config.retention == Retention { raw_days: 0, rollup_days: 0 });
+    assert_eq!(config.retention.cutoffs(1_000_000_000), (None, None));
+}
+
+#[test]
+fn devices_override_the_settings_they_set() {
+    let config = json!({
+        "retention": {"rawDays": 30, "rollupDays": 365},
+        "deviceRetention": {"a": {"rawDays": 60}}
+    })
+    .to_server();
+
+    assert!(config.retention_for("a") == Retention { raw_days: 60, rollup_days: 365 });
+    assert!(config.retention_for("b") == config.retention);
+    assert!(config.to_json().to_server().retention_for("a") == config.retention_for("a"));
+}
+
+#[test]
+fn raw_samples_are_kept_as_long_as_clients_spool_them() {
+    let now = 1_000 * DAY;
+
+    assert_eq!(Retention { raw_days: 1, rollup_days: 10 }.cutoffs(now), (Some(now - MAX_SAMPLE_AGE_SECONDS), Some(now - 10 * DAY)));
+    assert_eq!(Retention { raw_days: 30, rollup_days: 0 }.cutoffs(now), (Some(now - 30 * DAY), None));
+}
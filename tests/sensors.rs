@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use rlsd::{constants::SENSOR_TEMPERATURE, stats_handling::sensors::read_temperatures};
+
+fn fixture() -> Vec<(String, f32)> {
+    read_temperatures(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sys")))
+        .into_iter()
+        .inspect(|sensor| assert_eq!(sensor.kind, SENSOR_TEMPERATURE))
+        .map(|sensor| (sensor.label, sensor.value))
+        .collect()
+}
+
+#[test]
+fn reads_hwmon_and_thermal_zones_in_order() {
+    let labels: Vec<String> = fixture().into_iter().map(|(label, _)| label).collect();
+
+    assert_eq!(
+        labels,
+        [
+            "coretemp Package id 0",
+            "coretemp Core 0",
+            "coretemp Core 8",
+            "nvme Composite",
+            "acpitz temp1",
+            "nvme Composite 2",
+            "thermal x86_pkg_temp",
+        ]
+    );
+}
+
+#[test]
+fn converts_millidegrees_to_celsius() {
+    let sensors = fixture();
+
+    assert_eq!(sensors[0], ("coretemp Package id 0".to_string(), 45.0));
+    assert_eq!(sensors[1], ("coretemp Core 0".to_string(), 43.5));
+    assert_eq!(sensors[3], ("nvme Composite".to_string(), 38.85));
+}
+
+#[test]
+fn skips_unreadable_sensors() {
+    let labels: Vec<String> = fixture().into_iter().map(|(label, _)| label).collect();
+
+    // acpitz temp2 is empty and the acpitz thermal zone isn't a number
+    assert!(!labels.iter().any(|label| label == "acpitz temp2"));
+    assert!(!labels.iter().any(|label| label == "thermal acpitz"));
+}
+
+#[test]
+fn missing_sysfs_has_no_sensors() {
+    assert!(read_temperatures(Path::new("/nonexistent")).is_empty());
+}
@@ -0,0 +1,411 @@
+//! Tests every storage backend has to pass, they run against SQLite and, with the `postgres` feature,
+//! against the PostgreSQL database in `RLSD_TEST_POSTGRES_URL`, each test in a schema of its own
+use rlsd::{
+    json_handler::ToServerConfig,
+    stats_handling::{
+        database::{SampleQuery, SqlStorage},
+        device_info::{Device, FilesystemUsage, Inventory, ProcessInfo},
+        retention::prune,
+        storage::Storage,
+    },
+};
+use serde_json::json;
+use sqlx::sqlite::SqlitePoolOptions;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Makes an in-memory SQLite storage with every migration applied, one connection so every query sees the same database
+async fn sqlite_storage() -> Box<dyn Storage> {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+
+    let storage = SqlStorage::new(pool);
+    storage.migrate().await.unwrap();
+
+    Box::new(storage)
+}
+
+/// Makes a PostgreSQL storage with every migration applied in an empty schema named after the test
+///
+/// # Returns
+/// `Box<dyn Storage>` - The storage, it panics if `RLSD_TEST_POSTGRES_URL` isn't set
+#[cfg(feature = "postgres")]
+async fn postgres_storage(schema: &str) -> Box<dyn Storage> {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    let url = std::env::var("RLSD_TEST_POSTGRES_URL").expect("RLSD_TEST_POSTGRES_URL isn't set");
+
+    let options: PgConnectOptions = url.parse().unwrap();
+
+    let setup = PgPoolOptions::new().max_connections(1).connect_with(options.clone()).await.unwrap();
+
+    sqlx::raw_sql(&format!("DROP SCHEMA IF EXISTS {schema} CASCADE; CREATE SCHEMA {schema}"))
+        .execute(&setup)
+        .await
+        .unwrap();
+
+    let pool = PgPoolOptions::new().connect_with(options.options([("search_path", schema)])).await.unwrap();
+
+    let storage = SqlStorage::new(pool);
+    storage.migrate().await.unwrap();
+
+    Box::new(storage)
+}
+
+/// Runs each test against every backend that was built
+macro_rules! storage_tests {
+    ($($test:ident),* $(,)?) => {
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    super::$test(super::sqlite_storage().await).await;
+                }
+            )*
+        }
+
+        // They need a database so they only run with `--ignored`
+        #[cfg(feature = "postgres")]
+        mod postgres {
+            $(
+                #[tokio::test]
+                #[ignore = "needs a PostgreSQL database at RLSD_TEST_POSTGRES_URL"]
+                async fn $test() {
+                    super::$test(super::postgres_storage(stringify!($test)).await).await;
+                }
+            )*
+        }
+    };
+}
+
+storage_tests!(
+    samples_register_their_device_once,
+    renaming_only_changes_the_registry,
+    removing_a_device_removes_its_samples,
+    batches_sent_again_only_store_new_samples,
+    only_samples_in_the_range_are_loaded,
+    long_ranges_are_thinned_out_evenly,
+    columns_that_are_not_picked_are_left_empty,
+    the_latest_filesystems_processes_and_inventory_are_loaded,
+    samples_are_rolled_up_into_each_tier,
+    rolling_up_again_rebuilds_the_buckets_that_changed,
+    missing_values_are_stored_empty_and_left_out_of_rollups,
+    long_ranges_are_loaded_from_the_finest_tier_that_fits,
+    dry_runs_only_count_what_would_be_removed,
+    expired_samples_details_and_rollups_are_removed,
+    large_backlogs_are_removed_in_batches,
+);
+
+fn sample(device_id: &str, device_name: &str, time: i64) -> Device {
+    Device::new(device_id, device_name, 1024, 4096, 0.5, 10, 100, 200, time)
+}
+
+/// Stores a sample with a core and a custom metric for each time, the metric is the sample's position
+async fn store_samples(storage: &dyn Storage, device_id: &str, times: impl Iterator<Item = i64>) {
+    let samples: Vec<Device> = times
+        .enumerate()
+        .map(|(index, time)| {
+            let mut device = sample(device_id, device_id, time);
+            device.cpu_cores = vec![0.25];
+            device.metrics.insert("queue.depth".to_string(), index as f64);
+            device
+        })
+        .collect();
+
+    storage.input_data(&samples).await.unwrap();
+}
+
+/// Stores a sample every 10 seconds from 0 to 990
+async fn store_hundred_samples(storage: &dyn Storage) {
+    store_samples(storage, "a", (0..100).map(|index| index * 10)).await;
+}
+
+/// Reads the min, max, average, last value and sample count of the custom metric's buckets in a tier
+async fn metric_rollups(storage: &dyn Storage, resolution: i64) -> Vec<(i64, f64, f64, f64, f64, i64)> {
+    storage
+        .get_rollups("a", resolution, 0, i64::MAX)
+        .await
+        .into_iter()
+        .filter(|rollup| rollup.source == "metrics" && rollup.series == "queue.depth")
+        .map(|rollup| (rollup.time, rollup.min_value, rollup.max_value, rollup.avg_value, rollup.last_value, rollup.sample_count))
+        .collect()
+}
+
+/// Counts every rollup row of a device
+async fn rollup_rows(storage: &dyn Storage, device_id: &str) -> u64 {
+    storage.count_expired(device_id, None, Some(i64::MAX)).await.unwrap().1
+}
+
+async fn samples_register_their_device_once(storage: Box<dyn Storage>) {
+    storage.input_data(&[sample("a", "first", 200), sample("a", "second", 100)]).await.unwrap();
+
+    let devices = storage.get_devices().await;
+
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].device_name, "first");
+    assert_eq!((devices[0].created_at, devices[0].last_seen), (100, 200));
+    assert!(storage.check_device_id_exists("a").await);
+}
+
+async fn renaming_only_changes_the_registry(storage: Box<dyn Storage>) {
+    storage.input_data(&[sample("a", "old", 100), sample("a", "old", 200)]).await.unwrap();
+
+    assert_eq!(storage.rename_device("a", "new").await.unwrap(), 1);
+    assert_eq!(storage.rename_device("missing", "new").await.unwrap(), 0);
+
+    assert_eq!(storage.get_device_name_from_uid("a").await, "new");
+
+    let samples = storage.get_device_stats_after("a", 150).await;
+
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].time, 200);
+    assert_eq!(samples[0].device_name, "new");
+}
+
+async fn removing_a_device_removes_its_samples(storage: Box<dyn Storage>) {
+    storage.input_data(&[sample("a", "a", 100), sample("a", "a", 200), sample("b", "b", 100)]).await.unwrap();
+
+    // Two samples and the registry row
+    assert_eq!(storage.remove_device("a").await.unwrap(), 3);
+
+    assert!(storage.get_device_stats_after("a", 0).await.is_empty());
+    assert_eq!(storage.get_device_stats_after("b", 0).await.len(), 1);
+    assert_eq!(storage.get_all_device_uids().await.into_iter().collect::<Vec<String>>(), ["b"]);
+}
+
+async fn batches_sent_again_only_store_new_samples(storage: Box<dyn Storage>) {
+    store_samples(storage.as_ref(), "a", [100, 200].into_iter()).await;
+
+    // The reply to the first batch was lost so the client sends it again along with a newer sample
+    store_samples(storage.as_ref(), "a", [100, 200, 300].into_iter()).await;
+
+    let samples = storage.get_device_stats_after("a", 0).await;
+
+    assert_eq!(samples.iter().map(|device| device.time).collect::<Vec<i64>>(), [100, 200, 300]);
+    assert!(samples.iter().all(|device| device.cpu_cores == [0.25] && device.metrics.len() == 1));
+
+    // The stored samples keep their own details
+    assert_eq!(samples.iter().map(|device| device.metrics["queue.depth"]).collect::<Vec<f64>>(), [0.0, 1.0, 2.0]);
+}
+
+async fn only_samples_in_the_range_are_loaded(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    let samples = storage.get_samples(&SampleQuery::new("a", 100, 190)).await;
+
+    assert_eq!(samples.iter().map(|device| device.time).collect::<Vec<i64>>(), (10..20).map(|index| index * 10).collect::<Vec<i64>>());
+    assert!(samples.iter().all(|device| device.cpu_cores == [0.25] && device.metrics.len() == 1));
+}
+
+async fn long_ranges_are_thinned_out_evenly(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    let samples = storage.get_samples(&SampleQuery::new("a", 0, 990).with_limit(30)).await;
+
+    // Every 4th sample is kept, from the oldest, and the details follow the samples that were kept
+    assert_eq!(samples.len(), 25);
+    assert_eq!(samples.first().map(|device| device.time), Some(0));
+    assert_eq!(samples.last().map(|device| device.time), Some(960));
+    assert!(samples.iter().all(|device| device.cpu_cores == [0.25]));
+}
+
+async fn columns_that_are_not_picked_are_left_empty(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    let samples = storage.get_samples(&SampleQuery::new("a", 0, 990).with_columns(&["cpu_usage", "metrics"])).await;
+
+    assert_eq!(samples.len(), 100);
+    assert!(samples.iter().all(|device| device.cpu_usage == Some(0.5) && device.ram_used.is_none() && device.device_name == "a"));
+    assert!(samples.iter().all(|device| device.cpu_cores.is_empty() && device.metrics.len() == 1));
+}
+
+async fn the_latest_filesystems_processes_and_inventory_are_loaded(storage: Box<dyn Storage>) {
+    let samples: Vec<Device> = [100, 200]
+        .into_iter()
+        .map(|time| {
+            let mut device = sample("a", "a", time);
+            device.filesystems = vec![FilesystemUsage {
+                mount_point: format!("/{time}"),
+                fs_type: "ext4".to_string(),
+                total: 100,
+                used: 40,
+                free: 60,
+                inodes_total: 10,
+                inodes_used: 4,
+            }];
+            device.top_processes = vec![ProcessInfo {
+                pid: time,
+                name: "rlsd".to_string(),
+                user_name: "root".to_string(),
+                cpu_usage: 0.25,
+                rss: 2048,
+                cmdline: "rlsd -c".to_string(),
+            }];
+            device
+        })
+        .collect();
+
+    storage.input_data(&samples).await.unwrap();
+
+    assert_eq!(storage.get_latest_filesystems("a").await.iter().map(|fs| fs.mount_point.as_str()).collect::<Vec<&str>>(), ["/200"]);
+    assert_eq!(storage.get_processes_at("a", 150).await.iter().map(|process| process.pid).collect::<Vec<i64>>(), [100]);
+
+    let inventory = Inventory { hostname: "a".to_string(), cpu_cores: 4, time: 100, ..Default::default() };
+
+    assert!(storage.input_inventory("a", &inventory).await.unwrap());
+    assert!(!storage.input_inventory("a", &Inventory { time: 200, ..inventory.clone() }).await.unwrap());
+    assert!(storage.get_inventory("a").await == Some(inventory));
+}
+
+async fn samples_are_rolled_up_into_each_tier(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    assert_eq!(storage.get_rollup_starts().await.get("a"), Some(&0));
+
+    storage.roll_up("a", 0).await.unwrap();
+
+    assert_eq!(metric_rollups(storage.as_ref(), 900).await, [(0, 0.0, 89.0, 44.5, 89.0, 90), (900, 90.0, 99.0, 94.5, 99.0, 10)]);
+
+    // Coarser tiers are made from the 15 minute buckets and weight them by their sample counts
+    assert_eq!(metric_rollups(storage.as_ref(), 3600).await, [(0, 0.0, 99.0, 49.5, 99.0, 100)]);
+    assert_eq!(metric_rollups(storage.as_ref(), 86400).await, [(0, 0.0, 99.0, 49.5, 99.0, 100)]);
+
+    assert_eq!(storage.get_rollup_starts().await.get("a"), Some(&900));
+}
+
+async fn rolling_up_again_rebuilds_the_buckets_that_changed(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    storage.roll_up("a", 0).await.unwrap();
+
+    let mut late = sample("a", "a", 1000);
+    late.metrics.insert("queue.depth".to_string(), 100.0);
+
+    storage.input_data(&[late]).await.unwrap();
+
+    storage.roll_up("a", 1000).await.unwrap();
+
+    assert_eq!(metric_rollups(storage.as_ref(), 900).await, [(0, 0.0, 89.0, 44.5, 89.0, 90), (900, 90.0, 100.0, 95.0, 100.0, 11)]);
+    assert_eq!(metric_rollups(storage.as_ref(), 3600).await, [(0, 0.0, 100.0, 50.0, 100.0, 101)]);
+}
+
+async fn missing_values_are_stored_empty_and_left_out_of_rollups(storage: Box<dyn Storage>) {
+    // Only every other sample has the CPU usage, none has the RAM used
+    let samples: Vec<Device> = (0..10)
+        .map(|index| {
+            let mut device = Device::empty("a", "a", index * 10);
+            device.cpu_usage = (index % 2 == 0).then_some(0.5);
+            device
+        })
+        .collect();
+
+    storage.input_data(&samples).await.unwrap();
+
+    let stored = storage.get_device_stats_after("a", 0).await;
+
+    assert_eq!(stored.iter().map(|device| device.cpu_usage).collect::<Vec<_>>(), samples.iter().map(|device| device.cpu_usage).collect::<Vec<_>>());
+    assert!(stored.iter().all(|device| device.ram_used.is_none()));
+
+    storage.roll_up("a", 0).await.unwrap();
+
+    let rollups = storage.get_rollups("a", 900, 0, i64::MAX).await;
+    let cpu: Vec<(f64, i64)> =
+        rollups.iter().filter(|rollup| rollup.field == "cpu_usage").map(|rollup| (rollup.avg_value, rollup.sample_count)).collect();
+
+    assert_eq!(cpu, [(0.5, 5)]);
+    assert!(!rollups.iter().any(|rollup| rollup.field == "ram_used"));
+
+    // Buckets loaded from the rollups leave the values without rows empty too
+    let loaded = storage.get_samples(&SampleQuery::new("a", 0, 90).with_limit(1)).await;
+
+    assert_eq!(loaded.len(), 1);
+    assert!(loaded[0].cpu_usage == Some(0.5) && loaded[0].ram_used.is_none());
+}
+
+async fn long_ranges_are_loaded_from_the_finest_tier_that_fits(storage: Box<dyn Storage>) {
+    store_hundred_samples(storage.as_ref()).await;
+
+    // Nothing is rolled up yet so the samples are thinned out instead
+    assert_eq!(storage.get_samples(&SampleQuery::new("a", 0, 990).with_limit(5)).await.len(), 5);
+
+    storage.roll_up("a", 0).await.unwrap();
+
+    let samples = storage.get_samples(&SampleQuery::new("a", 0, 990).with_limit(5)).await;
+
+    assert_eq!(samples.iter().map(|device| device.time).collect::<Vec<i64>>(), [0, 900]);
+    assert_eq!(samples[0].metrics.get("queue.depth"), Some(&44.5));
+    assert!(samples.iter().all(|device| device.cpu_usage == Some(0.5) && device.ram_used == Some(1024) && device.cpu_cores == [0.25]));
+    assert!(samples.iter().all(|device| device.device_name == "a"));
+
+    // A single bucket only fits the daily tier
+    let samples = storage.get_samples(&SampleQuery::new("a", 0, 990).with_limit(1).with_columns(&["cpu_usage"])).await;
+
+    assert_eq!(samples.len(), 1);
+    assert!(samples[0].metrics.is_empty() && samples[0].ram_used.is_none());
+
+    // Ranges that fit the limit still load every sample
+    assert_eq!(storage.get_samples(&SampleQuery::new("a", 0, 990).with_limit(100)).await.len(), 100);
+}
+
+async fn dry_runs_only_count_what_would_be_removed(storage: Box<dyn Storage>) {
+    let now = 100 * DAY;
+
+    store_samples(storage.as_ref(), "a", (0..100).map(|day| day * DAY)).await;
+    storage.roll_up("a", 0).await.unwrap();
+
+    let config = json!({"retention": {"rawDays": 30, "rollupDays": 60}}).to_server();
+
+    let pruned = prune(storage.as_ref(), &config, now, true).await.unwrap();
+
+    // Each daily bucket holds one sample, so every tier has a bucket with a row for each value per day
+    let rollups_per_day = rollup_rows(storage.as_ref(), "a").await / 100;
+
+    assert_eq!(pruned.len(), 1);
+    assert_eq!((pruned[0].samples, pruned[0].rollups), (70, 40 * rollups_per_day));
+    assert_eq!(storage.get_device_stats_after("a", 0).await.len(), 100);
+    assert_eq!(rollup_rows(storage.as_ref(), "a").await, 100 * rollups_per_day);
+}
+
+async fn expired_samples_details_and_rollups_are_removed(storage: Box<dyn Storage>) {
+    let now = 100 * DAY;
+
+    store_samples(storage.as_ref(), "a", (0..100).map(|day| day * DAY)).await;
+    store_samples(storage.as_ref(), "b", (0..100).map(|day| day * DAY)).await;
+    storage.roll_up("a", 0).await.unwrap();
+
+    let config = json!({
+        "retention": {"rawDays": 30, "rollupDays": 0},
+        "deviceRetention": {"b": {"rawDays": 0}}
+    })
+    .to_server();
+
+    let pruned = prune(storage.as_ref(), &config, now, false).await.unwrap();
+
+    assert_eq!(pruned.iter().map(|device| (device.samples, device.rollups)).collect::<Vec<_>>(), [(70, 0), (0, 0)]);
+
+    let samples = storage.get_device_stats_after("a", 0).await;
+
+    assert_eq!(samples.first().map(|device| device.time), Some(70 * DAY));
+    assert!(samples.iter().all(|device| device.cpu_cores.len() == 1 && device.metrics.len() == 1));
+    assert_eq!(storage.get_device_stats_after("b", 0).await.len(), 100);
+
+    // Rollups outlive the samples they were made from
+    assert_eq!(storage.get_rollups("a", 86400, 0, i64::MAX).await.first().map(|rollup| rollup.time), Some(0));
+
+    // The metrics of the removed samples went with them, so rebuilding the rollups only finds the ones that were kept
+    storage.roll_up("a", 0).await.unwrap();
+
+    assert_eq!(metric_rollups(storage.as_ref(), 900).await.len(), 30);
+}
+
+async fn large_backlogs_are_removed_in_batches(storage: Box<dyn Storage>) {
+    store_samples(storage.as_ref(), "a", 0..12_000).await;
+
+    let config = json!({"retention": {"rawDays": 30, "rollupDays": 0}}).to_server();
+
+    // Everything but the last 500 samples is past the retention
+    let pruned = prune(storage.as_ref(), &config, 11_500 + 30 * DAY, false).await.unwrap();
+
+    assert_eq!(pruned[0].samples, 11_500);
+    assert_eq!(storage.count_expired("a", Some(i64::MAX), None).await.unwrap().0, 500);
+    assert!(storage.get_device_stats_after("a", 0).await.iter().all(|device| device.metrics.len() == 1));
+}